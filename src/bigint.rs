@@ -0,0 +1,695 @@
+use crate::{
+    error::{ Error, Result },
+    function::Function,
+    number::{ Number, no_factorial },
+    rational::Rational,
+    token::Span,
+};
+
+use std::{
+    cmp::Ordering,
+    convert::TryFrom,
+    fmt::{ Debug, Display, Formatter, Result as fmt_Result },
+    ops::{ Add, Mul, Neg, Sub },
+    str::FromStr,
+};
+
+
+/// The default `Settings::max_bits`, about 300,000 decimal digits
+pub const DEFAULT_MAX_BITS: u64 = 1 << 20;
+
+/// How big exact numbers (`BigInt` and `Rational`) are allowed to get
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    /// Results bigger than this many bits are refused rather than computed, `2 ^ 1000000000` would
+    /// otherwise take forever and all of the memory. `None` works out anything, however long it takes
+    pub max_bits: Option<u64>,
+} impl Settings {
+    /// No limit at all
+    pub const UNLIMITED: Settings = Settings { max_bits: None };
+
+    fn too_big(&self, bits: u64) -> bool {
+        self.max_bits.is_some_and(|max| bits > max)
+    }
+} impl Default for Settings {
+    /// Up to `DEFAULT_MAX_BITS`
+    fn default() -> Self {
+        Settings { max_bits: Some(DEFAULT_MAX_BITS) }
+    }
+}
+
+/// Largest power of ten that fits in a limb, what `Display` divides by
+const DECIMAL_BASE: u32 = 1_000_000_000;
+const DECIMAL_DIGITS: usize = 9;
+
+/// Magnitudes are little endian base 2^32 limbs without any trailing zeros, so zero is empty
+fn trim(mut limbs: Vec<u32>) -> Vec<u32> {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+    limbs
+}
+
+fn compare(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut sum = Vec::with_capacity(long.len() + 1);
+    let mut carry = 0u64;
+    for (i, &limb) in long.iter().enumerate() {
+        let total = limb as u64 + short.get(i).copied().unwrap_or(0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    if carry != 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+/// `a - b` in place, `a` has to be at least `b`
+fn sub_assign(a: &mut Vec<u32>, b: &[u32]) {
+    let mut borrow = 0i64;
+    for (i, limb) in a.iter_mut().enumerate() {
+        let difference = *limb as i64 - b.get(i).copied().unwrap_or(0) as i64 - borrow;
+        *limb = difference.rem_euclid(1 << 32) as u32;
+        borrow = (difference < 0) as i64;
+    }
+    debug_assert_eq!(borrow, 0);
+    while a.last() == Some(&0) {
+        a.pop();
+    }
+}
+
+fn mul(a: &[u32], b: &[u32]) -> Vec<u32> {
+    if a.is_empty() || b.is_empty() {
+        return Vec::new();
+    }
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, &y) in b.iter().enumerate() {
+            let total = x as u64 * y as u64 + product[i + j] as u64 + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    trim(product)
+}
+
+/// Quotient and remainder of `a` by a single limb
+fn div_rem_limb(a: &[u32], d: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for (i, &limb) in a.iter().enumerate().rev() {
+        let current = (remainder << 32) | limb as u64;
+        quotient[i] = (current / d as u64) as u32;
+        remainder = current % d as u64;
+    }
+    (trim(quotient), remainder as u32)
+}
+
+fn bits(a: &[u32]) -> u64 {
+    match a.last() {
+        Some(&top) => (a.len() as u64 - 1) * 32 + (32 - top.leading_zeros() as u64),
+        None => 0,
+    }
+}
+
+/// Quotient and remainder of `a` by `b`, which can't be zero. Long division a bit at a time
+fn div_rem(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if compare(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    if b.len() == 1 {
+        let (quotient, remainder) = div_rem_limb(a, b[0]);
+        return (quotient, trim(vec![remainder]));
+    }
+
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = Vec::with_capacity(b.len() + 1);
+    for i in (0..bits(a)).rev() {
+        // remainder = remainder * 2 + the next bit of a
+        let mut carry = (a[(i / 32) as usize] >> (i % 32)) & 1;
+        for limb in remainder.iter_mut() {
+            let shifted = *limb >> 31;
+            *limb = (*limb << 1) | carry;
+            carry = shifted;
+        }
+        if carry != 0 {
+            remainder.push(carry);
+        }
+
+        if compare(&remainder, b) != Ordering::Less {
+            sub_assign(&mut remainder, b);
+            quotient[(i / 32) as usize] |= 1 << (i % 32);
+        }
+    }
+    (trim(quotient), remainder)
+}
+
+/// An integer as big as it needs to be. Evaluating with it (see `Tree::evaluate_as`) only allows `/`
+/// when it divides evenly, `//` and `%` round down instead
+/// ```rust
+/// use pemdrs::{ Tree, bigint::{ BigInt, Settings } };
+///
+/// let big = BigInt::from(2).pow(100, &Settings::default())?;
+/// assert_eq!(big.to_string(), "1267650600228229401496703205376");
+/// assert_eq!(&big - &BigInt::from(1), "1267650600228229401496703205375".parse()?);
+/// assert_eq!(big.div_rem(&BigInt::from(-3))?.1, BigInt::from(1));
+///
+/// assert_eq!(Tree::parse("2^100 % 7 + 25! // 10^20")?.evaluate_as::<BigInt>()?.to_string(), "155114");
+/// assert!(Tree::parse("7 / 2")?.evaluate_as::<BigInt>().is_err());
+///
+/// // Results are limited to `DEFAULT_MAX_BITS` unless the settings say otherwise
+/// let small = Settings { max_bits: Some(64) };
+/// assert!(Tree::parse("2^100")?.evaluate_with_settings::<BigInt, _>(&(), &small).is_err());
+/// assert!(Tree::parse("2^(2^30)")?.evaluate_as::<BigInt>().is_err());
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct BigInt {
+    /// Never set for zero
+    negative: bool,
+    magnitude: Vec<u32>,
+} impl BigInt {
+    fn new(negative: bool, magnitude: Vec<u32>) -> Self {
+        let magnitude = trim(magnitude);
+        BigInt { negative: negative && !magnitude.is_empty(), magnitude }
+    }
+
+    pub fn zero() -> Self {
+        BigInt::default()
+    }
+
+    pub fn one() -> Self {
+        BigInt::from(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn is_one(&self) -> bool {
+        !self.negative && self.magnitude == [1]
+    }
+
+    pub fn is_even(&self) -> bool {
+        self.magnitude.first().is_none_or(|limb| limb % 2 == 0)
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt::new(false, self.magnitude.clone())
+    }
+
+    /// Number of bits in the magnitude, zero for zero
+    pub fn bits(&self) -> u64 {
+        bits(&self.magnitude)
+    }
+
+    /// Quotient rounded towards zero and the remainder that goes with it (which has the sign of `self`),
+    /// like `/` and `%` on primitive integers
+    pub fn div_rem(&self, other: &BigInt) -> Result<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return Err(Error::DivisionByZero(Span::default()));
+        }
+        let (quotient, remainder) = div_rem(&self.magnitude, &other.magnitude);
+        Ok((BigInt::new(self.negative != other.negative, quotient), BigInt::new(self.negative, remainder)))
+    }
+
+    /// Quotient rounded towards negative infinity and the remainder that goes with it (which has the sign of `other`)
+    pub fn div_mod_floor(&self, other: &BigInt) -> Result<(BigInt, BigInt)> {
+        let (quotient, remainder) = self.div_rem(other)?;
+        if !remainder.is_zero() && remainder.negative != other.negative {
+            Ok((&quotient - &BigInt::one(), &remainder + other))
+        } else {
+            Ok((quotient, remainder))
+        }
+    }
+
+    /// `self` to the `exponent`, errors if the result would be bigger than `settings` allows
+    pub fn pow(&self, exponent: u64, settings: &Settings) -> Result<BigInt> {
+        if self.bits() > 1 && settings.too_big((self.bits() - 1).saturating_mul(exponent)) {
+            return Err(Error::custom(&format!("{} ^ {} is too big to work out exactly", self, exponent)));
+        }
+
+        let mut result = BigInt::one();
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        Ok(result)
+    }
+
+    /// `self!`, errors if `self` is negative or the result would be bigger than `settings` allows
+    pub fn factorial(&self, settings: &Settings) -> Result<BigInt> {
+        if self.negative {
+            return Err(no_factorial(self));
+        }
+
+        let mut result = BigInt::one();
+        let mut k = BigInt::from(2);
+        while k <= *self {
+            result = &result * &k;
+            if settings.too_big(result.bits()) {
+                return Err(Error::custom(&format!("{}! is too big to work out exactly", self)));
+            }
+            k = &k + &BigInt::one();
+        }
+        Ok(result)
+    }
+
+    /// Greatest common divisor, never negative
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.magnitude.clone(), other.magnitude.clone());
+        while !b.is_empty() {
+            let remainder = div_rem(&a, &b).1;
+            a = std::mem::replace(&mut b, remainder);
+        }
+        BigInt::new(false, a)
+    }
+
+    /// The `n`th root of `self` if it's an integer. Negative numbers only have odd roots
+    pub fn root(&self, n: u64) -> Option<BigInt> {
+        if n == 0 || (self.negative && n.is_multiple_of(2)) {
+            return None;
+        }
+        if n == 1 || self.bits() <= 1 {
+            return Some(self.clone());
+        }
+
+        // Newton's method from above, stops once it stops going down
+        let magnitude = self.abs();
+        let n_big = BigInt::from(n);
+        let mut x = BigInt::one().shifted(self.bits() / n + 1);
+        loop {
+            let power = x.pow(n - 1, &Settings::UNLIMITED).ok()?;
+            let next = (&(&x * &BigInt::from(n - 1)) + &magnitude.div_rem(&power).ok()?.0).div_rem(&n_big).ok()?.0;
+            if next >= x {
+                break;
+            }
+            x = next;
+        }
+
+        (x.pow(n, &Settings::UNLIMITED).ok()? == magnitude).then(|| BigInt::new(self.negative, x.magnitude))
+    }
+
+    /// `self * 2^bits`
+    pub(crate) fn shifted(&self, bits: u64) -> BigInt {
+        let mut magnitude = vec![0u32; (bits / 32) as usize];
+        let shift = bits % 32;
+        let mut carry = 0u32;
+        for &limb in &self.magnitude {
+            magnitude.push(if shift == 0 { limb } else { (limb << shift) | carry });
+            carry = if shift == 0 { 0 } else { limb >> (32 - shift) };
+        }
+        magnitude.push(carry);
+        BigInt::new(self.negative, magnitude)
+    }
+
+    /// The closest `f64`, infinite if it's too big
+    pub fn to_f64(&self) -> f64 {
+        // The top 64 bits are more than enough to round correctly (nearly always)
+        let bits = self.bits();
+        let shift = bits.saturating_sub(64);
+        let top = self.abs().div_rem(&BigInt::one().shifted(shift)).map(|(top, _)| top).unwrap_or_default();
+        let top = top.magnitude.iter().rev().fold(0u64, |acc, &limb| (acc << 32) | limb as u64);
+        let value = (top as f64) * 2f64.powi(shift.min(i32::MAX as u64) as i32);
+        if self.negative { -value } else { value }
+    }
+
+    /// The value as an `i64`, if it fits
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.magnitude.len() > 2 {
+            return None;
+        }
+        let magnitude = self.magnitude.iter().rev().fold(0u64, |acc, &limb| (acc << 32) | limb as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(magnitude)
+        } else {
+            i64::try_from(magnitude).ok()
+        }
+    }
+} impl Number for BigInt {
+    type Settings = Settings;
+
+    /// Variables have to be whole numbers, and small enough that `f64` held them exactly
+    fn from_f64(v: f64, _settings: &Settings) -> Result<Self> {
+        if !v.is_finite() || v.fract() != 0.0 {
+            return Err(Error::custom(&format!("{} isn't a whole number", v)));
+        }
+        // Past 2^53 neighbouring integers share an f64, so the literal may not be what was written
+        if v.abs() >= 2f64.powi(53) {
+            return Err(Error::custom(&format!("{} is too big to be exact", v)));
+        }
+        Ok(BigInt::from(v as i64))
+    }
+
+    /// Any size, as long as there's nothing after the decimal point but zeros
+    fn from_literal(literal: &str, _settings: &Settings) -> Result<Self> {
+        match literal.parse::<Rational>()? {
+            n if n.is_integer() => Ok(n.numerator().clone()),
+            _ => Err(Error::custom(&format!("{} isn't a whole number", literal))),
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        BigInt::to_f64(self)
+    }
+
+    fn constant(name: &str, value: f64, settings: &Settings) -> Result<Self> {
+        BigInt::from_f64(value, settings).map_err(|_| Error::custom(&format!("{} isn't a whole number", name)))
+    }
+
+    fn add(&self, other: &Self) -> Result<Self> {
+        Ok(self + other)
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self> {
+        Ok(self - other)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self> {
+        Ok(self * other)
+    }
+
+    /// Only when it comes out even, `//` rounds
+    fn div(&self, other: &Self) -> Result<Self> {
+        match self.div_rem(other)? {
+            (quotient, remainder) if remainder.is_zero() => Ok(quotient),
+            _ => Err(Error::custom(&format!("{} / {} isn't a whole number, use // to round down", self, other))),
+        }
+    }
+
+    fn int_div(&self, other: &Self) -> Result<Self> {
+        Ok(self.div_mod_floor(other)?.0)
+    }
+
+    fn rem(&self, other: &Self) -> Result<Self> {
+        Ok(self.div_mod_floor(other)?.1)
+    }
+
+    /// Negative exponents only work out for bases of one and minus one
+    fn pow(&self, other: &Self, settings: &Settings) -> Result<Self> {
+        if !other.negative {
+            return match other.to_i64() {
+                Some(exponent) => BigInt::pow(self, exponent as u64, settings),
+                // Only zero and one keep their size
+                None if self.bits() <= 1 => BigInt::pow(self, if other.is_even() { 2 } else { 1 }, settings),
+                None => Err(Error::custom(&format!("{} ^ {} is too big to work out exactly", self, other))),
+            };
+        }
+
+        match self.bits() {
+            0 => Err(Error::DivisionByZero(Span::default())),
+            1 if self.negative && !other.is_even() => Ok(self.clone()),
+            1 => Ok(BigInt::one()),
+            _ => Err(Error::custom(&format!("{} ^ {} isn't a whole number", self, other))),
+        }
+    }
+
+    fn neg(&self) -> Self {
+        -self
+    }
+
+    fn factorial(&self, settings: &Settings) -> Result<Self> {
+        BigInt::factorial(self, settings)
+    }
+
+    /// Works out the exact result like `Rational` does, which then has to be a whole number
+    fn call(function: &dyn Function, args: &[Self], settings: &Settings) -> Result<Self> {
+        let exact = args.iter().cloned().map(Rational::from).collect::<Vec<Rational>>();
+        let result = Rational::call(function, &exact, settings)?;
+        if !result.is_integer() {
+            let args = args.iter().map(ToString::to_string).collect::<Vec<String>>().join(", ");
+            return Err(Error::custom(&format!("{}({}) isn't a whole number", function.name(), args)));
+        }
+        Ok(result.numerator().clone())
+    }
+} impl From<i64> for BigInt {
+    fn from(n: i64) -> Self {
+        let magnitude = n.unsigned_abs();
+        BigInt::new(n < 0, vec![magnitude as u32, (magnitude >> 32) as u32])
+    }
+} impl From<u64> for BigInt {
+    fn from(n: u64) -> Self {
+        BigInt::new(false, vec![n as u32, (n >> 32) as u32])
+    }
+} impl From<i32> for BigInt {
+    fn from(n: i32) -> Self {
+        BigInt::from(n as i64)
+    }
+} impl FromStr for BigInt {
+    type Err = Error;
+
+    /// Decimal digits with an optional leading `-`
+    fn from_str(s: &str) -> Result<BigInt> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, s),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(Error::Lex(s.to_string(), Span::new(0, s.len())));
+        }
+
+        let mut magnitude: Vec<u32> = Vec::new();
+        for chunk in digits.as_bytes().chunks(DECIMAL_DIGITS) {
+            let chunk = std::str::from_utf8(chunk).expect("Something went wrong! (digits weren't utf-8)");
+            // magnitude = magnitude * 10^len + chunk
+            let mut carry = chunk.parse::<u64>().expect("Something went wrong! (parsing a chunk of digits)");
+            let scale = 10u64.pow(chunk.len() as u32);
+            for limb in magnitude.iter_mut() {
+                let total = *limb as u64 * scale + carry;
+                *limb = total as u32;
+                carry = total >> 32;
+            }
+            if carry != 0 {
+                magnitude.push(carry as u32);
+            }
+        }
+        Ok(BigInt::new(negative, magnitude))
+    }
+} impl Display for BigInt {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        // Groups of nine digits, least significant first
+        let mut groups = Vec::new();
+        let mut magnitude = self.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, group) = div_rem_limb(&magnitude, DECIMAL_BASE);
+            groups.push(group);
+            magnitude = quotient;
+        }
+
+        let mut digits = String::new();
+        match groups.split_last() {
+            Some((first, rest)) => {
+                digits.push_str(&first.to_string());
+                for group in rest.iter().rev() {
+                    digits.push_str(&format!("{:0w$}", group, w = DECIMAL_DIGITS));
+                }
+            },
+            None => digits.push('0'),
+        }
+        f.pad_integral(!self.negative, "", &digits)
+    }
+} impl Debug for BigInt {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}", self)
+    }
+} impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, false) => compare(&self.magnitude, &other.magnitude),
+            (true, true) => compare(&other.magnitude, &self.magnitude),
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+        }
+    }
+} impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+} impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add(&self.magnitude, &other.magnitude));
+        }
+        // Opposite signs, take the smaller magnitude away from the bigger one
+        let (big, small) = match compare(&self.magnitude, &other.magnitude) {
+            Ordering::Less => (other, self),
+            _ => (self, other),
+        };
+        let mut magnitude = big.magnitude.clone();
+        sub_assign(&mut magnitude, &small.magnitude);
+        BigInt::new(big.negative, magnitude)
+    }
+} impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+} impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(self.negative != other.negative, mul(&self.magnitude, &other.magnitude))
+    }
+} impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.magnitude.clone())
+    }
+} impl Neg for BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.negative, self.magnitude)
+    }
+}
+
+
+#[test]
+fn test_bigint_arithmetic() {
+    use crate::generate::Rng;
+
+    let mut rng = Rng::new(23);
+    let mut random = || {
+        // Mix small and large magnitudes so single and multi limb paths both get used
+        let bits = rng.below(100) as u32;
+        let magnitude = ((rng.next_u64() as i128) << 64 | rng.next_u64() as i128) & ((1i128 << bits) - 1).max(0);
+        if rng.below(2) == 0 { magnitude } else { -magnitude }
+    };
+    let big = |n: i128| n.to_string().parse::<BigInt>().unwrap();
+
+    for _ in 0..2000 {
+        let (a, b) = (random() >> 2, random() >> 2);
+        assert_eq!(&big(a) + &big(b), big(a + b), "{} + {}", a, b);
+        assert_eq!(&big(a) - &big(b), big(a - b), "{} - {}", a, b);
+        assert_eq!(big(a).cmp(&big(b)), a.cmp(&b), "{} <=> {}", a, b);
+        assert_eq!(big(a).to_string(), a.to_string());
+
+        let (small_a, small_b) = (a >> 64, b >> 64);
+        assert_eq!(&big(small_a) * &big(small_b), big(small_a * small_b), "{} * {}", small_a, small_b);
+
+        if b != 0 {
+            assert_eq!(big(a).div_rem(&big(b)), Ok((big(a / b), big(a % b))), "{} / {}", a, b);
+            assert_eq!(big(a).div_mod_floor(&big(b)), Ok((big(a.div_euclid(b) - (b < 0 && a.rem_euclid(b) != 0) as i128), big(a - b * (a.div_euclid(b) - (b < 0 && a.rem_euclid(b) != 0) as i128)))), "{} // {}", a, b);
+            assert_eq!(big(a).gcd(&big(b)), big(gcd(a, b)), "gcd({}, {})", a, b);
+        }
+        assert_eq!(big(a).to_i64(), i64::try_from(a).ok());
+        assert_eq!(big(a).to_f64(), a as f64, "{}", a);
+    }
+
+    fn gcd(a: i128, b: i128) -> i128 {
+        if b == 0 { a.abs() } else { gcd(b, a % b) }
+    }
+}
+
+#[test]
+fn test_bigint_big() {
+    let two = BigInt::from(2);
+    let default = Settings::default();
+    let big = two.pow(200, &default).unwrap();
+    assert_eq!(big.to_string(), "1606938044258990275541962092341162602522202993782792835301376");
+    assert_eq!(big.bits(), 201);
+    assert_eq!(big.to_f64(), 2f64.powi(200));
+    assert_eq!(big.root(200), Some(two.clone()));
+    assert_eq!(big.root(3), None);
+    assert_eq!((&big - &BigInt::one()).root(2), None);
+    assert_eq!(BigInt::from(-27).root(3), Some(BigInt::from(-3)));
+    assert_eq!(BigInt::from(-4).root(2), None);
+    assert_eq!(big.div_rem(&two.pow(150, &default).unwrap()), Ok((two.pow(50, &default).unwrap(), BigInt::zero())));
+    assert_eq!(big.div_rem(&BigInt::zero()), Err(Error::DivisionByZero(Span::default())));
+    let small = Settings { max_bits: Some(1000) };
+    assert_eq!(two.pow(1001, &small).map(|_| ()), Err(Error::custom("2 ^ 1001 is too big to work out exactly")));
+    assert_eq!(two.pow(1000, &small).map(|n| n.bits()), Ok(1001));
+    assert_eq!(
+        two.pow(DEFAULT_MAX_BITS + 1, &default).map(|_| ()),
+        Err(Error::custom(&format!("2 ^ {} is too big to work out exactly", DEFAULT_MAX_BITS + 1))),
+    );
+    assert_eq!(BigInt::from(200).factorial(&small).map(|_| ()), Err(Error::custom("200! is too big to work out exactly")));
+    assert_eq!(BigInt::from(200).factorial(&Settings::UNLIMITED).map(|n| n.bits()), Ok(1246));
+    assert_eq!(BigInt::one().pow(u64::MAX, &small), Ok(BigInt::one()));
+    assert_eq!(big.to_i64(), None);
+    assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+    assert_eq!(format!("{:>6}|{:<5}|{:+}", BigInt::from(-42), BigInt::zero(), BigInt::from(7)), "   -42|0    |+7");
+
+    assert!("".parse::<BigInt>().is_err());
+    assert!("-".parse::<BigInt>().is_err());
+    assert!("12a".parse::<BigInt>().is_err());
+    assert_eq!("-0".parse::<BigInt>(), Ok(BigInt::zero()));
+    assert!(!BigInt::zero().is_negative());
+}
+
+#[test]
+fn test_bigint_evaluate() {
+    use crate::tree::Tree;
+
+    let evaluate = |input: &str| Tree::parse(input).unwrap().evaluate_as::<BigInt>().map(|n| n.to_string());
+    let error = |message: &str, start: usize, end: usize| Err(Error::Custom(message.to_string(), Span::new(start, end)));
+
+    assert_eq!(evaluate("2^200"), Ok("1606938044258990275541962092341162602522202993782792835301376".to_string()));
+    assert_eq!(evaluate("30!"), Ok("265252859812191058636308480000000".to_string()));
+    assert_eq!(evaluate("30! / 29!"), Ok("30".to_string()));
+    assert_eq!(evaluate("6 / -3"), Ok("-2".to_string()));
+    assert_eq!(evaluate("-7 // 2"), Ok("-4".to_string()));
+    assert_eq!(evaluate("-7 % 2"), Ok("1".to_string()));
+    assert_eq!(evaluate("7 % -2"), Ok("-1".to_string()));
+    assert_eq!(evaluate("(-1)^-3 + 1^-5"), Ok("0".to_string()));
+    assert_eq!(evaluate("sqrt(2^100) + abs(-3) + max(2, 5)"), Ok("1125899906842632".to_string()));
+    assert_eq!(evaluate("sqrt(10^400) // 10^199 + max(10^400, 1) // 10^400"), Ok("11".to_string()));
+
+    assert_eq!(evaluate("1 + 7/2"), error("7 / 2 isn't a whole number, use // to round down", 4, 7));
+    assert_eq!(evaluate("2^-1"), error("2 ^ -1 isn't a whole number", 0, 4));
+    assert_eq!(evaluate("0.5 + 1"), error("0.5 isn't a whole number", 0, 3));
+    assert_eq!(evaluate("18446744073709551617 - 2^64"), Ok("1".to_string()));
+    assert_eq!(evaluate("3.00 * 2."), Ok("6".to_string()));
+
+    let mut env = crate::context::Environment::new();
+    env.set("x", 1e20);
+    assert_eq!(
+        Tree::parse("x + 1").unwrap().evaluate_as_with::<BigInt, _>(&env).map(|n| n.to_string()),
+        error("100000000000000000000 is too big to be exact", 0, 1),
+    );
+    let env = crate::context::Environment::new();
+    let with_env = |s: &str| Tree::parse(s).unwrap().evaluate_as_with::<BigInt, _>(&env).map(|n| n.to_string());
+    assert_eq!(with_env("sqrt(2)"), Err(Error::Irrational("sqrt(2)".to_string(), Span::new(0, 7))));
+    assert_eq!(with_env("sin(1)"), Err(Error::Irrational("sin(1)".to_string(), Span::new(0, 6))));
+    assert_eq!(with_env("sin(1/2)"), error("1 / 2 isn't a whole number, use // to round down", 4, 7));
+    assert_eq!(with_env("sqrt((10^20 + 1)^2)"), Ok("100000000000000000001".to_string()));
+    assert_eq!(evaluate("(-2)!"), error("-2! isn't defined, only whole numbers that aren't negative have factorials", 1, 5));
+    assert_eq!(evaluate("2 * pi"), error("pi isn't a whole number", 4, 6));
+    assert_eq!(evaluate("sqrt(8)"), Err(Error::Irrational("sqrt(8)".to_string(), Span::new(0, 7))));
+    assert_eq!(evaluate("5 // (3 - 3)"), Err(Error::DivisionByZero(Span::new(6, 11))));
+    assert_eq!(evaluate("0^-1"), Err(Error::DivisionByZero(Span::new(2, 4))));
+    assert_eq!(evaluate("2^(2^21)"), error("2 ^ 2097152 is too big to work out exactly", 0, 7));
+
+    // The limit on size is a setting
+    let digits = |input: &str, settings: &Settings| {
+        Tree::parse(input).unwrap().evaluate_with_settings::<BigInt, _>(&(), settings).map(|n| n.to_string().len())
+    };
+    assert_eq!(digits("10000!", &Settings::default()), Ok(35660));
+    assert_eq!(digits("2^70000", &Settings::default()), Ok(21073));
+    assert_eq!(digits("2^300", &Settings::UNLIMITED), Ok(91));
+    let small = Settings { max_bits: Some(200) };
+    assert_eq!(digits("2^300", &small), Err(Error::Custom("2 ^ 300 is too big to work out exactly".to_string(), Span::new(0, 5))));
+    assert_eq!(digits("100!", &small), Err(Error::Custom("100! is too big to work out exactly".to_string(), Span::new(0, 4))));
+}
@@ -0,0 +1,179 @@
+use crate::{
+    context::Context,
+    error::{ Error, Result },
+    tree::Tree,
+};
+
+
+/// How far apart two numbers can be and still count as the same
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Tolerance {
+    /// Allowed difference no matter how big the numbers are
+    pub absolute: f64,
+    /// Allowed difference as a fraction of the bigger number
+    pub relative: f64,
+} impl Tolerance {
+    pub fn new(absolute: f64, relative: f64) -> Self {
+        Tolerance { absolute, relative }
+    }
+
+    /// Whether `a` and `b` are within either tolerance of each other. Infinities only match themselves
+    /// and NaN doesn't match anything
+    pub fn matches(&self, a: f64, b: f64) -> bool {
+        if a == b {
+            return true;
+        }
+        if !a.is_finite() || !b.is_finite() {
+            return false;
+        }
+        let difference = (a - b).abs();
+        difference <= self.absolute || difference <= self.relative * a.abs().max(b.abs())
+    }
+}
+
+/// A submission that parsed, and what it came out to
+#[derive(Clone, PartialEq, Debug)]
+pub struct Submission {
+    pub tree: Tree,
+    /// The error if it couldn't be evaluated, e.g. it divides by zero
+    pub value: Result<f64>,
+}
+
+/// How a submission compares to the reference answer
+#[derive(Clone, PartialEq, Debug)]
+pub enum Verdict {
+    /// Within `Checker::correct` of the answer
+    Correct(Submission),
+    /// Not quite, but within `Checker::close`
+    Close(Submission),
+    Wrong(Submission),
+    /// Not an expression at all
+    Unparseable(Error),
+} impl Verdict {
+    pub fn is_correct(&self) -> bool {
+        matches!(self, Verdict::Correct(_))
+    }
+
+    /// The parsed submission, `None` if it couldn't be parsed
+    pub fn submission(&self) -> Option<&Submission> {
+        match self {
+            Verdict::Correct(s) | Verdict::Close(s) | Verdict::Wrong(s) => Some(s),
+            Verdict::Unparseable(_) => None,
+        }
+    }
+}
+
+/// Grades submitted answers against a reference `Tree`. Submissions can be a number or any expression
+/// that evaluates to the same thing, so `0.5`, `1/2` and `2^-1` are all the same answer
+/// ```rust
+/// use pemdrs::{ Tree, check::{ Checker, Verdict } };
+///
+/// let reference = Tree::parse("3 / 6")?;
+/// let checker = Checker::new();
+///
+/// assert!(checker.check(&reference, "0.5")?.is_correct());
+/// assert!(checker.check(&reference, "1/2")?.is_correct());
+/// assert!(matches!(checker.check(&reference, "0.499")?, Verdict::Close(_)));
+/// assert!(matches!(checker.check(&reference, "2")?, Verdict::Wrong(_)));
+/// assert!(matches!(checker.check(&reference, "one half")?, Verdict::Unparseable(_)));
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Checker {
+    /// How close a submission has to be to be correct, tight enough to only forgive floating point error by default
+    pub correct: Tolerance,
+    /// How close a submission has to be to be close, within 1% by default
+    pub close: Tolerance,
+} impl Checker {
+    pub fn new() -> Self {
+        Checker::default()
+    }
+
+    /// Grade `submission` against `reference`, which can't use any variables
+    pub fn check(&self, reference: &Tree, submission: &str) -> Result<Verdict> {
+        self.check_with(reference, submission, &())
+    }
+
+    /// Grade `submission` against `reference`, evaluating both with `ctx`.
+    /// Errors if `reference` itself can't be evaluated
+    pub fn check_with<C: Context + ?Sized>(&self, reference: &Tree, submission: &str, ctx: &C) -> Result<Verdict> {
+        let expected = reference.evaluate_with(ctx)?;
+
+        let tree = match Tree::parse(submission) {
+            Ok(tree) => tree,
+            Err(e) => return Ok(Verdict::Unparseable(e)),
+        };
+        let value = tree.evaluate_with(ctx);
+        let submission = Submission { tree, value };
+
+        Ok(match submission.value {
+            Ok(value) if self.correct.matches(value, expected) => Verdict::Correct(submission),
+            Ok(value) if self.close.matches(value, expected) => Verdict::Close(submission),
+            _ => Verdict::Wrong(submission),
+        })
+    }
+} impl Default for Checker {
+    fn default() -> Self {
+        Checker {
+            correct: Tolerance::new(1e-9, 1e-9),
+            close: Tolerance::new(0.0, 0.01),
+        }
+    }
+}
+
+
+#[test]
+fn test_checker() {
+    use crate::token::Span;
+    use std::collections::HashMap;
+
+    let checker = Checker::new();
+    let check = |reference: &str, submission: &str| checker.check(&Tree::parse(reference).unwrap(), submission).unwrap();
+
+    // Equivalent forms
+    for submission in ["15", "15.0", "30/2", "(54/9)^2-4*7+7", "3*5", " 15 "].iter() {
+        assert!(check("(54/9)^2-4*7+7", submission).is_correct(), "{}", submission);
+    }
+    assert!(check("1/3", "2/6").is_correct());
+    assert!(check("0.1 + 0.2", "0.3").is_correct());
+
+    // Rounded answers are close, but not correct
+    assert!(matches!(check("1/3", "0.333"), Verdict::Close(_)));
+    assert!(matches!(check("2/3", "0.67"), Verdict::Close(_)));
+    assert!(matches!(check("1/3", "0.3"), Verdict::Wrong(_)));
+    assert!(matches!(check("-4", "4"), Verdict::Wrong(_)));
+
+    // The submission comes back parsed
+    let verdict = check("10", "2 * 4");
+    assert_eq!(verdict.submission().unwrap().tree, Tree::parse("2*4").unwrap());
+    assert_eq!(verdict.submission().unwrap().value, Ok(8.0));
+
+    // Submissions that parse but don't evaluate are wrong
+    let verdict = check("1", "1/0");
+    assert!(matches!(verdict, Verdict::Wrong(_)));
+    assert_eq!(verdict.submission().unwrap().value, Err(Error::DivisionByZero(Span::new(2, 3))));
+
+    assert!(matches!(check("1", "1 +"), Verdict::Unparseable(Error::MissingOperand(..))));
+    assert!(matches!(check("1", ""), Verdict::Unparseable(Error::EmptyExpression)));
+    assert!(check("1", "").submission().is_none());
+
+    // Infinities only match themselves, NaN never does
+    assert!(check("inf", "2 * inf + 1").is_correct());
+    assert!(matches!(check("inf", "-inf"), Verdict::Wrong(_)));
+    assert!(matches!(check("nan", "nan"), Verdict::Wrong(_)));
+
+    // Tolerances are configurable
+    let lenient = Checker { correct: Tolerance::new(0.01, 0.0), close: Tolerance::new(1.0, 0.0) };
+    let reference = Tree::parse("22/7").unwrap();
+    assert!(lenient.check(&reference, "3.14").unwrap().is_correct());
+    assert!(matches!(lenient.check(&reference, "3").unwrap(), Verdict::Close(_)));
+    assert!(matches!(lenient.check(&reference, "1").unwrap(), Verdict::Wrong(_)));
+
+    // Both sides are evaluated with the context, the reference has to evaluate
+    let mut env = HashMap::new();
+    env.insert("x", 3.0);
+    let reference = Tree::parse("x^2 - 1").unwrap();
+    assert!(checker.check_with(&reference, "(x-1)*(x+1)", &env).unwrap().is_correct());
+    assert!(matches!(checker.check_with(&reference, "y", &env).unwrap(), Verdict::Wrong(_)));
+    assert!(checker.check(&reference, "8").is_err());
+}
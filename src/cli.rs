@@ -0,0 +1,574 @@
+use pemdrs::{
+    Error, Tree,
+    bigint::BigInt,
+    decimal::{ Decimal, Settings },
+    diagnostic,
+    generate::{ Constraints, Difficulty, Generator },
+    rational::Rational,
+    token::Operator,
+};
+
+use std::{
+    fs::File,
+    io::{ self, BufRead, BufReader, Write },
+    path::PathBuf,
+    str::FromStr,
+    time::{ SystemTime, UNIX_EPOCH },
+};
+
+
+pub const USAGE: &str = "\
+Usage: pemdrs [OPTIONS] [EXPRESSION...]
+       pemdrs worksheet [WORKSHEET OPTIONS]
+
+Evaluates EXPRESSION if there is one, otherwise every line of --file or stdin.
+With no input at all on a terminal, starts an interactive session.
+
+Options:
+    -f, --file <PATH>         evaluate each line of PATH
+    -                         evaluate each line of stdin
+    -p, --precision <N>       print results with N digits after the decimal point
+    -m, --mode <MODE>         float (the default), exact for fractions, integer for
+                              whole numbers of any size, where / has to divide evenly,
+                              or decimal for fixed point
+        --scale <N>           digits kept after the decimal point in decimal mode, 2 by default
+        --rounding <MODE>     half-even (the default), half-up or truncate in decimal mode
+        --json                print one {\"input\", \"result\", \"error\"} object per line
+    -c, --continue-on-error   keep going after a line fails
+    -i, --interactive         start an interactive session
+    -h, --help                show this message
+        --                    treat everything after this as the expression
+
+Blank lines and lines starting with # are skipped.
+
+Worksheet options:
+    -n, --count <N>            how many problems to make, 10 by default
+    -d, --difficulty <LEVEL>   easy, medium (the default) or hard
+    -s, --seed <N>             make the same worksheet every time
+        --max-depth <N>        limit how deeply problems nest
+        --operators <OPS>      only use these operators, e.g. +-*/^ (u allows negative numbers)
+        --no-answers           leave off the answer key
+
+Exit codes:
+    0    everything evaluated
+    1    an expression couldn't be parsed
+    2    an expression parsed but couldn't be evaluated
+    64   bad command line arguments, or a worksheet that can't be made
+    74   couldn't read the input
+";
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_PARSE: i32 = 1;
+pub const EXIT_EVAL: i32 = 2;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_IO: i32 = 74;
+
+/// Where expressions come from
+#[derive(Clone, PartialEq, Debug)]
+pub enum Input {
+    Expression(String),
+    Stdin,
+    File(PathBuf),
+    Interactive,
+}
+
+/// What expressions are evaluated with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Float,
+    /// `Rational`
+    Exact,
+    /// `BigInt`
+    Integer,
+    /// `Decimal`
+    Decimal,
+} impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "float" => Ok(Mode::Float),
+            "exact" => Ok(Mode::Exact),
+            "integer" => Ok(Mode::Integer),
+            "decimal" => Ok(Mode::Decimal),
+            _ => Err(format!("Unknown mode {:?}, expected float, exact, integer or decimal", s)),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Options {
+    pub input: Input,
+    pub mode: Mode,
+    /// Only applies to `Mode::Float`, exact results are always printed in full
+    pub precision: Option<usize>,
+    /// Only applies to `Mode::Decimal`
+    pub decimal: Settings,
+    pub json: bool,
+    pub continue_on_error: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct WorksheetOptions {
+    pub count: usize,
+    pub difficulty: Difficulty,
+    pub constraints: Constraints,
+    /// Picked from the clock if there isn't one
+    pub seed: Option<u64>,
+    pub answers: bool,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Action {
+    Run(Options),
+    Worksheet(WorksheetOptions),
+    Help,
+}
+
+/// Parse the command line (without the program name). `interactive` is whether stdin is a terminal,
+/// which decides what happens when no input is given
+pub fn parse_args<I>(args: I, interactive: bool) -> Result<Action, String>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("worksheet") {
+        args.next();
+        return parse_worksheet_args(args);
+    }
+
+    let mut input: Option<Input> = None;
+    let mut expression: Vec<String> = Vec::new();
+    let mut mode = Mode::Float;
+    let mut precision = None;
+    let mut decimal = Settings::default();
+    let mut json = false;
+    let mut continue_on_error = false;
+
+    let set_input = |input: &mut Option<Input>, new: Input| -> Result<(), String> {
+        if input.is_some() {
+            return Err("Only one of --file, - and --interactive can be given".to_string());
+        }
+        *input = Some(new);
+        Ok(())
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "--json" => json = true,
+            "-c" | "--continue-on-error" => continue_on_error = true,
+            "-i" | "--interactive" => set_input(&mut input, Input::Interactive)?,
+            "-" => set_input(&mut input, Input::Stdin)?,
+            "-f" | "--file" => {
+                let path = args.next().ok_or_else(|| format!("{} needs a path", arg))?;
+                set_input(&mut input, Input::File(PathBuf::from(path)))?;
+            },
+            "-p" | "--precision" => {
+                let digits = args.next().ok_or_else(|| format!("{} needs a number", arg))?;
+                precision = Some(digits.parse::<usize>().map_err(|_| format!("Invalid precision {:?}", digits))?);
+            },
+            "-m" | "--mode" => {
+                mode = args.next().ok_or_else(|| format!("{} needs a mode", arg))?.parse()?;
+            },
+            "--scale" => {
+                let digits = args.next().ok_or_else(|| format!("{} needs a number", arg))?;
+                decimal.scale = digits.parse().map_err(|_| format!("Invalid scale {:?}", digits))?;
+            },
+            "--rounding" => {
+                let rounding = args.next().ok_or_else(|| format!("{} needs a rounding mode", arg))?;
+                decimal.rounding = rounding.parse().map_err(|e: Error| e.to_string())?;
+            },
+            "--" => {
+                expression.extend(args.by_ref());
+            },
+            // Dashes followed by a letter is an option we don't know, but `-3+4` or `-(1)` is an expression
+            option if option.starts_with('-') && option.trim_start_matches('-').starts_with(char::is_alphabetic) => {
+                return Err(format!("Unknown option {}", option));
+            },
+            _ => expression.push(arg),
+        }
+    }
+
+    let input = match (input, expression.is_empty()) {
+        (Some(_), false) => return Err("Can't take an expression along with --file, - or --interactive".to_string()),
+        (Some(input), true) => input,
+        (None, false) => Input::Expression(expression.join(" ")),
+        (None, true) if interactive => Input::Interactive,
+        (None, true) => Input::Stdin,
+    };
+
+    Ok(Action::Run(Options { input, mode, precision, decimal, json, continue_on_error }))
+}
+
+/// Everything after `worksheet`
+fn parse_worksheet_args<I>(mut args: I) -> Result<Action, String>
+where
+    I: Iterator<Item = String>,
+{
+    fn number<T: std::str::FromStr>(arg: &str, value: Option<String>) -> Result<T, String> {
+        let value = value.ok_or_else(|| format!("{} needs a number", arg))?;
+        value.parse().map_err(|_| format!("Invalid number {:?} for {}", value, arg))
+    }
+
+    let mut count = 10;
+    let mut difficulty = Difficulty::Medium;
+    let mut seed = None;
+    let mut max_depth = None;
+    let mut operators = None;
+    let mut answers = true;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Action::Help),
+            "-n" | "--count" => count = number(&arg, args.next())?,
+            "-s" | "--seed" => seed = Some(number(&arg, args.next())?),
+            "--max-depth" => max_depth = Some(number(&arg, args.next())?),
+            "--no-answers" => answers = false,
+            "-d" | "--difficulty" => {
+                let level = args.next().ok_or_else(|| format!("{} needs a level", arg))?;
+                difficulty = level.parse().map_err(|e: Error| e.to_string())?;
+            },
+            "--operators" => {
+                let list = args.next().ok_or_else(|| format!("{} needs a list of operators", arg))?;
+                operators = Some(
+                    list.chars()
+                        .filter(|c| !c.is_whitespace())
+                        .map(|c| Operator::from_char(c).ok_or_else(|| format!("Unknown operator {:?}", c)))
+                        .collect::<Result<Vec<Operator>, String>>()?
+                );
+            },
+            _ => return Err(format!("Unknown worksheet option {}", arg)),
+        }
+    }
+
+    let mut constraints = Constraints::new(difficulty);
+    if let Some(max_depth) = max_depth {
+        constraints.max_depth = max_depth;
+    }
+    if let Some(operators) = operators {
+        constraints.operators = operators;
+    }
+
+    Ok(Action::Worksheet(WorksheetOptions { count, difficulty, constraints, seed, answers }))
+}
+
+/// Numbered problems followed by an answer key
+pub fn worksheet(options: &WorksheetOptions) -> Result<String, Error> {
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos() as u64).unwrap_or_default()
+    });
+    let problems = Generator::new(options.constraints.clone(), seed).worksheet(options.count)?;
+    let width = options.count.to_string().len();
+
+    // The seed's there so the same sheet can be made again
+    let mut sheet = format!("Worksheet ({}, seed {})\n\n", options.difficulty, seed);
+    for (i, problem) in problems.iter().enumerate() {
+        sheet.push_str(&format!("{:>w$}. {}\n", i + 1, problem.tree, w = width));
+    }
+    if options.answers {
+        sheet.push_str("\nAnswers\n\n");
+        for (i, problem) in problems.iter().enumerate() {
+            sheet.push_str(&format!("{:>w$}. {}\n", i + 1, problem.answer, w = width));
+        }
+    }
+    Ok(sheet)
+}
+
+/// What a line evaluated to
+enum Value {
+    Float(f64),
+    /// Already formatted, since there's nothing to round
+    Exact(String),
+}
+
+fn evaluate(input: &str, options: &Options) -> Result<Value, Error> {
+    let tree = Tree::parse(input)?;
+    Ok(match options.mode {
+        Mode::Float => Value::Float(tree.evaluate()?),
+        Mode::Exact => Value::Exact(tree.evaluate_as::<Rational>()?.to_string()),
+        Mode::Integer => Value::Exact(tree.evaluate_as::<BigInt>()?.to_string()),
+        Mode::Decimal => Value::Exact(tree.evaluate_with_settings::<Decimal, _>(&(), &options.decimal)?.to_string()),
+    })
+}
+
+fn format_number(value: &Value, precision: Option<usize>) -> String {
+    match (value, precision) {
+        (&Value::Float(value), Some(digits)) if value.is_finite() => format!("{:.*}", digits, value),
+        (Value::Float(value), _) => value.to_string(),
+        (Value::Exact(value), _) => value.clone(),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// `{"input": ..., "result": ..., "error": ...}`, non finite results are strings since JSON has no such numbers.
+/// So are exact ones, which could be fractions or too big for a double to hold
+fn json_record(input: &str, result: &Result<Value, Error>, precision: Option<usize>) -> String {
+    let (result, error) = match result {
+        Ok(value @ Value::Float(v)) if v.is_finite() => (format_number(value, precision), "null".to_string()),
+        Ok(value) => (json_string(&format_number(value, precision)), "null".to_string()),
+        Err(e) => ("null".to_string(), json_string(&e.to_string())),
+    };
+    format!("{{\"input\": {}, \"result\": {}, \"error\": {}}}", json_string(input), result, error)
+}
+
+/// Evaluate every line of `lines`, writing results to `out` and errors to `err`. Returns the exit code
+pub fn evaluate_lines<R, O, E>(lines: R, options: &Options, out: &mut O, err: &mut E) -> i32
+where
+    R: BufRead,
+    O: Write,
+    E: Write,
+{
+    let mut code = EXIT_OK;
+
+    for line in lines.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                let _ = writeln!(err, "error: {}", e);
+                return EXIT_IO;
+            },
+        };
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+
+        let result = evaluate(input, options);
+
+        // Nowhere left to report a failed write to, so they're ignored
+        if options.json {
+            let _ = writeln!(out, "{}", json_record(input, &result, options.precision));
+        } else {
+            match &result {
+                Ok(value) => { let _ = writeln!(out, "{}", format_number(value, options.precision)); },
+                Err(e) => { let _ = write!(err, "{}", diagnostic::render(input, e)); },
+            }
+        }
+
+        if let Err(e) = result {
+            if code == EXIT_OK {
+                code = if e.is_parse_error() { EXIT_PARSE } else { EXIT_EVAL };
+            }
+            if !options.continue_on_error {
+                break;
+            }
+        }
+    }
+
+    code
+}
+
+/// Run everything but the interactive session, returns the exit code
+pub fn run(options: &Options) -> i32 {
+    let stdout = io::stdout();
+    let stderr = io::stderr();
+    let (mut out, mut err) = (stdout.lock(), stderr.lock());
+
+    match &options.input {
+        Input::Expression(expression) => evaluate_lines(expression.as_bytes(), options, &mut out, &mut err),
+        Input::Stdin => evaluate_lines(io::stdin().lock(), options, &mut out, &mut err),
+        Input::File(path) => match File::open(path) {
+            Ok(file) => evaluate_lines(BufReader::new(file), options, &mut out, &mut err),
+            Err(e) => {
+                let _ = writeln!(err, "error: Couldn't open {}: {}", path.display(), e);
+                EXIT_IO
+            },
+        },
+        Input::Interactive => unreachable!("interactive sessions are handled by the repl"),
+    }
+}
+
+
+#[test]
+fn test_parse_args() {
+    use pemdrs::decimal::Rounding;
+
+    let args = |list: &[&str], interactive: bool| parse_args(list.iter().map(|s| s.to_string()), interactive);
+    let options = |input: Input| Options { input, mode: Mode::Float, precision: None, decimal: Settings::default(), json: false, continue_on_error: false };
+
+    assert_eq!(args(&["3+4*2"], true), Ok(Action::Run(options(Input::Expression("3+4*2".to_string())))));
+    assert_eq!(args(&["3", "+", "4"], true), Ok(Action::Run(options(Input::Expression("3 + 4".to_string())))));
+    assert_eq!(args(&["-3+4"], true), Ok(Action::Run(options(Input::Expression("-3+4".to_string())))));
+    assert_eq!(args(&["--", "-x"], true), Ok(Action::Run(options(Input::Expression("-x".to_string())))));
+    assert_eq!(args(&[], true), Ok(Action::Run(options(Input::Interactive))));
+    assert_eq!(args(&[], false), Ok(Action::Run(options(Input::Stdin))));
+    assert_eq!(args(&["-"], true), Ok(Action::Run(options(Input::Stdin))));
+    assert_eq!(args(&["-f", "problems.txt"], true), Ok(Action::Run(options(Input::File(PathBuf::from("problems.txt"))))));
+    assert_eq!(args(&["--help", "1"], true), Ok(Action::Help));
+
+    let expected = Options {
+        input: Input::Stdin,
+        mode: Mode::Integer,
+        precision: Some(3),
+        decimal: Settings::default(),
+        json: true,
+        continue_on_error: true,
+    };
+    assert_eq!(args(&["--json", "-p", "3", "--continue-on-error", "--mode", "integer"], false), Ok(Action::Run(expected)));
+
+    assert!(args(&["--nope"], true).is_err());
+    assert!(args(&["-x"], true).is_err());
+    assert!(args(&["-p"], true).is_err());
+    assert!(args(&["-p", "x"], true).is_err());
+    assert!(args(&["-m", "complex"], true).is_err());
+    assert!(args(&["--scale", "-1"], true).is_err());
+    assert!(args(&["--rounding", "up"], true).is_err());
+
+    let expected = Options {
+        mode: Mode::Decimal,
+        decimal: Settings { scale: 4, rounding: Rounding::Truncate },
+        ..options(Input::Expression("1/3".to_string()))
+    };
+    assert_eq!(args(&["--scale", "4", "-m", "decimal", "--rounding", "truncate", "1/3"], true), Ok(Action::Run(expected)));
+    assert!(args(&["-f", "a", "1+1"], true).is_err());
+    assert!(args(&["-f", "a", "-"], true).is_err());
+
+    // Only a subcommand when it comes first
+    assert_eq!(args(&["1", "worksheet"], true), Ok(Action::Run(options(Input::Expression("1 worksheet".to_string())))));
+}
+
+#[test]
+fn test_worksheet() {
+    let args = |list: &[&str]| parse_args(list.iter().map(|s| s.to_string()), true);
+
+    let expected = WorksheetOptions {
+        count: 10,
+        difficulty: Difficulty::Medium,
+        constraints: Constraints::default(),
+        seed: None,
+        answers: true,
+    };
+    assert_eq!(args(&["worksheet"]), Ok(Action::Worksheet(expected)));
+
+    let mut constraints = Constraints::new(Difficulty::Hard);
+    constraints.max_depth = 3;
+    constraints.operators = vec![Operator::Add, Operator::Mul, Operator::USub];
+    let expected = WorksheetOptions {
+        count: 3,
+        difficulty: Difficulty::Hard,
+        constraints,
+        seed: Some(5),
+        answers: false,
+    };
+    let parsed = args(&["worksheet", "--operators", "+*u", "-n", "3", "-d", "hard", "--max-depth", "3", "-s", "5", "--no-answers"]);
+    assert_eq!(parsed, Ok(Action::Worksheet(expected)));
+
+    assert!(args(&["worksheet", "-d", "extreme"]).is_err());
+    assert!(args(&["worksheet", "--operators", "+&"]).is_err());
+    assert!(args(&["worksheet", "-n", "ten"]).is_err());
+    assert!(args(&["worksheet", "1+1"]).is_err());
+
+    let options = match args(&["worksheet", "-n", "12", "-s", "42"]) {
+        Ok(Action::Worksheet(options)) => options,
+        other => panic!("{:?}", other),
+    };
+    let sheet = worksheet(&options).unwrap();
+    assert_eq!(sheet, worksheet(&options).unwrap());
+    assert!(sheet.starts_with("Worksheet (medium, seed 42)\n\n 1. "));
+
+    // Every problem's answer is in the key
+    let lines = sheet.lines().collect::<Vec<&str>>();
+    let problems = &lines[2..14];
+    let answers = &lines[17..29];
+    for (problem, answer) in problems.iter().zip(answers.iter()) {
+        let (number, problem) = problem.split_at(4);
+        assert_eq!(&answer[..4], number);
+        assert_eq!(Tree::parse(problem).unwrap().evaluate().unwrap().to_string(), &answer[4..]);
+    }
+
+    let options = match args(&["worksheet", "--operators", "u"]) {
+        Ok(Action::Worksheet(options)) => options,
+        other => panic!("{:?}", other),
+    };
+    assert!(worksheet(&options).is_err());
+}
+
+#[test]
+fn test_evaluate_lines() {
+    use pemdrs::decimal::Rounding;
+
+    let run = |input: &str, options: &Options| {
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let code = evaluate_lines(input.as_bytes(), options, &mut out, &mut err);
+        (code, String::from_utf8(out).unwrap(), String::from_utf8(err).unwrap())
+    };
+    let mut options = Options {
+        input: Input::Stdin,
+        mode: Mode::Float,
+        precision: None,
+        decimal: Settings::default(),
+        json: false,
+        continue_on_error: false,
+    };
+
+    let (code, out, err) = run("3+4*2\n\n# comment\n1/4\n", &options);
+    assert_eq!((code, out.as_str(), err.as_str()), (EXIT_OK, "11\n0.25\n", ""));
+
+    // Stops at the first failure, exit code says what kind it was
+    let (code, out, err) = run("1\n2 +\n3\n", &options);
+    assert_eq!((code, out.as_str()), (EXIT_PARSE, "1\n"));
+    assert!(err.starts_with("error: Missing operand"));
+
+    let (code, out, _) = run("1/0\n2 +\n3\n", &options);
+    assert_eq!((code, out.as_str()), (EXIT_EVAL, ""));
+
+    // Unless asked not to, in which case the first failure decides the exit code
+    options.continue_on_error = true;
+    let (code, out, _) = run("1/0\n2 +\n3\n", &options);
+    assert_eq!((code, out.as_str()), (EXIT_EVAL, "3\n"));
+
+    options.precision = Some(2);
+    let (_, out, _) = run("2/3\n1/0\n", &options);
+    assert_eq!(out, "0.67\n");
+
+    options.json = true;
+    let (code, out, err) = run("2/3\nx\n\"1\" + 1\n", &options);
+    assert_eq!(code, EXIT_EVAL);
+    assert_eq!(err, "");
+    assert_eq!(out, concat!(
+        "{\"input\": \"2/3\", \"result\": 0.67, \"error\": null}\n",
+        "{\"input\": \"x\", \"result\": null, \"error\": \"Unbound variable 'x'\"}\n",
+        "{\"input\": \"\\\"1\\\" + 1\", \"result\": null, \"error\": \"Unknown characters '\\\"' at 0, '\\\"' at 2\"}\n",
+    ));
+
+    options.precision = None;
+    let (_, out, _) = run("10^400\n", &options);
+    assert_eq!(out, "{\"input\": \"10^400\", \"result\": \"inf\", \"error\": null}\n");
+
+    // Exact results are strings, and don't get rounded
+    options.mode = Mode::Integer;
+    options.precision = Some(2);
+    let (code, out, _) = run("2^70 // 3\n7/2\n", &options);
+    assert_eq!(code, EXIT_EVAL);
+    assert_eq!(out, concat!(
+        "{\"input\": \"2^70 // 3\", \"result\": \"393530540239137101141\", \"error\": null}\n",
+        "{\"input\": \"7/2\", \"result\": null, \"error\": \"7 / 2 isn't a whole number, use // to round down\"}\n",
+    ));
+
+    options.json = false;
+    options.mode = Mode::Exact;
+    let (_, out, _) = run("1/3 + 1/6\n", &options);
+    assert_eq!(out, "1/2\n");
+
+    options.mode = Mode::Decimal;
+    options.decimal.rounding = Rounding::HalfUp;
+    let (_, out, _) = run("0.1 + 0.2\n2.675 * 1\n10 / 3\n", &options);
+    assert_eq!(out, "0.30\n2.68\n3.33\n");
+}
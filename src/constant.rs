@@ -0,0 +1,96 @@
+use std::{
+    collections::HashMap,
+    f64::consts,
+};
+
+
+/// Constants every `Constants` table starts out with, also what `Context::constant` falls back on
+pub const BUILTIN_CONSTANTS: &[(&str, f64)] = &[
+    ("pi",  consts::PI),
+    ("e",   consts::E),
+    ("tau", consts::TAU),
+    ("inf", f64::INFINITY),
+    ("nan", f64::NAN),
+];
+
+/// Look up a builtin constant by name
+pub fn builtin_constant(name: &str) -> Option<f64> {
+    BUILTIN_CONSTANTS.iter()
+        .find(|&&(n, _)| n == name)
+        .map(|&(_, value)| value)
+}
+
+/// Named values that aren't expected to change, unlike variables they can be folded into
+/// the tree ahead of time with `Tree::fold_constants`
+/// ```rust
+/// use pemdrs::{ Tree, constant::Constants };
+///
+/// let mut constants = Constants::new();
+/// constants.define("g", 9.81);
+///
+/// let mut tree = Tree::parse("g * t^2 / 2")?;
+/// tree.fold_constants(&constants);
+/// assert_eq!(tree.variables().into_iter().collect::<Vec<&str>>(), vec!["t"]);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Constants {
+    values: HashMap<String, f64>,
+} impl Constants {
+    /// A table with the builtin constants in it
+    pub fn new() -> Self {
+        let mut constants = Constants::empty();
+        for &(name, value) in BUILTIN_CONSTANTS {
+            constants.define(name, value);
+        }
+        constants
+    }
+
+    /// A table without even the builtins
+    pub fn empty() -> Self {
+        Constants { values: HashMap::new() }
+    }
+
+    /// Add a constant, returning the old value if `name` was already defined
+    pub fn define(&mut self, name: &str, value: f64) -> Option<f64> {
+        self.values.insert(name.to_string(), value)
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<f64> {
+        self.values.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        self.values.get(name).copied()
+    }
+
+    /// Every constant and its value, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.values.iter().map(|(name, &value)| (name.as_str(), value))
+    }
+} impl Default for Constants {
+    fn default() -> Self {
+        Constants::new()
+    }
+}
+
+
+#[test]
+fn test_constants() {
+    let mut constants = Constants::new();
+    assert_eq!(constants.get("pi"), Some(consts::PI));
+    assert_eq!(constants.get("inf"), Some(f64::INFINITY));
+    assert!(constants.get("nan").unwrap().is_nan());
+    assert_eq!(constants.get("g"), None);
+
+    assert_eq!(constants.define("g", 9.81), None);
+    assert_eq!(constants.define("g", 9.8), Some(9.81));
+    assert_eq!(constants.get("g"), Some(9.8));
+
+    assert_eq!(constants.remove("pi"), Some(consts::PI));
+    assert_eq!(constants.get("pi"), None);
+
+    assert!(Constants::empty().get("pi").is_none());
+    assert_eq!(builtin_constant("tau"), Some(consts::TAU));
+    assert_eq!(builtin_constant("g"), None);
+}
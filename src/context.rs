@@ -0,0 +1,191 @@
+use crate::{
+    constant::{ Constants, builtin_constant },
+    function::{ Function, FunctionRegistry, builtin },
+};
+
+use std::{
+    borrow::Borrow,
+    collections::{ BTreeMap, HashMap },
+    hash::{ BuildHasher, Hash },
+};
+
+
+/// Where variables get their values from when evaluating a `Tree`
+/// ```rust
+/// use std::collections::HashMap;
+/// use pemdrs::Tree;
+///
+/// let mut env = HashMap::new();
+/// env.insert("rate", 0.25);
+/// env.insert("total", 80.0);
+///
+/// assert_eq!(Tree::parse("total * (1 + rate)")?.evaluate_with(&env)?, 100.0);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub trait Context {
+    /// Value bound to `name`, `None` if there isn't one
+    fn variable(&self, name: &str) -> Option<f64>;
+
+    /// Function called `name`, only the builtins unless overridden
+    fn function(&self, name: &str) -> Option<&dyn Function> {
+        builtin(name).map(|f| f as &dyn Function)
+    }
+
+    /// Constant called `name`, only consulted when there's no variable by that name.
+    /// Only the builtins unless overridden
+    fn constant(&self, name: &str) -> Option<f64> {
+        builtin_constant(name)
+    }
+}
+
+impl<K, S> Context for HashMap<K, f64, S>
+where
+    K: Borrow<str> + Hash + Eq,
+    S: BuildHasher,
+{
+    fn variable(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+impl<K> Context for BTreeMap<K, f64>
+where
+    K: Borrow<str> + Ord,
+{
+    fn variable(&self, name: &str) -> Option<f64> {
+        self.get(name).copied()
+    }
+}
+
+impl<C> Context for &C
+where
+    C: Context + ?Sized,
+{
+    fn variable(&self, name: &str) -> Option<f64> {
+        (**self).variable(name)
+    }
+
+    fn function(&self, name: &str) -> Option<&dyn Function> {
+        (**self).function(name)
+    }
+
+    fn constant(&self, name: &str) -> Option<f64> {
+        (**self).constant(name)
+    }
+}
+
+/// No variables at all, used by `Tree::evaluate`
+impl Context for () {
+    fn variable(&self, _name: &str) -> Option<f64> {
+        None
+    }
+}
+
+/// Functions but no variables
+impl Context for FunctionRegistry {
+    fn variable(&self, _name: &str) -> Option<f64> {
+        None
+    }
+
+    fn function(&self, name: &str) -> Option<&dyn Function> {
+        self.get(name)
+    }
+}
+
+/// Variables, functions and constants together, the usual thing to evaluate against
+#[derive(Default)]
+pub struct Environment {
+    pub variables: HashMap<String, f64>,
+    pub functions: FunctionRegistry,
+    pub constants: Constants,
+} impl Environment {
+    /// No variables, just the builtin functions and constants
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    /// Bind `name` to `value`, returning the old value if there was one
+    pub fn set(&mut self, name: &str, value: f64) -> Option<f64> {
+        self.variables.insert(name.to_string(), value)
+    }
+} impl Context for Environment {
+    fn variable(&self, name: &str) -> Option<f64> {
+        self.variables.get(name).copied()
+    }
+
+    fn function(&self, name: &str) -> Option<&dyn Function> {
+        self.functions.get(name)
+    }
+
+    fn constant(&self, name: &str) -> Option<f64> {
+        self.constants.get(name)
+    }
+}
+
+
+#[test]
+fn test_environment() {
+    use crate::{ error::Error, function::Arity, token::Span, tree::Tree };
+
+    let mut env = Environment::new();
+    env.set("amount", 250.0);
+    env.functions.register_fn("tax", Arity::Fixed(2), |args| {
+        match args[1] as u32 {
+            1 => Ok(args[0] * 0.2),
+            _ => Err(Error::custom("unknown region")),
+        }
+    });
+
+    assert_eq!(Tree::parse("amount + tax(amount, 1)").unwrap().evaluate_with(&env), Ok(300.0));
+    assert_eq!(Tree::parse("sqrt(amount - 6)").unwrap().evaluate_with(&env), Ok(244.0_f64.sqrt()));
+
+    // Errors the function makes up point at the call
+    assert_eq!(
+        Tree::parse("1 + tax(amount, 7)").unwrap().evaluate_with(&env),
+        Err(Error::Custom("unknown region".to_string(), Span::new(4, 18))),
+    );
+    assert_eq!(
+        Tree::parse("tax(1)").unwrap().evaluate_with(&env),
+        Err(Error::ArityMismatch("tax".to_string(), Arity::Fixed(2), 1, Span::new(0, 6))),
+    );
+
+    // Plain maps only know about the builtins
+    let vars: HashMap<&str, f64> = HashMap::new();
+    assert_eq!(
+        Tree::parse("tax(1, 1)").unwrap().evaluate_with(&vars),
+        Err(Error::UnknownFunction("tax".to_string(), Span::new(0, 9))),
+    );
+
+    // And a registry on its own has no variables
+    env.functions.remove("sqrt");
+    assert_eq!(
+        Tree::parse("sqrt(4)").unwrap().evaluate_with(&env.functions),
+        Err(Error::UnknownFunction("sqrt".to_string(), Span::new(0, 7))),
+    );
+}
+
+#[test]
+fn test_constants() {
+    use crate::{ error::Error, token::Span, tree::Tree };
+    use std::f64::consts::PI;
+
+    let circumference = Tree::parse("2 * pi * r").unwrap();
+
+    let mut env = Environment::new();
+    env.set("r", 2.0);
+    assert_eq!(circumference.evaluate_with(&env), Ok(4.0 * PI));
+
+    // Plain maps get the builtin constants too
+    let mut vars = HashMap::new();
+    vars.insert("r", 0.5);
+    assert_eq!(circumference.evaluate_with(&vars), Ok(PI));
+    assert_eq!(Tree::parse("tau / 2").unwrap().evaluate(), Ok(PI));
+
+    // Variables shadow constants
+    vars.insert("pi", 3.0);
+    assert_eq!(circumference.evaluate_with(&vars), Ok(3.0));
+
+    env.constants.define("g", 9.8);
+    assert_eq!(Tree::parse("g * 2").unwrap().evaluate_with(&env), Ok(19.6));
+    assert_eq!(Tree::parse("g * 2").unwrap().evaluate(), Err(Error::UnboundVariable("g".to_string(), Span::new(0, 1))));
+}
@@ -0,0 +1,362 @@
+use crate::{
+    bigint::{ self, BigInt },
+    error::{ Error, Result },
+    function::Function,
+    number::{ Number, no_factorial },
+    rational::Rational,
+};
+
+use std::{
+    cmp::Ordering,
+    fmt::{ Debug, Display, Formatter, Result as fmt_Result },
+    str::FromStr,
+};
+
+
+/// What `Decimal` does with the digits past its scale
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Rounding {
+    /// To the nearest, halves go to the even neighbour (banker's rounding)
+    #[default]
+    HalfEven,
+    /// To the nearest, halves go away from zero
+    HalfUp,
+    /// Towards zero, dropping the extra digits
+    Truncate,
+} impl FromStr for Rounding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Rounding> {
+        match s {
+            "half-even" => Ok(Rounding::HalfEven),
+            "half-up" => Ok(Rounding::HalfUp),
+            "truncate" => Ok(Rounding::Truncate),
+            _ => Err(Error::custom(&format!("Unknown rounding mode {:?}, expected half-even, half-up or truncate", s))),
+        }
+    }
+} impl Display for Rounding {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        let name = match self {
+            Rounding::HalfEven => "half-even",
+            Rounding::HalfUp => "half-up",
+            Rounding::Truncate => "truncate",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How many digits a `Decimal` keeps after the point, and how it gets rid of the rest
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    pub scale: u32,
+    pub rounding: Rounding,
+} impl Settings {
+    /// `10 ^ scale`, what a value is multiplied by to make it a whole number
+    fn factor(&self) -> Result<BigInt> {
+        BigInt::from(10).pow(self.scale as u64, &bigint::Settings::default())
+    }
+} impl Default for Settings {
+    /// Cents, rounded half to even
+    fn default() -> Self {
+        Settings { scale: 2, rounding: Rounding::HalfEven }
+    }
+}
+
+/// `numerator / denominator` as a whole number, rounded with `rounding`
+fn divide(numerator: &BigInt, denominator: &BigInt, rounding: Rounding) -> Result<BigInt> {
+    let (quotient, remainder) = numerator.div_rem(denominator)?;
+    if remainder.is_zero() || rounding == Rounding::Truncate {
+        return Ok(quotient);
+    }
+
+    // The remainder is the same sign as the numerator, so away from zero is the way the quotient leans
+    let away = if numerator.is_negative() != denominator.is_negative() {
+        &quotient - &BigInt::one()
+    } else {
+        &quotient + &BigInt::one()
+    };
+    let twice = &remainder.abs() * &BigInt::from(2);
+    Ok(match (twice.cmp(&denominator.abs()), rounding) {
+        (Ordering::Less, _) => quotient,
+        (Ordering::Equal, Rounding::HalfEven) if quotient.is_even() => quotient,
+        _ => away,
+    })
+}
+
+/// A fixed point decimal, every result is rounded to the same number of places so it never picks up
+/// binary floating point error. Evaluating with it (see `Tree::evaluate_with_settings`) reads literals
+/// digit for digit, only irrational results (like `sqrt(2)`) are worked out with `f64` before rounding
+/// ```rust
+/// use pemdrs::{ Tree, decimal::{ Decimal, Rounding, Settings } };
+///
+/// let cents = Settings::default();
+/// assert_eq!(Tree::parse("0.1 + 0.2")?.evaluate_with_settings::<Decimal, _>(&(), &cents)?.to_string(), "0.30");
+/// assert_eq!(Tree::parse("10 / 3")?.evaluate_with_settings::<Decimal, _>(&(), &cents)?.to_string(), "3.33");
+///
+/// let price = Decimal::parse("2.675", Settings { scale: 2, rounding: Rounding::HalfUp })?;
+/// assert_eq!(price.to_string(), "2.68");
+/// assert_eq!(Decimal::parse("2.675", cents)?.to_string(), "2.68");
+/// assert_eq!(Decimal::parse("2.665", cents)?.to_string(), "2.66");
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct Decimal {
+    /// The value times `10 ^ settings.scale`
+    unscaled: BigInt,
+    settings: Settings,
+} impl Decimal {
+    /// `value` rounded to `settings.scale` places
+    pub fn from_rational(value: &Rational, settings: Settings) -> Result<Self> {
+        let unscaled = divide(&(value.numerator() * &settings.factor()?), value.denominator(), settings.rounding)?;
+        Ok(Decimal { unscaled, settings })
+    }
+
+    /// A decimal like `12.345` (or a fraction like `1/3`), rounded to `settings.scale` places
+    pub fn parse(s: &str, settings: Settings) -> Result<Self> {
+        Decimal::from_rational(&s.parse()?, settings)
+    }
+
+    pub fn settings(&self) -> Settings {
+        self.settings
+    }
+
+    /// The exact value
+    pub fn to_rational(&self) -> Result<Rational> {
+        Rational::new(self.unscaled.clone(), self.settings.factor()?)
+    }
+
+    fn with_unscaled(&self, unscaled: BigInt) -> Decimal {
+        Decimal { unscaled, settings: self.settings }
+    }
+
+    /// `other` with `self`'s scale, so the two can be combined digit for digit
+    fn operand(&self, other: &Decimal) -> Result<BigInt> {
+        if other.settings.scale == self.settings.scale {
+            Ok(other.unscaled.clone())
+        } else {
+            Ok(Decimal::from_rational(&other.to_rational()?, self.settings)?.unscaled)
+        }
+    }
+
+    /// An exact result rounded to `self`'s scale, or if it has no exact value the closest `f64` rounded instead
+    fn round(&self, exact: Result<Rational>, approximate: impl FnOnce() -> f64) -> Result<Decimal> {
+        match exact {
+            Ok(value) => Decimal::from_rational(&value, self.settings),
+            Err(Error::Irrational(..)) => Decimal::from_f64(approximate(), &self.settings),
+            Err(e) => Err(e),
+        }
+    }
+} impl Number for Decimal {
+    type Settings = Settings;
+
+    /// The shortest decimal that rounds to `v`, so `0.1` is exactly `0.1`
+    fn from_f64(v: f64, settings: &Settings) -> Result<Self> {
+        if !v.is_finite() {
+            return Err(Error::custom(&format!("{} isn't a real number", v)));
+        }
+        Decimal::from_rational(&Rational::from_f64(v, &bigint::Settings::default())?, *settings)
+    }
+
+    /// Digit for digit, never through `f64`
+    fn from_literal(literal: &str, settings: &Settings) -> Result<Self> {
+        Decimal::parse(literal, *settings)
+    }
+
+    fn to_f64(&self) -> f64 {
+        self.to_string().parse().expect("Something went wrong! (decimal didn't display as a number)")
+    }
+
+    fn add(&self, other: &Self) -> Result<Self> {
+        Ok(self.with_unscaled(&self.unscaled + &self.operand(other)?))
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self> {
+        Ok(self.with_unscaled(&self.unscaled - &self.operand(other)?))
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self> {
+        let product = &self.unscaled * &self.operand(other)?;
+        Ok(self.with_unscaled(divide(&product, &self.settings.factor()?, self.settings.rounding)?))
+    }
+
+    fn div(&self, other: &Self) -> Result<Self> {
+        let dividend = &self.unscaled * &self.settings.factor()?;
+        Ok(self.with_unscaled(divide(&dividend, &self.operand(other)?, self.settings.rounding)?))
+    }
+
+    fn int_div(&self, other: &Self) -> Result<Self> {
+        let quotient = self.unscaled.div_mod_floor(&self.operand(other)?)?.0;
+        Ok(self.with_unscaled(&quotient * &self.settings.factor()?))
+    }
+
+    fn rem(&self, other: &Self) -> Result<Self> {
+        Ok(self.with_unscaled(self.unscaled.div_mod_floor(&self.operand(other)?)?.1))
+    }
+
+    /// Exact for whole exponents and perfect roots, rounded once at the end
+    fn pow(&self, other: &Self, _settings: &Settings) -> Result<Self> {
+        let exact = self.to_rational()?.pow(&other.to_rational()?, &bigint::Settings::default());
+        self.round(exact, || self.to_f64().powf(other.to_f64()))
+    }
+
+    fn neg(&self) -> Self {
+        self.with_unscaled(-&self.unscaled)
+    }
+
+    fn factorial(&self, _settings: &Settings) -> Result<Self> {
+        let (whole, fraction) = self.unscaled.div_rem(&self.settings.factor()?)?;
+        if self.unscaled.is_negative() || !fraction.is_zero() {
+            return Err(no_factorial(self));
+        }
+        Ok(self.with_unscaled(&whole.factorial(&bigint::Settings::default())? * &self.settings.factor()?))
+    }
+
+    /// Exact wherever `Rational` is, irrational results are only as good as an `f64`
+    fn call(function: &dyn Function, args: &[Self], settings: &Settings) -> Result<Self> {
+        let exact = args.iter().map(Decimal::to_rational).collect::<Result<Vec<Rational>>>()?;
+        let approximate = || function.eval(&args.iter().map(Number::to_f64).collect::<Vec<f64>>());
+        match Rational::call(function, &exact, &bigint::Settings::default()) {
+            Ok(value) => Decimal::from_rational(&value, *settings),
+            Err(Error::Irrational(..)) => Decimal::from_f64(approximate()?, settings),
+            Err(e) => Err(e),
+        }
+    }
+} impl Display for Decimal {
+    /// Always `scale` digits after the point, like `-1.50`
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        let scale = self.settings.scale as usize;
+        let digits = format!("{:0>w$}", self.unscaled.abs().to_string(), w = scale + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - scale);
+        if fraction.is_empty() {
+            f.pad_integral(!self.unscaled.is_negative(), "", whole)
+        } else {
+            f.pad_integral(!self.unscaled.is_negative(), "", &format!("{}.{}", whole, fraction))
+        }
+    }
+} impl Debug for Decimal {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}", self)
+    }
+}
+
+
+#[test]
+fn test_decimal_arithmetic() {
+    use crate::token::Span;
+
+    let settings = |scale: u32, rounding: Rounding| Settings { scale, rounding };
+    let d = |s: &str| Decimal::parse(s, Settings::default()).unwrap();
+
+    // Every rounding mode on ties, near ties and negatives
+    let cases = [
+        ("2.345", "2.34", "2.35", "2.34"),
+        ("2.355", "2.36", "2.36", "2.35"),
+        ("2.3451", "2.35", "2.35", "2.34"),
+        ("-2.345", "-2.34", "-2.35", "-2.34"),
+        ("-2.3449", "-2.34", "-2.34", "-2.34"),
+        ("0.005", "0.00", "0.01", "0.00"),
+        ("-0.001", "0.00", "0.00", "0.00"),
+        ("1/3", "0.33", "0.33", "0.33"),
+        ("-2/3", "-0.67", "-0.67", "-0.66"),
+    ];
+    for &(input, even, up, truncate) in cases.iter() {
+        assert_eq!(Decimal::parse(input, settings(2, Rounding::HalfEven)).unwrap().to_string(), even, "{}", input);
+        assert_eq!(Decimal::parse(input, settings(2, Rounding::HalfUp)).unwrap().to_string(), up, "{}", input);
+        assert_eq!(Decimal::parse(input, settings(2, Rounding::Truncate)).unwrap().to_string(), truncate, "{}", input);
+    }
+
+    assert_eq!(d("0.1").add(&d("0.2")), Ok(d("0.3")));
+    assert_eq!(d("1.05").mul(&d("1.05")).unwrap().to_string(), "1.10");
+    assert_eq!(d("-7.5").int_div(&d("2")).unwrap().to_string(), "-4.00");
+    assert_eq!(d("-7.5").rem(&d("2")).unwrap().to_string(), "0.50");
+    assert_eq!(d("1").div(&d("0")), Err(Error::DivisionByZero(Span::default())));
+    assert_eq!(d("5").factorial(&Settings::default()).unwrap().to_string(), "120.00");
+    assert!(d("2.5").factorial(&Settings::default()).is_err());
+    assert_eq!(d("12.5").to_f64(), 12.5);
+
+    // Mixing scales rounds to the left operand's
+    let fine = Decimal::parse("0.125", settings(3, Rounding::HalfEven)).unwrap();
+    assert_eq!(d("1").add(&fine).unwrap().to_string(), "1.12");
+
+    assert_eq!(Decimal::parse("7", settings(0, Rounding::HalfEven)).unwrap().to_string(), "7");
+    assert_eq!(format!("{:>8}|{:+}", d("-1.5"), d("3")), "   -1.50|+3.00");
+    assert!(Decimal::parse("1.2.3", Settings::default()).is_err());
+
+    assert_eq!("half-up".parse::<Rounding>(), Ok(Rounding::HalfUp));
+    assert_eq!(Rounding::Truncate.to_string(), "truncate");
+    assert!("ceiling".parse::<Rounding>().is_err());
+}
+
+#[test]
+fn test_decimal_evaluate() {
+    use crate::{ context::Environment, token::Span, tree::Tree };
+
+    let evaluate = |input: &str, scale: u32, rounding: Rounding| {
+        Tree::parse(input).unwrap()
+            .evaluate_with_settings::<Decimal, _>(&(), &Settings { scale, rounding })
+            .map(|d| d.to_string())
+    };
+    let cases = [
+        ("0.1 + 0.2", 2, "0.30"),
+        ("19.99 * 3 - 0.01", 2, "59.96"),
+        ("100 / 3 * 3", 2, "99.99"),
+        ("1 / 8", 2, "0.12"),
+        ("1.000000000000000000001 - 1", 21, "0.000000000000000000001"),
+        ("123456789012345678901234567890.5 + 0.5", 1, "123456789012345678901234567891.0"),
+        ("1.1^2", 4, "1.2100"),
+        ("2^-2", 1, "0.2"),
+        ("(9/4)^0.5", 2, "1.50"),
+        ("sqrt(2)", 6, "1.414214"),
+        ("abs(-2.5) + floor(2.5) + max(0.1, 0.25)", 2, "4.75"),
+        ("pi", 4, "3.1416"),
+        ("10 // 3 + 10 % 3 + 4!", 0, "28"),
+        ("floor(10^400 / 3) * 3 - 10^400", 0, "-1"),
+    ];
+    for &(input, scale, expected) in cases.iter() {
+        assert_eq!(evaluate(input, scale, Rounding::HalfEven).as_deref(), Ok(expected), "{}", input);
+    }
+
+    assert_eq!(evaluate("2 / 3", 3, Rounding::HalfUp).as_deref(), Ok("0.667"));
+    assert_eq!(evaluate("2 / 3", 3, Rounding::Truncate).as_deref(), Ok("0.666"));
+    assert_eq!(evaluate("-2 / 3", 3, Rounding::Truncate).as_deref(), Ok("-0.666"));
+
+    // Errors point at what caused them
+    assert_eq!(evaluate("1 / (2 - 2)", 2, Rounding::HalfEven), Err(Error::DivisionByZero(Span::new(5, 10))));
+    assert_eq!(
+        evaluate("1 + (-4)^0.5", 2, Rounding::HalfEven),
+        Err(Error::Custom("-4 ^ (1/2) isn't a real number".to_string(), Span::new(5, 12))),
+    );
+    assert!(matches!(evaluate("ln(0)", 2, Rounding::HalfEven), Err(Error::Custom(_, span)) if span == Span::new(0, 5)));
+
+    // Variables come from f64s, but only their shortest decimal
+    let mut env = Environment::new();
+    env.set("price", 0.1);
+    let settings = Settings { scale: 20, rounding: Rounding::HalfEven };
+    assert_eq!(
+        Tree::parse("price * 3").unwrap().evaluate_with_settings::<Decimal, _>(&env, &settings).map(|d| d.to_string()),
+        Ok("0.30000000000000000000".to_string()),
+    );
+
+    // Literals are read digit for digit from any text format, not just infix
+    let settings = Settings { scale: 21, rounding: Rounding::HalfEven };
+    let exact = "0.000000000000000000001";
+    for tree in [Tree::parse_rpn("1.000000000000000000001 1 -"), Tree::parse_sexpr("(- 1.000000000000000000001 1)")] {
+        let tree = tree.unwrap();
+        assert_eq!(tree.evaluate_with_settings::<Decimal, _>(&(), &settings).map(|d| d.to_string()).as_deref(), Ok(exact));
+    }
+    assert_eq!(Tree::parse_rpn("0.1 3 *").unwrap().evaluate_as::<Decimal>().map(|d| d.to_string()), Ok("0.30".to_string()));
+
+    // Builtins looked up in an environment are only approximated when they're irrational
+    let env = Environment::new();
+    let with_env = |input: &str, scale: u32| {
+        Tree::parse(input).unwrap()
+            .evaluate_with_settings::<Decimal, _>(&env, &Settings { scale, rounding: Rounding::HalfEven })
+            .map(|d| d.to_string())
+    };
+    assert_eq!(with_env("sqrt(2)", 6).as_deref(), Ok("1.414214"));
+    assert_eq!(with_env("sin(1/2)", 6).as_deref(), Ok("0.479426"));
+    assert_eq!(with_env("sqrt((10^20 + 1)^2)", 0).as_deref(), Ok("100000000000000000001"));
+
+    // The default settings are used without asking for any
+    assert_eq!(Tree::parse("1/3").unwrap().evaluate_as::<Decimal>().map(|d| d.to_string()), Ok("0.33".to_string()));
+}
@@ -0,0 +1,359 @@
+use crate::{
+    error::{ Error, Result },
+    function::builtin,
+    token::{ Token, Operator, Span },
+    tree::{ Node, Tree },
+};
+
+
+// Building blocks for derivatives. Each one simplifies the obvious cases (constant operands, adding zero,
+// multiplying by one, ...) as it goes, otherwise every rule application would leave a trail of `* 1`s and `+ 0`s
+
+fn value(v: f64, span: Span) -> Node {
+    Node::value(v, span)
+}
+
+fn is_value(node: &Node, v: f64) -> bool {
+    node.as_value() == Some(v)
+}
+
+fn operator(op: Operator, left: Node, right: Node, span: Span) -> Node {
+    // Operands that are both values fold into one, unless that would hide an error like dividing by zero
+    if let (Some(a), Some(b)) = (left.as_value(), right.as_value()) {
+        if let Ok(v) = op.evaluate(a, b) {
+            return value(v, span);
+        }
+    }
+    Node::binary(op, left, right, span)
+}
+
+fn call(name: &str, args: Vec<Node>, span: Span) -> Node {
+    let mut node = Node::new(Token::Function(name.to_string(), args.len()), span);
+    node.args = args;
+    node
+}
+
+fn negate(mut node: Node, span: Span) -> Node {
+    if let Some(v) = node.as_value() {
+        return value(-v, span);
+    }
+    match node.token {
+        Token::Operator(Operator::USub) => return *node.right.expect("Something went wrong! (unary minus with no operand)"),
+        // Fold into a leading coefficient, -(2 * x) reads better as -2 * x
+        Token::Operator(Operator::Mul) => {
+            if let Some(c) = node.left().and_then(Node::as_value) {
+                node.left = Some(Box::new(value(-c, span)));
+                return node;
+            }
+        },
+        _ => {},
+    }
+
+    Node::negative(node, span)
+}
+
+fn sum(a: Node, b: Node, span: Span) -> Node {
+    if is_value(&a, 0.0) {
+        b
+    } else if is_value(&b, 0.0) {
+        a
+    } else if matches!(b.token, Token::Operator(Operator::USub)) {
+        difference(a, negate(b, span), span)
+    } else {
+        operator(Operator::Add, a, b, span)
+    }
+}
+
+fn difference(a: Node, b: Node, span: Span) -> Node {
+    if is_value(&b, 0.0) {
+        a
+    } else if is_value(&a, 0.0) {
+        negate(b, span)
+    } else {
+        operator(Operator::Sub, a, b, span)
+    }
+}
+
+fn product(a: Node, b: Node, span: Span) -> Node {
+    if is_value(&a, 0.0) || is_value(&b, 0.0) {
+        value(0.0, span)
+    } else if is_value(&a, 1.0) {
+        b
+    } else if is_value(&b, 1.0) {
+        a
+    } else if is_value(&a, -1.0) {
+        negate(b, span)
+    } else if is_value(&b, -1.0) {
+        negate(a, span)
+    } else if b.as_value().is_some() && a.as_value().is_none() {
+        // Coefficients go in front
+        product(b, a, span)
+    } else {
+        // And get merged with the coefficient already there, 2 * (3 * x) is 6 * x
+        if let (Some(c), Token::Operator(Operator::Mul)) = (a.as_value(), &b.token) {
+            if let Some(d) = b.left().and_then(Node::as_value) {
+                let mut b = b;
+                b.left = Some(Box::new(value(c * d, span)));
+                return b;
+            }
+        }
+        operator(Operator::Mul, a, b, span)
+    }
+}
+
+fn quotient(a: Node, b: Node, span: Span) -> Node {
+    if is_value(&a, 0.0) && !is_value(&b, 0.0) {
+        value(0.0, span)
+    } else if is_value(&b, 1.0) {
+        a
+    } else {
+        operator(Operator::Div, a, b, span)
+    }
+}
+
+fn power(a: Node, b: Node, span: Span) -> Node {
+    if is_value(&b, 0.0) {
+        value(1.0, span)
+    } else if is_value(&b, 1.0) {
+        a
+    } else {
+        operator(Operator::Pow, a, b, span)
+    }
+}
+
+/// Whether `node` changes when `var` does
+fn depends_on(node: &Node, var: &str) -> bool {
+    match &node.token {
+        Token::Variable(name) => name == var,
+        _ => node.children().any(|child| depends_on(child, var)),
+    }
+}
+
+/// Derivative of `node` with respect to `var`, every node it creates spans whatever it's the derivative of
+fn derivative(node: &Node, var: &str) -> Result<Node> {
+    let span = node.span;
+    if !depends_on(node, var) {
+        return Ok(value(0.0, span));
+    }
+
+    let (op, u, v) = match &node.token {
+        Token::Variable(_) => return Ok(value(1.0, span)),
+        Token::Function(name, _) => return function_derivative(name, &node.args, var, span),
+        &Token::Operator(op) => (
+            op,
+            node.left.as_deref(),
+            node.right.as_deref().ok_or(Error::MissingOperand(op, span))?,
+        ),
+        _ => unreachable!(),
+    };
+    // Factorials only exist at whole numbers
+    if op == Operator::Factorial {
+        return Err(Error::NotDifferentiable(op.to_string(), span));
+    }
+
+    let dv = derivative(v, var)?;
+    let u = match (op, u) {
+        (Operator::USub, _) => return Ok(negate(dv, span)),
+        (_, Some(u)) => u,
+        (_, None) => return Err(Error::MissingOperand(op, span)),
+    };
+    let du = derivative(u, var)?;
+
+    Ok(match op {
+        Operator::Add => sum(du, dv, span),
+        Operator::Sub => difference(du, dv, span),
+        // (uv)' = u'v + uv'
+        Operator::Mul => sum(product(du, v.clone(), span), product(u.clone(), dv, span), span),
+        // (u/c)' = u'/c
+        Operator::Div if is_value(&dv, 0.0) => quotient(du, v.clone(), span),
+        // (u/v)' = (u'v - uv') / v^2
+        Operator::Div => quotient(
+            difference(product(du, v.clone(), span), product(u.clone(), dv, span), span),
+            power(v.clone(), value(2.0, span), span),
+            span,
+        ),
+        Operator::Pow if !depends_on(v, var) => {
+            // (u^n)' = n u^(n - 1) u'
+            let exponent = difference(v.clone(), value(1.0, span), span);
+            product(product(v.clone(), power(u.clone(), exponent, span), span), du, span)
+        },
+        Operator::Pow if !depends_on(u, var) => {
+            // (a^v)' = a^v ln(a) v'
+            product(product(node.clone(), call("ln", vec![u.clone()], span), span), dv, span)
+        },
+        Operator::Pow => {
+            // (u^v)' = u^v (v' ln(u) + v u' / u)
+            let logarithmic = product(dv, call("ln", vec![u.clone()], span), span);
+            let power_rule = quotient(product(v.clone(), du, span), u.clone(), span);
+            product(node.clone(), sum(logarithmic, power_rule, span), span)
+        },
+        // Flat between the jumps, like floor
+        Operator::IntDiv => value(0.0, span),
+        // u % v = u - v (u // v), and u // v is flat
+        Operator::Mod => difference(du, product(operator(Operator::IntDiv, u.clone(), v.clone(), span), dv, span), span),
+        Operator::USub | Operator::Factorial => unreachable!(),
+    })
+}
+
+/// Derivative of a call to the built in function `name`, by the chain rule
+fn function_derivative(name: &str, args: &[Node], var: &str, span: Span) -> Result<Node> {
+    let function = builtin(name).ok_or_else(|| Error::UnknownFunction(name.to_string(), span))?;
+    if !function.arity.accepts(args.len()) {
+        return Err(Error::ArityMismatch(name.to_string(), function.arity, args.len(), span));
+    }
+
+    let this = || call(name, args.to_vec(), span);
+    let square = |node: Node| power(node, value(2.0, span), span);
+
+    if let [a, b] = args {
+        let (da, db) = (derivative(a, var)?, derivative(b, var)?);
+        return match name {
+            // atan2(a, b)' = (b a' - a b') / (a^2 + b^2)
+            "atan2" => Ok(quotient(
+                difference(product(b.clone(), da, span), product(a.clone(), db, span), span),
+                sum(square(a.clone()), square(b.clone()), span),
+                span,
+            )),
+            // hypot(a, b)' = (a a' + b b') / hypot(a, b)
+            "hypot" => Ok(quotient(
+                sum(product(a.clone(), da, span), product(b.clone(), db, span), span),
+                this(),
+                span,
+            )),
+            _ => Err(Error::NotDifferentiable(name.to_string(), span)),
+        };
+    }
+
+    let u = match args {
+        [u] => u,
+        _ => return Err(Error::NotDifferentiable(name.to_string(), span)),
+    };
+    let du = derivative(u, var)?;
+    let of_u = |name: &str| call(name, vec![u.clone()], span);
+    // 1 - u^2, for the inverse trig functions
+    let one_minus_square = || difference(value(1.0, span), square(u.clone()), span);
+
+    Ok(match name {
+        "sqrt" => quotient(du, product(value(2.0, span), this(), span), span),
+        "cbrt" => quotient(du, product(value(3.0, span), square(this()), span), span),
+        "abs" => product(du, of_u("sign"), span),
+        "exp" => product(du, this(), span),
+        "ln" => quotient(du, u.clone(), span),
+        "log" => quotient(du, product(u.clone(), call("ln", vec![value(10.0, span)], span), span), span),
+        "log2" => quotient(du, product(u.clone(), call("ln", vec![value(2.0, span)], span), span), span),
+        "sin" => product(du, of_u("cos"), span),
+        "cos" => product(negate(du, span), of_u("sin"), span),
+        "tan" => quotient(du, square(of_u("cos")), span),
+        "asin" => quotient(du, call("sqrt", vec![one_minus_square()], span), span),
+        "acos" => negate(quotient(du, call("sqrt", vec![one_minus_square()], span), span), span),
+        "atan" => quotient(du, sum(value(1.0, span), square(u.clone()), span), span),
+        "sinh" => product(du, of_u("cosh"), span),
+        "cosh" => product(du, of_u("sinh"), span),
+        "tanh" => quotient(du, square(of_u("cosh")), span),
+        // Flat everywhere they're differentiable
+        "floor" | "ceil" | "round" | "trunc" | "sign" => value(0.0, span),
+        _ => return Err(Error::NotDifferentiable(name.to_string(), span)),
+    })
+}
+
+impl Tree {
+    /// Derivative of the expression with respect to the variable `var`, every other variable is held constant.
+    /// Built in functions are differentiated by the chain rule, except for `min` and `max`. The result is
+    /// run through `Tree::simplify`
+    /// ```rust
+    /// use pemdrs::Tree;
+    ///
+    /// let tree = Tree::parse("x^3 + 2*x*y - sin(x)")?;
+    /// assert_eq!(tree.derivative("x")?.to_string(), "3 * x ^ 2 + 2 * y - cos(x)");
+    /// assert_eq!(tree.derivative("y")?.to_string(), "2 * x");
+    /// # Ok::<(), pemdrs::Error>(())
+    /// ```
+    pub fn derivative(&self, var: &str) -> Result<Tree> {
+        Ok(Tree { root: derivative(&self.root, var)? }.simplify())
+    }
+}
+
+
+#[test]
+fn test_derivative() {
+    let cases = [
+        ("x", "x", "1"),
+        ("y", "x", "0"),
+        ("pi * x", "x", "pi"),
+        ("7", "x", "0"),
+        ("x + x", "x", "2"),
+        ("3*x - 4", "x", "3"),
+        ("x*y", "x", "y"),
+        ("x*y", "y", "x"),
+        ("x^2", "x", "2 * x"),
+        ("x^-1", "x", "-(x ^ -2)"),
+        ("-2*x^3", "x", "-6 * x ^ 2"),
+        ("-x^2", "x", "2 * x"),
+        ("-(x^2)", "x", "-2 * x"),
+        ("1/x", "x", "-1 / x ^ 2"),
+        ("x/y", "x", "1 / y"),
+        ("x/y", "y", "-(x / y ^ 2)"),
+        ("2^x", "x", "2 ^ x * ln(2)"),
+        ("x^x", "x", "x ^ x * (ln(x) + 1)"),
+        ("sin(x^2)", "x", "2 * x * cos(x ^ 2)"),
+        ("cos(3*x)", "x", "-3 * sin(3 * x)"),
+        ("exp(x) * ln(x)", "x", "exp(x) * ln(x) + exp(x) / x"),
+        ("sqrt(x)", "x", "1 / (2 * sqrt(x))"),
+        ("atan(y)", "x", "0"),
+        ("floor(x)", "x", "0"),
+        ("x // 2", "x", "0"),
+        ("x^2 % 3", "x", "2 * x"),
+        ("7 % x", "x", "-(7 // x)"),
+    ];
+    for (input, var, expected) in cases.iter() {
+        let derivative = Tree::parse(input).unwrap().derivative(var).unwrap();
+        assert_eq!(derivative.to_string(), *expected, "d/d{} {}", var, input);
+    }
+
+    // Spans point at what each part is the derivative of
+    let derivative = Tree::parse("1 + sin(x)").unwrap().derivative("x").unwrap();
+    assert_eq!(derivative.root().span(), Span::new(4, 10));
+
+    assert_eq!(
+        Tree::parse("max(x, 1)").unwrap().derivative("x"),
+        Err(Error::NotDifferentiable("max".to_string(), Span::new(0, 9))),
+    );
+    assert_eq!(
+        Tree::parse("2 * x!").unwrap().derivative("x").map_err(|e| (e.to_string(), e.span())),
+        Err(("Operator '!' can't be differentiated".to_string(), Some(Span::new(4, 6)))),
+    );
+    assert_eq!(
+        Tree::parse("1 + f(x)").unwrap().derivative("x"),
+        Err(Error::UnknownFunction("f".to_string(), Span::new(4, 8))),
+    );
+    assert!(matches!(Tree::parse("sin(x, 2)").unwrap().derivative("x"), Err(Error::ArityMismatch(..))));
+    // Nothing to differentiate, so it doesn't matter what the function is
+    assert_eq!(Tree::parse("f(y)").unwrap().derivative("x").unwrap().to_string(), "0");
+}
+
+#[test]
+fn test_derivative_numeric() {
+    use crate::check::Tolerance;
+    use std::collections::HashMap;
+
+    let functions = [
+        "x^3 - 2*x^2 + x - 5", "(x + 1) / (x - 3)", "x * sin(x) * exp(-x)", "sqrt(x^2 + y^2)", "2^x * x^y",
+        "cbrt(x) + abs(x - 5)", "ln(x) + log(x) + log2(x)", "tan(x) / cos(x)", "asin(x / 4) + acos(x / 5) + atan(x)",
+        "sinh(x) + cosh(x) * tanh(x)", "atan2(y, x) + hypot(x, 2*y)", "x^x^0.5", "-(x - y)^3 / -y",
+        "floor(y) * round(x) + x", "e^(x * pi)",
+    ];
+
+    let tolerance = Tolerance::new(1e-6, 1e-5);
+    let h = 1e-6;
+    for function in functions.iter() {
+        let tree = Tree::parse(function).unwrap();
+        let derivative = tree.derivative("x").unwrap();
+        for &x in [0.3, 1.7, 2.2].iter() {
+            let at = |x: f64| -> HashMap<&str, f64> { [("x", x), ("y", 1.4)].iter().cloned().collect() };
+            // Central difference
+            let expected = (tree.evaluate_with(&at(x + h)).unwrap() - tree.evaluate_with(&at(x - h)).unwrap()) / (2.0 * h);
+            let actual = derivative.evaluate_with(&at(x)).unwrap();
+            assert!(tolerance.matches(actual, expected), "d/dx {} at {}: {} is {}, expected {}", function, x, derivative, actual, expected);
+        }
+    }
+}
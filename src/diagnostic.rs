@@ -0,0 +1,82 @@
+use crate::error::Error;
+
+
+/// Render `error` against the `source` it came from, underlining the offending region
+/// ```text
+/// error: Missing operand for operator '*'
+///   |
+///   | 2 + 3 *
+///   |       ^
+/// ```
+pub fn render(source: &str, error: &Error) -> String {
+    let mut out = format!("error: {}\n", error);
+
+    let spans = error.spans();
+    let first = match spans.first() {
+        Some(span) => *span,
+        None => return out,
+    };
+
+    // Only show the line the (first) error starts on
+    let start = floor_char_boundary(source, first.start);
+    let line_start = source[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let line_end = source[start..].find('\n').map(|i| start + i).unwrap_or_else(|| source.len());
+    let line = &source[line_start..line_end];
+
+    // Columns are in chars, not bytes, so multibyte input still lines up
+    let mut marker = String::new();
+    for span in spans {
+        // Clamp to the source so a span from somewhere else can't make us panic
+        let start = floor_char_boundary(source, span.start);
+        let end = floor_char_boundary(source, span.end.max(span.start)).min(line_end);
+        if start < line_start || start > line_end {
+            continue;
+        }
+
+        let column = source[line_start..start].chars().count();
+        let width = source[start..end.max(start)].chars().count().max(1);
+        let current = marker.chars().count();
+        if column < current {
+            continue;
+        }
+        marker.push_str(&" ".repeat(column - current));
+        marker.push_str(&"^".repeat(width));
+    }
+
+    out.push_str("  |\n");
+    out.push_str(&format!("  | {}\n", line));
+    out.push_str(&format!("  | {}\n", marker));
+
+    out
+}
+
+fn floor_char_boundary(s: &str, idx: usize) -> usize {
+    let mut idx = idx.min(s.len());
+    while !s.is_char_boundary(idx) {
+        idx -= 1;
+    }
+    idx
+}
+
+
+#[test]
+fn test_render() {
+    use crate::token::{ Operator, Span };
+
+    let rendered = render("2+3)", &Error::MismatchedParen(Span::new(3, 4)));
+    assert_eq!(rendered, "error: Mismatched parenthesis\n  |\n  | 2+3)\n  |    ^\n");
+
+    let rendered = render("2 * 1..5", &Error::Lex("1..5".to_string(), Span::new(4, 8)));
+    assert_eq!(rendered, "error: Unable to parse literal \"1..5\"\n  |\n  | 2 * 1..5\n  |     ^^^^\n");
+
+    // Multibyte characters before the span count as one column
+    let rendered = render("π + *", &Error::MissingOperand(Operator::Mul, Span::new(5, 6)));
+    assert_eq!(rendered, "error: Missing operand for operator '*'\n  |\n  | π + *\n  |     ^\n");
+
+    // Every unknown character gets a caret
+    let rendered = render("2x+3 1,000", &Error::UnknownCharacters(vec![('x', Span::new(1, 2)), (',', Span::new(6, 7))]));
+    assert_eq!(rendered, "error: Unknown characters 'x' at 1, ',' at 6\n  |\n  | 2x+3 1,000\n  |  ^    ^\n");
+
+    // Nothing to point at
+    assert_eq!(render("", &Error::EmptyExpression), "error: Empty expression\n");
+}
@@ -0,0 +1,151 @@
+use crate::{
+    function::Arity,
+    token::{ Operator, Span, is_identifier },
+};
+
+use std::{
+    fmt::{ Display, Formatter, Result as fmt_Result },
+};
+
+
+/// Everything that can go wrong while lexing, parsing or evaluating an expression
+///
+/// Spans are byte offsets into the source string, see `diagnostic::render`
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    /// A literal that could not be turned into a token (e.g. `1.2.3`)
+    Lex(String, Span),
+    /// Characters that aren't part of the grammar at all, every one of them in the input
+    UnknownCharacters(Vec<(char, Span)>),
+    /// A `(` without a matching `)`, or the other way around
+    MismatchedParen(Span),
+    /// An operator that didn't have enough operands to work with
+    MissingOperand(Operator, Span),
+    /// Operands left over after every operator was applied (e.g. `(2)(3)`)
+    ExtraOperand(Span),
+    /// Nothing to parse
+    EmptyExpression,
+    /// Right hand side of a `/` evaluated to zero, spans the divisor
+    DivisionByZero(Span),
+    /// A variable that the evaluation context has no value for
+    UnboundVariable(String, Span),
+    /// A comma that isn't separating the arguments of a function call
+    MisplacedComma(Span),
+    /// Nothing between two commas, or between a comma and a paren, in a function call
+    EmptyArgument(Span),
+    /// A postfix stream calling a function with more arguments than there are operands
+    MissingArgument(String, Span),
+    /// A call to a function nobody has defined
+    UnknownFunction(String, Span),
+    /// A function called with the wrong number of arguments: name, what it takes, what it got
+    ArityMismatch(String, Arity, usize, Span),
+    /// A LaTeX command the LaTeX front end doesn't understand, backslash included
+    UnknownCommand(String, Span),
+    /// Something missing from the source, described by the string (e.g. `'{' after \frac`)
+    Expected(String, Span),
+    /// A function or operator `Tree::derivative` doesn't know how to differentiate (e.g. `max` or `%`)
+    NotDifferentiable(String, Span),
+    /// A result exact arithmetic can't represent because it's irrational (e.g. `2 ^ 0.5`), described by the string
+    Irrational(String, Span),
+    /// Anything else, usually coming from a user defined `function::Function`
+    Custom(String, Span),
+} impl Error {
+    /// An `Error::Custom` with an empty span, evaluation points it at the offending function call
+    pub fn custom(message: &str) -> Self {
+        Error::Custom(message.to_string(), Span::default())
+    }
+
+    /// Whether the expression couldn't be parsed at all, as opposed to parsing fine but failing to evaluate
+    pub fn is_parse_error(&self) -> bool {
+        match self {
+            Error::Lex(..)
+            | Error::UnknownCharacters(_)
+            | Error::MismatchedParen(_)
+            | Error::MissingOperand(..)
+            | Error::ExtraOperand(_)
+            | Error::EmptyExpression
+            | Error::MisplacedComma(_)
+            | Error::EmptyArgument(_)
+            | Error::MissingArgument(..)
+            | Error::UnknownCommand(..)
+            | Error::Expected(..) => true,
+            Error::DivisionByZero(_)
+            | Error::UnboundVariable(..)
+            | Error::UnknownFunction(..)
+            | Error::ArityMismatch(..)
+            | Error::NotDifferentiable(..)
+            | Error::Irrational(..)
+            | Error::Custom(..) => false,
+        }
+    }
+
+    /// Where in the source the error happened, if it happened anywhere in particular
+    pub fn span(&self) -> Option<Span> {
+        self.spans().first().copied()
+    }
+
+    /// Every region of the source the error refers to, usually just the one
+    pub fn spans(&self) -> Vec<Span> {
+        match self {
+            Error::Lex(_, span)
+            | Error::MismatchedParen(span)
+            | Error::MissingOperand(_, span)
+            | Error::ExtraOperand(span)
+            | Error::DivisionByZero(span)
+            | Error::UnboundVariable(_, span)
+            | Error::MisplacedComma(span)
+            | Error::EmptyArgument(span)
+            | Error::MissingArgument(_, span)
+            | Error::UnknownFunction(_, span)
+            | Error::ArityMismatch(_, _, _, span)
+            | Error::UnknownCommand(_, span)
+            | Error::Expected(_, span)
+            | Error::NotDifferentiable(_, span)
+            | Error::Irrational(_, span)
+            | Error::Custom(_, span) => vec![*span],
+            Error::UnknownCharacters(chars) => chars.iter().map(|&(_, span)| span).collect(),
+            Error::EmptyExpression => vec![],
+        }
+    }
+} impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        match self {
+            Error::Lex(literal, _) => write!(f, "Unable to parse literal {:?}", literal),
+            Error::UnknownCharacters(chars) => {
+                let list = chars.iter()
+                            .map(|(c, span)| format!("{:?} at {}", c, span.start))
+                            .collect::<Vec<String>>()
+                            .join(", ");
+                write!(f, "Unknown character{} {}", if chars.len() == 1 { "" } else { "s" }, list)
+            },
+            Error::MismatchedParen(_) => write!(f, "Mismatched parenthesis"),
+            Error::MissingOperand(op, _) => write!(f, "Missing operand for operator '{}'", op),
+            Error::ExtraOperand(_) => write!(f, "Too many operands, expected an operator"),
+            Error::EmptyExpression => write!(f, "Empty expression"),
+            Error::DivisionByZero(_) => write!(f, "Divide by zero"),
+            Error::UnboundVariable(name, _) => write!(f, "Unbound variable '{}'", name),
+            Error::MisplacedComma(_) => write!(f, "Comma outside of a function call"),
+            Error::EmptyArgument(_) => write!(f, "Missing function argument"),
+            Error::MissingArgument(name, _) => write!(f, "Not enough arguments for function '{}'", name),
+            Error::UnknownFunction(name, _) => write!(f, "Unknown function '{}'", name),
+            Error::ArityMismatch(name, expected, found, _) => {
+                write!(
+                    f,
+                    "Function '{}' takes {} argument{}, but was called with {}",
+                    name,
+                    expected,
+                    if *expected == Arity::Fixed(1) { "" } else { "s" },
+                    found,
+                )
+            },
+            Error::UnknownCommand(command, _) => write!(f, "Unknown command '{}'", command),
+            Error::Expected(what, _) => write!(f, "Expected {}", what),
+            Error::NotDifferentiable(name, _) if is_identifier(name) => write!(f, "Function '{}' can't be differentiated", name),
+            Error::NotDifferentiable(symbol, _) => write!(f, "Operator '{}' can't be differentiated", symbol),
+            Error::Irrational(what, _) => write!(f, "{} is irrational, so it has no exact value", what),
+            Error::Custom(message, _) => write!(f, "{}", message),
+        }
+    }
+} impl std::error::Error for Error {}
+
+pub type Result<T> = std::result::Result<T, Error>;
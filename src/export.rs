@@ -0,0 +1,198 @@
+use crate::{
+    context::Context,
+    token::{ Token, Operator },
+    tree::{ Node, Tree },
+};
+
+use std::fmt::Write;
+
+
+/// Extra information to show next to each node
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Annotate {
+    /// What the node's subtree evaluates to, `?` if it can't be
+    pub value: bool,
+    /// The node's `Node::depth`
+    pub depth: bool,
+} impl Annotate {
+    /// Nothing but the labels
+    pub fn none() -> Self {
+        Annotate::default()
+    }
+
+    pub fn all() -> Self {
+        Annotate { value: true, depth: true }
+    }
+
+    /// Annotations for `node`, each as its own string
+    fn notes<C: Context + ?Sized>(&self, node: &Node, ctx: &C) -> Vec<String> {
+        let mut notes = Vec::new();
+        if self.value {
+            match node.evaluate(ctx) {
+                Ok(value) => notes.push(format!("= {}", value)),
+                Err(_) => notes.push("= ?".to_string()),
+            }
+        }
+        if self.depth {
+            notes.push(format!("depth {}", node.depth()));
+        }
+        notes
+    }
+}
+
+/// What a node is drawn as: its operator, value, variable name or function name
+pub fn label(node: &Node) -> String {
+    match node.token() {
+        Token::Operator(Operator::USub) => "u-".to_string(),
+        token => token.to_string(),
+    }
+}
+
+/// `tree` as a Graphviz graph, operators and functions are ellipses and everything else is a box.
+/// Subtree values are found with `ctx`
+/// ```rust
+/// use pemdrs::{ Tree, export::{ self, Annotate } };
+///
+/// let dot = export::dot(&Tree::parse("3 + 4")?, Annotate::none(), &());
+/// assert_eq!(dot, "digraph expression {
+///     node [fontname=\"monospace\"];
+///     n0 [label=\"+\", shape=ellipse];
+///     n1 [label=\"3\", shape=box];
+///     n0 -> n1;
+///     n2 [label=\"4\", shape=box];
+///     n0 -> n2;
+/// }
+/// ");
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub fn dot<C: Context + ?Sized>(tree: &Tree, annotate: Annotate, ctx: &C) -> String {
+    fn escape(s: &str) -> String {
+        s.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    fn visit<C: Context + ?Sized>(node: &Node, annotate: Annotate, ctx: &C, next_id: &mut usize, out: &mut String) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+
+        let mut lines = vec![label(node)];
+        lines.extend(annotate.notes(node, ctx));
+        let text = lines.iter().map(|line| escape(line)).collect::<Vec<String>>().join("\\n");
+        let shape = if node.is_leaf() { "box" } else { "ellipse" };
+        let _ = writeln!(out, "    n{} [label=\"{}\", shape={}];", id, text, shape);
+
+        for child in node.children() {
+            let child_id = visit(child, annotate, ctx, next_id, out);
+            let _ = writeln!(out, "    n{} -> n{};", id, child_id);
+        }
+        id
+    }
+
+    let mut out = String::from("digraph expression {\n    node [fontname=\"monospace\"];\n");
+    visit(tree.root(), annotate, ctx, &mut 0, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+/// `tree` drawn with box drawing characters, children below their parent, left to right.
+/// Subtree values are found with `ctx`
+/// ```rust
+/// use pemdrs::{ Tree, export::{ self, Annotate } };
+///
+/// let drawn = export::ascii(&Tree::parse("3 + 4 * 2")?, Annotate { value: true, depth: false }, &());
+/// assert_eq!(drawn, "\
+/// + (= 11)
+/// ├── 3 (= 3)
+/// └── * (= 8)
+///     ├── 4 (= 4)
+///     └── 2 (= 2)
+/// ");
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub fn ascii<C: Context + ?Sized>(tree: &Tree, annotate: Annotate, ctx: &C) -> String {
+    fn line<C: Context + ?Sized>(node: &Node, annotate: Annotate, ctx: &C) -> String {
+        let notes = annotate.notes(node, ctx);
+        if notes.is_empty() {
+            label(node)
+        } else {
+            format!("{} ({})", label(node), notes.join(", "))
+        }
+    }
+
+    fn visit<C: Context + ?Sized>(node: &Node, annotate: Annotate, ctx: &C, prefix: &str, out: &mut String) {
+        let children = node.children().collect::<Vec<&Node>>();
+        for (i, child) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+            let _ = writeln!(out, "{}{}{}", prefix, branch, line(child, annotate, ctx));
+            visit(child, annotate, ctx, &format!("{}{}", prefix, indent), out);
+        }
+    }
+
+    let mut out = format!("{}\n", line(tree.root(), annotate, ctx));
+    visit(tree.root(), annotate, ctx, "", &mut out);
+    out
+}
+
+impl Tree {
+    /// Graphviz graph of an expression that doesn't use any variables, see `export::dot`
+    pub fn to_dot(&self, annotate: Annotate) -> String {
+        dot(self, annotate, &())
+    }
+
+    /// Box drawing of an expression that doesn't use any variables, see `export::ascii`
+    pub fn to_ascii_tree(&self, annotate: Annotate) -> String {
+        ascii(self, annotate, &())
+    }
+}
+
+
+#[test]
+fn test_export() {
+    use std::collections::HashMap;
+
+    let tree = Tree::parse("(54/9)^2 - -x + max(1, 2)").unwrap();
+    assert_eq!(tree.to_ascii_tree(Annotate::none()), concat!(
+        "+\n",
+        "├── -\n",
+        "│   ├── ^\n",
+        "│   │   ├── /\n",
+        "│   │   │   ├── 54\n",
+        "│   │   │   └── 9\n",
+        "│   │   └── 2\n",
+        "│   └── u-\n",
+        "│       └── x\n",
+        "└── max\n",
+        "    ├── 1\n",
+        "    └── 2\n",
+    ));
+
+    // Values come from the context, anything that can't be evaluated is a ?
+    let mut env = HashMap::new();
+    env.insert("x", 3.0);
+    assert_eq!(ascii(&tree, Annotate::all(), &env).lines().take(3).collect::<Vec<&str>>(), vec![
+        "+ (= 41, depth 5)",
+        "├── - (= 39, depth 4)",
+        "│   ├── ^ (= 36, depth 3)",
+    ]);
+    assert_eq!(tree.to_ascii_tree(Annotate::all()).lines().nth(7), Some("│   └── u- (= ?, depth 2)"));
+    assert_eq!(Tree::parse("7").unwrap().to_ascii_tree(Annotate::all()), "7 (= 7, depth 1)\n");
+
+    let dot = Tree::parse("-2^x").unwrap().to_dot(Annotate { value: false, depth: true });
+    assert_eq!(dot, concat!(
+        "digraph expression {\n",
+        "    node [fontname=\"monospace\"];\n",
+        "    n0 [label=\"^\\ndepth 3\", shape=ellipse];\n",
+        "    n1 [label=\"u-\\ndepth 2\", shape=ellipse];\n",
+        "    n2 [label=\"2\\ndepth 1\", shape=box];\n",
+        "    n1 -> n2;\n",
+        "    n0 -> n1;\n",
+        "    n3 [label=\"x\\ndepth 1\", shape=box];\n",
+        "    n0 -> n3;\n",
+        "}\n",
+    ));
+
+    // One node and one edge per node in the tree
+    let dot = tree.to_dot(Annotate::all());
+    assert_eq!(dot.matches("shape=").count(), tree.pre_order().count());
+    assert_eq!(dot.matches(" -> ").count(), tree.pre_order().count() - 1);
+}
@@ -0,0 +1,286 @@
+use crate::{
+    token::{ Token, Operator, OperatorAssociativity, precedence },
+    tree::{ Node, Tree },
+};
+
+
+/// How tightly a node holds together when typeset. Fractions and function calls are drawn as a single unit,
+/// so like values and variables they never need parentheses
+fn binding(node: &Node) -> u32 {
+    match node.token() {
+        Token::Operator(Operator::Div) | Token::Operator(Operator::IntDiv) => u32::MAX,
+        Token::Operator(_) => precedence(node.token()),
+        Token::Value(v) if v.is_sign_negative() && !v.is_nan() => precedence(&Token::Operator(Operator::USub)),
+        _ => u32::MAX,
+    }
+}
+
+/// Whether the left and right operands of `node` need parentheses, the same rules as `Display`
+/// except fractions and exponents group their operands without them. Bases of powers are wrapped unless
+/// they're a positive value, a variable or a call, since `-2^{2}` reads as `-(2^2)` no matter what the parser thinks
+fn parenthesized(node: &Node) -> (bool, bool) {
+    let (op, right) = match (node.token(), node.right()) {
+        (&Token::Operator(op), Some(right)) => (op, right),
+        _ => return (false, false),
+    };
+    let p = precedence(node.token());
+
+    match (op, node.left()) {
+        (Operator::Div, _) | (Operator::IntDiv, _) => (false, false),
+        (Operator::Pow, Some(left)) => (matches!(left.token(), Token::Operator(_)) || binding(left) != u32::MAX, false),
+        (_, None) => (false, binding(right) < p),
+        (_, Some(left)) => match OperatorAssociativity::from(node.token()) {
+            OperatorAssociativity::Left => (binding(left) < p, binding(right) <= p),
+            OperatorAssociativity::Right => (binding(left) <= p, binding(right) < p),
+        },
+    }
+}
+
+/// Functions with their own LaTeX command
+pub(crate) const LATEX_FUNCTIONS: &[(&str, &str)] = &[
+    ("sin", "\\sin"), ("cos", "\\cos"), ("tan", "\\tan"),
+    ("asin", "\\arcsin"), ("acos", "\\arccos"), ("atan", "\\arctan"),
+    ("sinh", "\\sinh"), ("cosh", "\\cosh"), ("tanh", "\\tanh"),
+    ("exp", "\\exp"), ("ln", "\\ln"), ("log", "\\log"),
+    ("min", "\\min"), ("max", "\\max"),
+];
+
+/// Constants with their own symbol, as a LaTeX command and as the character MathML wants
+pub(crate) const SYMBOLS: &[(&str, &str, &str)] = &[
+    ("pi", "\\pi", "\u{3c0}"),
+    ("tau", "\\tau", "\u{3c4}"),
+    ("inf", "\\infty", "\u{221e}"),
+];
+
+fn latex_node(node: &Node) -> String {
+    let (left_parens, right_parens) = parenthesized(node);
+    let wrap = |node: &Node, parens: bool| {
+        if parens {
+            format!("\\left({}\\right)", latex_node(node))
+        } else {
+            latex_node(node)
+        }
+    };
+
+    match node.token() {
+        Token::Value(v) if v.is_nan() => "\\mathrm{NaN}".to_string(),
+        Token::Value(v) if v.is_infinite() => format!("{}\\infty", if *v < 0.0 { "-" } else { "" }),
+        Token::Value(v) => v.to_string(),
+        Token::Variable(name) => match SYMBOLS.iter().find(|&&(n, ..)| n == name) {
+            Some(&(_, command, _)) => command.to_string(),
+            None if name.chars().count() == 1 => name.clone(),
+            None => format!("\\mathrm{{{}}}", name.replace('_', "\\_")),
+        },
+        Token::Function(name, _) => {
+            let args = node.args().iter().map(latex_node).collect::<Vec<String>>().join(", ");
+            match (name.as_str(), node.args().len()) {
+                ("sqrt", 1) => format!("\\sqrt{{{}}}", args),
+                ("cbrt", 1) => format!("\\sqrt[3]{{{}}}", args),
+                ("abs", 1) => format!("\\left|{}\\right|", args),
+                _ => {
+                    let command = match LATEX_FUNCTIONS.iter().find(|&&(n, _)| n == name) {
+                        Some(&(_, command)) => command.to_string(),
+                        None => format!("\\operatorname{{{}}}", name.replace('_', "\\_")),
+                    };
+                    format!("{}\\left({}\\right)", command, args)
+                },
+            }
+        },
+        &Token::Operator(op) => {
+            let right = node.right().expect("Something went wrong! (format operator node with no right child)");
+            let left = match (op, node.left()) {
+                (Operator::Factorial, _) => return format!("{}!", wrap(right, right_parens)),
+                (_, Some(left)) => left,
+                (_, None) => return format!("-{}", wrap(right, right_parens)),
+            };
+            match op {
+                Operator::Div => format!("\\frac{{{}}}{{{}}}", latex_node(left), latex_node(right)),
+                Operator::IntDiv => format!("\\left\\lfloor\\frac{{{}}}{{{}}}\\right\\rfloor", latex_node(left), latex_node(right)),
+                Operator::Pow => format!("{}^{{{}}}", wrap(left, left_parens), latex_node(right)),
+                _ => {
+                    let symbol = match op {
+                        Operator::Add => "+",
+                        Operator::Sub => "-",
+                        Operator::Mod => "\\bmod",
+                        _ => "\\cdot",
+                    };
+                    format!("{} {} {}", wrap(left, left_parens), symbol, wrap(right, right_parens))
+                },
+            }
+        },
+        Token::Paren(_) | Token::Comma => unreachable!(),
+    }
+}
+
+/// `tree` as LaTeX math, without the surrounding `$`s. Division is a `\frac` (floored for `//`), powers are superscripts,
+/// and parentheses only show up where they're needed
+/// ```rust
+/// use pemdrs::{ Tree, format };
+///
+/// let tree = Tree::parse("(54/9)^2-4*7+sqrt(x)")?;
+/// assert_eq!(format::latex(&tree), "\\left(\\frac{54}{9}\\right)^{2} - 4 \\cdot 7 + \\sqrt{x}");
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub fn latex(tree: &Tree) -> String {
+    latex_node(tree.root())
+}
+
+/// Escape the characters that mean something in XML
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn mathml_node(node: &Node) -> String {
+    fn fenced(inner: &str, open: &str, close: &str) -> String {
+        format!("<mrow><mo>{}</mo>{}<mo>{}</mo></mrow>", open, inner, close)
+    }
+
+    let (left_parens, right_parens) = parenthesized(node);
+    let wrap = |node: &Node, parens: bool| {
+        if parens {
+            fenced(&mathml_node(node), "(", ")")
+        } else {
+            mathml_node(node)
+        }
+    };
+
+    match node.token() {
+        Token::Value(v) if v.is_nan() => "<mi>NaN</mi>".to_string(),
+        Token::Value(v) if v.is_sign_negative() => format!("<mrow><mo>&#x2212;</mo>{}</mrow>", mathml_node(&Node::new(Token::Value(-v), node.span()))),
+        Token::Value(v) if v.is_infinite() => "<mi>&#x221e;</mi>".to_string(),
+        Token::Value(v) => format!("<mn>{}</mn>", v),
+        Token::Variable(name) => match SYMBOLS.iter().find(|&&(n, ..)| n == name) {
+            Some(&(_, _, symbol)) => format!("<mi>{}</mi>", symbol),
+            None => format!("<mi>{}</mi>", xml_escape(name)),
+        },
+        Token::Function(name, _) => {
+            let args = node.args().iter().map(mathml_node).collect::<Vec<String>>();
+            match (name.as_str(), args.len()) {
+                ("sqrt", 1) => format!("<msqrt>{}</msqrt>", args[0]),
+                ("cbrt", 1) => format!("<mroot>{}<mn>3</mn></mroot>", args[0]),
+                ("abs", 1) => fenced(&args[0], "|", "|"),
+                _ => {
+                    // The invisible function application operator, so screen readers know it's a call
+                    let args = fenced(&args.join("<mo>,</mo>"), "(", ")");
+                    format!("<mrow><mi>{}</mi><mo>&#x2061;</mo>{}</mrow>", xml_escape(name), args)
+                },
+            }
+        },
+        &Token::Operator(op) => {
+            let right = node.right().expect("Something went wrong! (format operator node with no right child)");
+            let left = match (op, node.left()) {
+                (Operator::Factorial, _) => return format!("<mrow>{}<mo>!</mo></mrow>", wrap(right, right_parens)),
+                (_, Some(left)) => left,
+                (_, None) => return format!("<mrow><mo>&#x2212;</mo>{}</mrow>", wrap(right, right_parens)),
+            };
+            match op {
+                Operator::Div => format!("<mfrac>{}{}</mfrac>", mathml_node(left), mathml_node(right)),
+                Operator::IntDiv => fenced(&format!("<mfrac>{}{}</mfrac>", mathml_node(left), mathml_node(right)), "&#x230a;", "&#x230b;"),
+                Operator::Pow => format!("<msup>{}{}</msup>", wrap(left, left_parens), mathml_node(right)),
+                _ => {
+                    let symbol = match op {
+                        Operator::Add => "+",
+                        Operator::Sub => "&#x2212;",
+                        Operator::Mod => "mod",
+                        _ => "&#x22c5;",
+                    };
+                    format!("<mrow>{}<mo>{}</mo>{}</mrow>", wrap(left, left_parens), symbol, wrap(right, right_parens))
+                },
+            }
+        },
+        Token::Paren(_) | Token::Comma => unreachable!(),
+    }
+}
+
+/// `tree` as presentation MathML, a whole `<math>` element. Parenthesized the same way as `latex`
+/// ```rust
+/// use pemdrs::{ Tree, format };
+///
+/// let tree = Tree::parse("1/2 + x^2")?;
+/// assert_eq!(
+///     format::mathml(&tree),
+///     "<math><mrow><mfrac><mn>1</mn><mn>2</mn></mfrac><mo>+</mo><msup><mi>x</mi><mn>2</mn></msup></mrow></math>",
+/// );
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub fn mathml(tree: &Tree) -> String {
+    format!("<math>{}</math>", mathml_node(tree.root()))
+}
+
+impl Tree {
+    /// See `format::latex`
+    pub fn to_latex(&self) -> String {
+        latex(self)
+    }
+
+    /// See `format::mathml`
+    pub fn to_mathml(&self) -> String {
+        mathml(self)
+    }
+}
+
+
+#[test]
+fn test_latex() {
+    let cases = vec![
+        ("1+2*3", "1 + 2 \\cdot 3"),
+        ("(1+2)*3", "\\left(1 + 2\\right) \\cdot 3"),
+        ("1-(2-3)", "1 - \\left(2 - 3\\right)"),
+        ("(1-2)-3", "1 - 2 - 3"),
+        ("(1+2)/(3-x)", "\\frac{1 + 2}{3 - x}"),
+        ("1/2/3", "\\frac{\\frac{1}{2}}{3}"),
+        ("2*(3/4)", "2 \\cdot \\frac{3}{4}"),
+        ("2^3^4", "2^{3^{4}}"),
+        ("(2^3)^4", "\\left(2^{3}\\right)^{4}"),
+        ("2^(1+x)", "2^{1 + x}"),
+        ("(1/2)^2", "\\left(\\frac{1}{2}\\right)^{2}"),
+        ("-2^2", "\\left(-2\\right)^{2}"),
+        ("-(2^2)", "-\\left(2^{2}\\right)"),
+        ("-(x+1)", "-\\left(x + 1\\right)"),
+        ("-(1/x)", "-\\frac{1}{x}"),
+        ("3 - -x", "3 - -x"),
+        ("sqrt(x^2+1)", "\\sqrt{x^{2} + 1}"),
+        ("cbrt(8) + abs(-x)", "\\sqrt[3]{8} + \\left|-x\\right|"),
+        ("sin(x)^2 + max(1, y)", "\\sin\\left(x\\right)^{2} + \\max\\left(1, y\\right)"),
+        ("floor(2.5)", "\\operatorname{floor}\\left(2.5\\right)"),
+        ("2*pi*rate_of_change", "2 \\cdot \\pi \\cdot \\mathrm{rate\\_of\\_change}"),
+        ("inf", "\\infty"),
+        ("7 // 2 + 1", "\\left\\lfloor\\frac{7}{2}\\right\\rfloor + 1"),
+        ("x % 3 * 2", "x \\bmod 3 \\cdot 2"),
+        ("(n+1)! / n!", "\\frac{\\left(n + 1\\right)!}{n!}"),
+        ("-3!", "-3!"),
+        ("(-3)!", "\\left(-3\\right)!"),
+    ];
+
+    for (input, expected) in cases {
+        assert_eq!(Tree::parse(input).unwrap().to_latex(), expected, "formatting {}", input);
+    }
+}
+
+#[test]
+fn test_mathml() {
+    let mathml = |input: &str| {
+        let formatted = Tree::parse(input).unwrap().to_mathml();
+        formatted["<math>".len()..formatted.len() - "</math>".len()].to_string()
+    };
+
+    assert_eq!(mathml("(1+2)*3"), "<mrow><mrow><mo>(</mo><mrow><mn>1</mn><mo>+</mo><mn>2</mn></mrow><mo>)</mo></mrow><mo>&#x22c5;</mo><mn>3</mn></mrow>");
+    assert_eq!(mathml("-2^2"), "<msup><mrow><mo>(</mo><mrow><mo>&#x2212;</mo><mn>2</mn></mrow><mo>)</mo></mrow><mn>2</mn></msup>");
+    assert_eq!(mathml("x - 1/y"), "<mrow><mi>x</mi><mo>&#x2212;</mo><mfrac><mn>1</mn><mi>y</mi></mfrac></mrow>");
+    assert_eq!(mathml("sqrt(2) * pi"), "<mrow><msqrt><mn>2</mn></msqrt><mo>&#x22c5;</mo><mi>\u{3c0}</mi></mrow>");
+    assert_eq!(mathml("cbrt(x)"), "<mroot><mi>x</mi><mn>3</mn></mroot>");
+    assert_eq!(mathml("abs(x)"), "<mrow><mo>|</mo><mi>x</mi><mo>|</mo></mrow>");
+    assert_eq!(mathml("max(1, x)"), "<mrow><mi>max</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mn>1</mn><mo>,</mo><mi>x</mi><mo>)</mo></mrow></mrow>");
+    assert_eq!(mathml("7 // x"), "<mrow><mo>&#x230a;</mo><mfrac><mn>7</mn><mi>x</mi></mfrac><mo>&#x230b;</mo></mrow>");
+    assert_eq!(mathml("n! % 3"), "<mrow><mrow><mi>n</mi><mo>!</mo></mrow><mo>mod</mo><mn>3</mn></mrow>");
+    assert_eq!(mathml("--x"), "<mrow><mo>&#x2212;</mo><mrow><mo>&#x2212;</mo><mi>x</mi></mrow></mrow>");
+
+    // Same parentheses as LaTeX
+    for input in ["1-(2-3)", "(2^3)^4", "-(x+1)", "2^(1+x)", "(1/2)^2"].iter() {
+        assert_eq!(
+            mathml(input).matches("<mo>(</mo>").count(),
+            Tree::parse(input).unwrap().to_latex().matches("\\left(").count(),
+            "parentheses in {}", input,
+        );
+    }
+}
@@ -0,0 +1,281 @@
+use crate::error::Result;
+
+use std::{
+    collections::HashMap,
+    fmt::{ Display, Formatter, Result as fmt_Result },
+};
+
+
+/// How many arguments a function takes
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Arity {
+    /// Exactly this many
+    Fixed(usize),
+    /// At least this many
+    Variadic(usize),
+} impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Fixed(n) => count == n,
+            Arity::Variadic(min) => count >= min,
+        }
+    }
+} impl Display for Arity {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        match self {
+            Arity::Fixed(n) => write!(f, "{}", n),
+            Arity::Variadic(min) => write!(f, "at least {}", min),
+        }
+    }
+}
+
+/// Anything that can be called from an expression
+/// ```rust
+/// use pemdrs::{ Error, Result, Tree };
+/// use pemdrs::context::Environment;
+/// use pemdrs::function::{ Arity, Function };
+///
+/// struct Tax;
+/// impl Function for Tax {
+///     fn name(&self) -> &str { "tax" }
+///     fn arity(&self) -> Arity { Arity::Fixed(2) }
+///     fn eval(&self, args: &[f64]) -> Result<f64> {
+///         match args[1] as u32 {
+///             1 => Ok(args[0] * 0.2),
+///             2 => Ok(args[0] * 0.1),
+///             _ => Err(Error::custom("unknown region")),
+///         }
+///     }
+/// }
+///
+/// let mut env = Environment::new();
+/// env.functions.register(Tax);
+/// env.functions.register_fn("double", Arity::Fixed(1), |args| Ok(args[0] * 2.0));
+///
+/// assert_eq!(Tree::parse("double(tax(100, 2))")?.evaluate_with(&env)?, 20.0);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub trait Function: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn arity(&self) -> Arity;
+
+    /// Call the function, `args` has already been checked against `self.arity()`
+    fn eval(&self, args: &[f64]) -> Result<f64>;
+
+    /// `Some` if this is one of the builtins, which exact numbers know how to call without going through `f64`
+    fn as_builtin(&self) -> Option<&Builtin> {
+        None
+    }
+}
+
+/// A function that ships with the crate, see `builtin`
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    f: fn(&[f64]) -> f64,
+} impl Function for Builtin {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn eval(&self, args: &[f64]) -> Result<f64> {
+        Ok((self.f)(args))
+    }
+
+    fn as_builtin(&self) -> Option<&Builtin> {
+        Some(self)
+    }
+}
+
+/// A closure along with the name and arity to call it by, see `FunctionRegistry::register_fn`
+pub struct Closure<F> {
+    name: String,
+    arity: Arity,
+    f: F,
+} impl<F> Closure<F>
+where
+    F: Fn(&[f64]) -> Result<f64> + Send + Sync,
+{
+    pub fn new(name: &str, arity: Arity, f: F) -> Self {
+        Closure { name: name.to_string(), arity, f }
+    }
+} impl<F> Function for Closure<F>
+where
+    F: Fn(&[f64]) -> Result<f64> + Send + Sync,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> Arity {
+        self.arity
+    }
+
+    fn eval(&self, args: &[f64]) -> Result<f64> {
+        (self.f)(args)
+    }
+}
+
+macro_rules! unary {
+    ($name:literal, $f:expr) => {
+        Builtin { name: $name, arity: Arity::Fixed(1), f: |args| $f(args[0]) }
+    };
+}
+
+/// Everything `builtin` knows about, math functions behave like their `f64` counterparts
+pub const BUILTINS: &[Builtin] = &[
+    unary!("sqrt",  f64::sqrt),
+    unary!("cbrt",  f64::cbrt),
+    unary!("abs",   f64::abs),
+    unary!("exp",   f64::exp),
+    unary!("ln",    f64::ln),
+    unary!("log",   f64::log10),
+    unary!("log2",  f64::log2),
+    unary!("sin",   f64::sin),
+    unary!("cos",   f64::cos),
+    unary!("tan",   f64::tan),
+    unary!("asin",  f64::asin),
+    unary!("acos",  f64::acos),
+    unary!("atan",  f64::atan),
+    unary!("sinh",  f64::sinh),
+    unary!("cosh",  f64::cosh),
+    unary!("tanh",  f64::tanh),
+    unary!("floor", f64::floor),
+    unary!("ceil",  f64::ceil),
+    unary!("round", f64::round),
+    unary!("trunc", f64::trunc),
+    unary!("sign",  |x: f64| if x == 0.0 { 0.0 } else { x.signum() }),
+    Builtin { name: "atan2", arity: Arity::Fixed(2),    f: |args| args[0].atan2(args[1]) },
+    Builtin { name: "hypot", arity: Arity::Fixed(2),    f: |args| args[0].hypot(args[1]) },
+    Builtin { name: "min",   arity: Arity::Variadic(1), f: |args| args.iter().copied().fold(f64::INFINITY, f64::min) },
+    Builtin { name: "max",   arity: Arity::Variadic(1), f: |args| args.iter().copied().fold(f64::NEG_INFINITY, f64::max) },
+];
+
+/// Look up a built in function by name
+pub fn builtin(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|f| f.name == name)
+}
+
+/// Functions that can be called by name, starts out with the builtins
+pub struct FunctionRegistry {
+    functions: HashMap<String, Box<dyn Function>>,
+} impl FunctionRegistry {
+    /// A registry with every builtin in it
+    pub fn new() -> Self {
+        let mut registry = FunctionRegistry::empty();
+        for function in BUILTINS {
+            registry.functions.insert(function.name.to_string(), Box::new(function));
+        }
+        registry
+    }
+
+    /// A registry without even the builtins
+    pub fn empty() -> Self {
+        FunctionRegistry { functions: HashMap::new() }
+    }
+
+    /// Add `function`, returning whatever was previously registered under the same name
+    pub fn register<F: Function + 'static>(&mut self, function: F) -> Option<Box<dyn Function>> {
+        self.functions.insert(function.name().to_string(), Box::new(function))
+    }
+
+    /// Add a closure as a function
+    pub fn register_fn<F>(&mut self, name: &str, arity: Arity, f: F) -> Option<Box<dyn Function>>
+    where
+        F: Fn(&[f64]) -> Result<f64> + Send + Sync + 'static,
+    {
+        self.register(Closure::new(name, arity, f))
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Box<dyn Function>> {
+        self.functions.remove(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Function> {
+        self.functions.get(name).map(|f| f.as_ref())
+    }
+
+    /// Names of every registered function, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(|name| name.as_str())
+    }
+} impl Default for FunctionRegistry {
+    fn default() -> Self {
+        FunctionRegistry::new()
+    }
+}
+
+impl<F> Function for &F
+where
+    F: Function + ?Sized,
+{
+    fn name(&self) -> &str {
+        (**self).name()
+    }
+
+    fn arity(&self) -> Arity {
+        (**self).arity()
+    }
+
+    fn eval(&self, args: &[f64]) -> Result<f64> {
+        (**self).eval(args)
+    }
+
+    fn as_builtin(&self) -> Option<&Builtin> {
+        (**self).as_builtin()
+    }
+}
+
+
+#[test]
+fn test_arity() {
+    assert!(Arity::Fixed(2).accepts(2));
+    assert!(!Arity::Fixed(2).accepts(1));
+    assert!(!Arity::Fixed(2).accepts(3));
+
+    assert!(Arity::Variadic(1).accepts(1));
+    assert!(Arity::Variadic(1).accepts(10));
+    assert!(!Arity::Variadic(1).accepts(0));
+}
+
+#[test]
+fn test_builtins() {
+    let call = |name: &str, args: &[f64]| builtin(name).unwrap().eval(args).unwrap();
+
+    assert_eq!(call("sqrt", &[16.0]), 4.0);
+    assert_eq!(call("abs", &[-2.5]), 2.5);
+    assert_eq!(call("log", &[1000.0]), 3.0);
+    assert_eq!(call("min", &[3.0, -1.0, 2.0]), -1.0);
+    assert_eq!(call("max", &[3.0]), 3.0);
+    assert_eq!(call("sign", &[0.0]), 0.0);
+    assert_eq!(call("hypot", &[3.0, 4.0]), 5.0);
+
+    assert!(builtin("nope").is_none());
+}
+
+#[test]
+fn test_registry() {
+    use crate::error::Error;
+
+    let mut registry = FunctionRegistry::new();
+    assert!(registry.get("sqrt").is_some());
+    assert!(FunctionRegistry::empty().get("sqrt").is_none());
+
+    assert!(registry.register_fn("tax", Arity::Fixed(2), |args| Ok(args[0] * args[1])).is_none());
+    let tax = registry.get("tax").unwrap();
+    assert_eq!(tax.arity(), Arity::Fixed(2));
+    assert_eq!(tax.eval(&[100.0, 0.2]), Ok(20.0));
+
+    // Registering under an existing name replaces it
+    let old = registry.register_fn("sqrt", Arity::Fixed(1), |_| Err(Error::custom("no roots allowed")));
+    assert_eq!(old.unwrap().eval(&[4.0]), Ok(2.0));
+    assert_eq!(registry.get("sqrt").unwrap().eval(&[4.0]), Err(Error::custom("no roots allowed")));
+
+    assert!(registry.remove("tax").is_some());
+    assert!(registry.get("tax").is_none());
+}
@@ -0,0 +1,304 @@
+use crate::{
+    error::{ Error, Result },
+    token::{ Token, Operator, Span },
+    tree::{ Node, Tree },
+};
+
+use std::{
+    fmt::{ Display, Formatter, Result as fmt_Result },
+    str::FromStr,
+};
+
+
+/// How many tries `Generator::problem` gets before giving up on the constraints
+const ATTEMPTS: usize = 10_000;
+
+/// SplitMix64, small and plenty random enough for making up homework
+#[derive(Clone, Debug)]
+pub(crate) struct Rng {
+    state: u64,
+} impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform-ish in `0..n`, `n` can't be 0
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Uniform-ish in `low..=high`
+    pub(crate) fn range(&mut self, low: i64, high: i64) -> i64 {
+        low + self.below((high - low) as u64 + 1) as i64
+    }
+
+    pub(crate) fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len() as u64) as usize]
+    }
+}
+
+/// Preset `Constraints`, see `Constraints::new`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+} impl FromStr for Difficulty {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "easy" => Ok(Difficulty::Easy),
+            "medium" => Ok(Difficulty::Medium),
+            "hard" => Ok(Difficulty::Hard),
+            _ => Err(Error::custom(&format!("Unknown difficulty {:?}, expected easy, medium or hard", s))),
+        }
+    }
+} impl Display for Difficulty {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        let name = match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a generated problem is allowed to look like
+#[derive(Clone, PartialEq, Debug)]
+pub struct Constraints {
+    /// Upper bound on the problem's `Tree::depth`
+    pub max_depth: u16,
+    /// Operators to build problems out of. Including `USub` allows negative numbers
+    pub operators: Vec<Operator>,
+    /// Literals are between 1 and this, dividends can go over so they divide evenly
+    pub max_operand: u32,
+    /// Neither the answer nor anything computed along the way can be bigger than this (or smaller than its negative)
+    pub max_magnitude: u64,
+    /// Whether the answer, and everything computed along the way, has to be a whole number
+    pub integer_only: bool,
+    /// Whether every division has to come out even
+    pub exact_division: bool,
+} impl Constraints {
+    /// Sensible constraints for a worksheet at `difficulty`
+    pub fn new(difficulty: Difficulty) -> Self {
+        use Operator::*;
+
+        let (max_depth, operators, max_operand, max_magnitude) = match difficulty {
+            Difficulty::Easy => (3, vec![Add, Sub, Mul], 10, 100),
+            Difficulty::Medium => (4, vec![Add, Sub, Mul, Div], 12, 1_000),
+            Difficulty::Hard => (5, vec![Add, Sub, Mul, Div, Pow, USub], 20, 10_000),
+        };
+        Constraints {
+            max_depth,
+            operators,
+            max_operand,
+            max_magnitude,
+            integer_only: true,
+            exact_division: true,
+        }
+    }
+} impl Default for Constraints {
+    fn default() -> Self {
+        Constraints::new(Difficulty::Medium)
+    }
+}
+
+/// A generated expression along with what it evaluates to
+#[derive(Clone, PartialEq, Debug)]
+pub struct Problem {
+    pub tree: Tree,
+    pub answer: f64,
+} impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{} = {}", self.tree, self.answer)
+    }
+}
+
+/// Makes up random order of operations problems, the same seed always makes the same problems
+/// ```rust
+/// use pemdrs::generate::{ Constraints, Difficulty, Generator };
+///
+/// let mut generator = Generator::new(Constraints::new(Difficulty::Hard), 42);
+/// let problem = generator.problem()?;
+/// assert!(problem.tree.depth() <= 5);
+/// assert_eq!(problem.tree.evaluate()?, problem.answer);
+/// assert_eq!(problem.answer.fract(), 0.0);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Generator {
+    constraints: Constraints,
+    rng: Rng,
+} impl Generator {
+    pub fn new(constraints: Constraints, seed: u64) -> Self {
+        Generator { constraints, rng: Rng::new(seed) }
+    }
+
+    pub fn constraints(&self) -> &Constraints {
+        &self.constraints
+    }
+
+    /// A new problem, errors if the constraints can't be satisfied
+    pub fn problem(&mut self) -> Result<Problem> {
+        if self.constraints.max_depth == 0 {
+            return Err(Error::custom("Problems need a max depth of at least 1"));
+        }
+        if self.binary_operators().is_empty() {
+            return Err(Error::custom("Problems need at least one operator other than negation"));
+        }
+
+        for _ in 0..ATTEMPTS {
+            let (root, answer) = match self.expression(self.constraints.max_depth, true) {
+                Some(generated) => generated,
+                None => continue,
+            };
+            // Parse what students will see, so spans point into it and negative literals become negations
+            let tree = Tree::parse(&root.to_string())?;
+            if tree.depth() <= self.constraints.max_depth && tree.evaluate() == Ok(answer) {
+                return Ok(Problem { tree, answer });
+            }
+        }
+
+        Err(Error::custom("Couldn't generate a problem satisfying the constraints"))
+    }
+
+    /// `count` new problems
+    pub fn worksheet(&mut self, count: usize) -> Result<Vec<Problem>> {
+        (0..count).map(|_| self.problem()).collect()
+    }
+
+    fn binary_operators(&self) -> Vec<Operator> {
+        self.constraints.operators.iter()
+            .copied()
+            .filter(|op| !op.is_unary())
+            .collect()
+    }
+
+    fn negatives(&self) -> bool {
+        self.constraints.operators.contains(&Operator::USub)
+    }
+
+    fn literal(value: f64) -> Node {
+        Node::new(Token::Value(value), Span::default())
+    }
+
+    /// A random literal, negative ones take a level of depth once they're parsed as a negation
+    fn operand(&mut self, depth: u16) -> (Node, f64) {
+        let mut value = self.rng.range(1, self.constraints.max_operand.max(1) as i64) as f64;
+        if depth >= 2 && self.negatives() && self.rng.below(3) == 0 {
+            value = -value;
+        }
+        (Generator::literal(value), value)
+    }
+
+    /// A random expression at most `depth` deep, exactly that deep if `full`.
+    /// `None` if it broke one of the constraints along the way
+    fn expression(&mut self, depth: u16, full: bool) -> Option<(Node, f64)> {
+        if depth <= 1 || (!full && self.rng.below(3) == 0) {
+            return Some(self.operand(depth));
+        }
+
+        let op = *self.rng.choose(&self.binary_operators());
+        let (mut left, right) = if op == Operator::Pow {
+            // Small literal exponents keep the answers reasonable
+            let exponent = self.rng.range(2, 3) as f64;
+            (self.expression(depth - 1, full)?, (Generator::literal(exponent), exponent))
+        } else if self.rng.below(2) == 0 {
+            (self.expression(depth - 1, full)?, self.expression(depth - 1, false)?)
+        } else {
+            (self.expression(depth - 1, false)?, self.expression(depth - 1, full)?)
+        };
+
+        if op == Operator::Div && self.constraints.exact_division && left.1 % right.1 != 0.0 {
+            // Swap a literal dividend for a multiple of the divisor rather than starting over
+            if !left.0.is_leaf() || right.1 == 0.0 {
+                return None;
+            }
+            let multiple = self.rng.range(1, (self.constraints.max_operand as i64 / right.1.abs() as i64).max(1)) as f64;
+            let sign = if left.1 < 0.0 { -1.0 } else { 1.0 };
+            let value = sign * (multiple * right.1).abs();
+            left = (Generator::literal(value), value);
+        }
+
+        let value = op.evaluate(left.1, right.1).ok()?;
+        if !value.is_finite()
+            || value.abs() > self.constraints.max_magnitude as f64
+            || (self.constraints.integer_only && value.fract() != 0.0)
+        {
+            return None;
+        }
+
+        let mut node = Node::new(Token::Operator(op), Span::default());
+        node.left = Some(Box::new(left.0));
+        node.right = Some(Box::new(right.0));
+        Some((node, value))
+    }
+}
+
+
+#[test]
+fn test_generator() {
+    let values = |tree: &Tree| tree.post_order().map(|node| node.evaluate(&()).unwrap()).collect::<Vec<f64>>();
+
+    for &difficulty in [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard].iter() {
+        let constraints = Constraints::new(difficulty);
+        let problems = Generator::new(constraints.clone(), 7).worksheet(200).unwrap();
+
+        // Same seed, same problems
+        assert_eq!(Generator::new(constraints.clone(), 7).worksheet(200).unwrap(), problems);
+        assert_ne!(Generator::new(constraints.clone(), 8).worksheet(200).unwrap(), problems);
+
+        for problem in problems.iter() {
+            let tree = &problem.tree;
+            assert!(tree.depth() <= constraints.max_depth, "{} is too deep", tree);
+            assert_eq!(tree.evaluate(), Ok(problem.answer));
+            assert!(values(tree).iter().all(|v| v.fract() == 0.0 && v.abs() <= constraints.max_magnitude as f64), "{}", problem);
+
+            for node in tree.pre_order() {
+                match *node.token() {
+                    Token::Operator(op) => assert!(constraints.operators.contains(&op), "{} uses {}", tree, op),
+                    Token::Value(v) => assert!(v >= 1.0, "{}", tree),
+                    _ => panic!("{} has a {:?}", tree, node.token()),
+                }
+            }
+        }
+    }
+
+    // Every operator shows up eventually
+    let problems = Generator::new(Constraints::new(Difficulty::Hard), 1).worksheet(100).unwrap();
+    for op in Constraints::new(Difficulty::Hard).operators {
+        let token = Token::Operator(op);
+        assert!(problems.iter().any(|p| p.tree.pre_order().any(|node| *node.token() == token)), "no {}", op);
+    }
+
+    // Allowing remainders
+    let constraints = Constraints {
+        operators: vec![Operator::Div],
+        integer_only: false,
+        exact_division: false,
+        ..Constraints::default()
+    };
+    let problems = Generator::new(constraints, 3).worksheet(50).unwrap();
+    assert!(problems.iter().any(|p| p.answer.fract() != 0.0));
+
+    assert_eq!(Generator::new(Constraints { max_depth: 1, ..Constraints::default() }, 0).problem().unwrap().tree.depth(), 1);
+    assert!(Generator::new(Constraints { max_depth: 0, ..Constraints::default() }, 0).problem().is_err());
+    assert!(Generator::new(Constraints { operators: vec![Operator::USub], ..Constraints::default() }, 0).problem().is_err());
+
+    // Adding positive numbers never gets that small
+    let impossible = Constraints { operators: vec![Operator::Add], max_magnitude: 1, ..Constraints::default() };
+    assert!(Generator::new(impossible, 0).problem().is_err());
+
+    assert_eq!("Hard".parse::<Difficulty>(), Ok(Difficulty::Hard));
+    assert!("extreme".parse::<Difficulty>().is_err());
+}
@@ -0,0 +1,200 @@
+use crate::{
+    error::{ Error, Result },
+    notation::{ operator, operator_word },
+    token::{ Token, Span, is_identifier },
+    tree::{ Node, Tree },
+};
+
+use serde::{ Serialize, Serializer, Deserialize, Deserializer };
+
+use std::convert::TryFrom;
+
+
+/// A value as JSON, which doesn't have infinities or NaN, so those are the strings `inf`, `-inf` and `nan`.
+/// Only the `f64` is kept, not how it was written, so exact numbers read back no more than an `f64` holds
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Finite(f64),
+    NonFinite(String),
+}
+
+/// What a node is, keyed by its kind, e.g. `{"operator": "+"}`
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+    Value(Number),
+    Variable(String),
+    /// Written like `notation::words`, so unary minus is `~`
+    Operator(String),
+    Function(String),
+}
+
+/// How a `Node` is laid out as JSON
+#[derive(Serialize, Deserialize)]
+struct Repr {
+    #[serde(flatten)]
+    kind: Kind,
+    /// Operands or function arguments, left to right
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    children: Vec<Repr>,
+    #[serde(default)]
+    span: Span,
+}
+
+impl From<&Node> for Repr {
+    fn from(node: &Node) -> Self {
+        let kind = match node.token() {
+            &Token::Value(v) if v.is_finite() => Kind::Value(Number::Finite(v)),
+            Token::Value(v) => Kind::Value(Number::NonFinite(v.to_string().to_lowercase())),
+            Token::Variable(name) => Kind::Variable(name.clone()),
+            &Token::Operator(op) => Kind::Operator(operator_word(op)),
+            Token::Function(name, _) => Kind::Function(name.clone()),
+            Token::Paren(_) | Token::Comma => unreachable!(),
+        };
+
+        Repr {
+            kind,
+            children: node.children().map(Repr::from).collect(),
+            span: node.span(),
+        }
+    }
+}
+
+impl TryFrom<Repr> for Node {
+    type Error = Error;
+
+    /// Check `repr` describes a valid node, operators need exactly as many operands as they take
+    fn try_from(repr: Repr) -> Result<Node> {
+        let span = repr.span;
+        let token = match repr.kind {
+            Kind::Value(Number::Finite(v)) => Token::Value(v),
+            Kind::Value(Number::NonFinite(v)) => match v.as_str() {
+                "nan" => Token::Value(f64::NAN),
+                "inf" => Token::Value(f64::INFINITY),
+                "-inf" => Token::Value(f64::NEG_INFINITY),
+                _ => return Err(Error::Lex(v, span)),
+            },
+            Kind::Variable(name) if is_identifier(&name) => Token::Variable(name),
+            Kind::Operator(symbol) => match operator(&symbol) {
+                Some(op) => Token::Operator(op),
+                None => return Err(Error::Lex(symbol, span)),
+            },
+            Kind::Function(name) if is_identifier(&name) => Token::Function(name, 0),
+            Kind::Variable(name) | Kind::Function(name) => return Err(Error::Lex(name, span)),
+        };
+
+        let operands = repr.children.into_iter().map(Node::try_from).collect::<Result<Vec<Node>>>()?;
+        Node::with_operands(token, span, operands)
+    }
+}
+
+impl Serialize for Node {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        Repr::from(self).serialize(serializer)
+    }
+} impl<'de> Deserialize<'de> for Node {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Node::try_from(Repr::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+} impl Serialize for Tree {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.root().serialize(serializer)
+    }
+} impl<'de> Deserialize<'de> for Tree {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Ok(Tree { root: Node::deserialize(deserializer)? })
+    }
+}
+
+impl Tree {
+    /// The expression as JSON, every node keeps its span so errors can still point at the original source
+    /// ```rust
+    /// use pemdrs::Tree;
+    ///
+    /// let tree = Tree::parse("-x")?;
+    /// assert_eq!(
+    ///     tree.to_json(),
+    ///     r#"{"operator":"~","children":[{"variable":"x","span":{"start":1,"end":2}}],"span":{"start":0,"end":2}}"#,
+    /// );
+    /// assert_eq!(Tree::from_json(&tree.to_json())?, tree);
+    /// # Ok::<(), pemdrs::Error>(())
+    /// ```
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("Something went wrong! (serializing a tree to JSON)")
+    }
+
+    /// Parse JSON written by `to_json`. Operators have to get exactly as many operands as they take,
+    /// and missing spans are left empty
+    pub fn from_json(s: &str) -> Result<Self> {
+        let repr = serde_json::from_str::<Repr>(s).map_err(|e| Error::custom(&e.to_string()))?;
+        Ok(Tree { root: Node::try_from(repr)? })
+    }
+}
+
+
+#[test]
+fn test_json_round_trip() {
+    use crate::constant::Constants;
+
+    let inputs = [
+        "1+2*3", "(1+2)*3", "2^3^4", "-2^2", "-(2^2)", "3 - -x", "0.1 + 0.2", "1/3",
+        "123456789.123456789 * 2^1000", "sqrt(x^2+1)", "max(1, (2+3)*x)", "min(4) + hypot(3, 4)",
+        "-inf * nan + inf",
+    ];
+    for input in inputs.iter() {
+        let mut tree = Tree::parse(input).unwrap();
+        tree.fold_constants(&Constants::new());
+        let parsed = Tree::from_json(&tree.to_json()).unwrap();
+        // Compared as printed, JSON only keeps the value and not how it was written like S-expressions do
+        assert_eq!(parsed.to_string(), tree.to_string(), "{}", input);
+
+        // Spans survive too
+        let spans = |tree: &Tree| tree.pre_order().map(Node::span).collect::<Vec<Span>>();
+        assert_eq!(spans(&parsed), spans(&tree), "{}", input);
+    }
+
+    assert_eq!(
+        Tree::parse("2 * inf").map(|mut tree| { tree.fold_constants(&Constants::new()); tree.to_json() }),
+        Ok(concat!(
+            r#"{"operator":"*","children":["#,
+            r#"{"value":2.0,"span":{"start":0,"end":1}},"#,
+            r#"{"value":"inf","span":{"start":4,"end":7}}"#,
+            r#"],"span":{"start":0,"end":7}}"#,
+        ).to_string()),
+    );
+
+    // Spans are optional, and it works through serde directly
+    let tree: Tree = serde_json::from_str(r#"{"function":"max","children":[{"value":1},{"variable":"y"}]}"#).unwrap();
+    assert_eq!(tree, Tree::parse("max(1, y)").unwrap());
+    assert_eq!(tree.root().span(), Span::default());
+    let node: Node = serde_json::from_str(&serde_json::to_string(tree.root()).unwrap()).unwrap();
+    assert_eq!(&node, tree.root());
+}
+
+#[test]
+fn test_json_errors() {
+    use crate::token::Operator;
+
+    assert_eq!(
+        Tree::from_json(r#"{"operator":"-","children":[{"value":1}],"span":{"start":2,"end":5}}"#),
+        Err(Error::MissingOperand(Operator::Sub, Span::new(2, 5))),
+    );
+    assert_eq!(
+        Tree::from_json(r#"{"operator":"~","children":[{"value":1},{"value":2,"span":{"start":3,"end":4}}]}"#),
+        Err(Error::ExtraOperand(Span::new(3, 4))),
+    );
+    assert_eq!(
+        Tree::from_json(r#"{"value":1,"children":[{"value":2,"span":{"start":3,"end":4}}]}"#),
+        Err(Error::ExtraOperand(Span::new(3, 4))),
+    );
+    assert_eq!(Tree::from_json(r#"{"operator":"&"}"#), Err(Error::Lex("&".to_string(), Span::default())));
+    assert_eq!(Tree::from_json(r#"{"variable":"2x"}"#), Err(Error::Lex("2x".to_string(), Span::default())));
+    assert_eq!(Tree::from_json(r#"{"value":"lots"}"#), Err(Error::Lex("lots".to_string(), Span::default())));
+    assert!(matches!(Tree::from_json(r#"{"constant":"pi"}"#), Err(Error::Custom(..))));
+    assert!(matches!(Tree::from_json("[1, 2"), Err(Error::Custom(..))));
+
+    // Deserializing through serde gives the same errors as messages
+    let error = serde_json::from_str::<Tree>(r#"{"operator":"+","children":[]}"#).unwrap_err();
+    assert_eq!(error.to_string(), Error::MissingOperand(Operator::Add, Span::default()).to_string());
+}
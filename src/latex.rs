@@ -0,0 +1,465 @@
+use crate::{
+    error::{ Error, Result },
+    format::{ LATEX_FUNCTIONS, SYMBOLS },
+    token::{ Token, Operator, Paren, Span, Spanned, is_identifier, shunting_yard },
+    tree::Tree,
+};
+
+use std::convert::TryFrom;
+
+
+/// Greek letters that are just variables named after themselves
+const GREEK: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa", "lambda",
+    "mu", "nu", "xi", "rho", "sigma", "upsilon", "phi", "chi", "psi", "omega",
+];
+
+/// Commands that only change spacing
+const SPACING: &[&str] = &[",", ";", ":", "!", " ", "quad", "qquad"];
+
+/// Turns LaTeX into the same infix token stream `token::tokenize` makes, every token spanning
+/// the LaTeX it came from. Tokens that don't appear in the source (like the parentheses
+/// around a `\frac`) span the command that implied them
+struct Lexer<'a> {
+    source: &'a str,
+    /// Byte offset of the next character
+    position: usize,
+    tokens: Vec<Spanned>,
+    /// Index of the last single digit argument, like the `2` in `x^23`, which can be followed by a number
+    digit_argument: Option<usize>,
+} impl<'a> Lexer<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<(usize, char)> {
+        let c = self.peek()?;
+        let start = self.position;
+        self.position += c.len_utf8();
+        Some((start, c))
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    /// Where the end of the source is, for complaining about things that are missing
+    fn end(&self) -> Span {
+        Span::new(self.source.len(), self.source.len())
+    }
+
+    fn push(&mut self, token: Token, span: Span) {
+        self.push_spanned(Spanned::new(token, span));
+    }
+
+    /// Push the number literal from `start` to here. Two numbers in a row are an error rather than
+    /// a product, since they're more likely digit grouping like `1\,000` than `1 \cdot 000`
+    fn push_literal(&mut self, start: usize) -> Result<()> {
+        let span = Span::new(start, self.position);
+        let literal = Spanned::literal(&self.source[start..self.position], span)?;
+        let after_argument = self.digit_argument.is_some_and(|index| index + 1 == self.tokens.len());
+        if let Some(previous) = self.tokens.last().filter(|t| matches!(t.token, Token::Value(_)) && !after_argument) {
+            return Err(Error::Expected("an operator between two numbers".to_string(), previous.span.join(span)));
+        }
+        self.push_spanned(literal);
+        Ok(())
+    }
+
+    /// Push `spanned`, with a `*` in front of it if it's an operand directly following another one
+    fn push_spanned(&mut self, spanned: Spanned) {
+        let span = spanned.span;
+        let starts_operand = matches!(
+            spanned.token,
+            Token::Value(_) | Token::Variable(_) | Token::Function(..) | Token::Paren(Paren::Left)
+        );
+        let ends_operand = matches!(
+            self.tokens.last().map(|t| &t.token),
+            Some(Token::Value(_)) | Some(Token::Variable(_)) | Some(Token::Paren(Paren::Right))
+                | Some(Token::Operator(Operator::Factorial))
+        );
+        if starts_operand && ends_operand {
+            self.tokens.push(Spanned::new(Token::Operator(Operator::Mul), Span::new(span.start, span.start)));
+        }
+        self.tokens.push(spanned);
+    }
+
+    /// A `\command`, returning its name (without the backslash) and span. Names are letters, or a single other character
+    fn command(&mut self) -> Result<(String, Span)> {
+        let (start, _) = self.bump().expect("Something went wrong! (read a command at the end of the source)");
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(char::is_ascii_alphabetic) {
+            name.push(c);
+            self.bump();
+        }
+        if name.is_empty() {
+            match self.bump() {
+                Some((_, c)) => name.push(c),
+                None => return Err(Error::Expected("a command after '\\'".to_string(), Span::new(start, start + 1))),
+            }
+        }
+        Ok((name, Span::new(start, self.position)))
+    }
+
+    /// Everything up to the next `}` verbatim, for things like `\mathrm{rate}`. `\_` is unescaped
+    fn raw_group(&mut self, command: &str, span: Span) -> Result<(String, Span)> {
+        self.skip_whitespace();
+        if self.peek() != Some('{') {
+            return Err(Error::Expected(format!("'{{' after \\{}", command), span));
+        }
+        self.bump();
+        let start = self.position;
+        let end = match self.source[start..].find('}') {
+            Some(offset) => start + offset,
+            None => return Err(Error::Expected("'}'".to_string(), self.end())),
+        };
+        self.position = end + 1;
+
+        let text = self.source[start..end].replace("\\_", "_");
+        Ok((text.split_whitespace().collect(), Span::new(start, end)))
+    }
+
+    /// A `{}` group as a parenthesized expression
+    fn group(&mut self) -> Result<()> {
+        let (start, _) = self.bump().expect("Something went wrong! (read a group at the end of the source)");
+        let open = Span::new(start, start + 1);
+        self.push(Token::Paren(Paren::Left), open);
+        match self.expression(Some('}'))? {
+            Some(close) => {
+                self.push(Token::Paren(Paren::Right), close);
+                Ok(())
+            },
+            None => Err(Error::MismatchedParen(open)),
+        }
+    }
+
+    /// The argument of `^`, `\frac` or `\sqrt`: a `{}` group, or else a single digit, letter or symbol
+    fn argument(&mut self, what: &str, span: Span) -> Result<()> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.group(),
+            Some(c) if c.is_ascii_digit() => {
+                let (start, _) = self.bump().unwrap();
+                self.push_literal(start)?;
+                self.digit_argument = Some(self.tokens.len() - 1);
+                Ok(())
+            },
+            Some(c) if c.is_ascii_alphabetic() => {
+                let (start, c) = self.bump().unwrap();
+                self.push(Token::Variable(c.to_string()), Span::new(start, start + 1));
+                Ok(())
+            },
+            Some('\\') => {
+                let (name, command) = self.command()?;
+                let variable = symbol(&name).ok_or_else(|| Error::Expected(format!("an argument for {}", what), command))?;
+                self.push(Token::Variable(variable), command);
+                Ok(())
+            },
+            _ => Err(Error::Expected(format!("an argument for {}", what), span)),
+        }
+    }
+
+    /// Call `name`, the arguments either follow in parentheses or are a single argument like `\sin x`
+    fn call(&mut self, name: &str, span: Span) -> Result<()> {
+        self.push(Token::Function(name.to_string(), 0), span);
+        self.skip_whitespace();
+        let rest = &self.source[self.position..];
+        if rest.starts_with('(') || rest.starts_with("\\left(") || rest.starts_with("\\left[") {
+            return Ok(());
+        }
+        self.push(Token::Paren(Paren::Left), span);
+        self.argument(&format!("{}()", name), span)?;
+        self.push(Token::Paren(Paren::Right), span);
+        Ok(())
+    }
+
+    fn backslash(&mut self) -> Result<()> {
+        let (name, span) = self.command()?;
+
+        match name.as_str() {
+            "cdot" | "times" => self.push(Token::Operator(Operator::Mul), span),
+            "div" => self.push(Token::Operator(Operator::Div), span),
+            "bmod" => self.push(Token::Operator(Operator::Mod), span),
+            "frac" | "dfrac" | "tfrac" => {
+                self.push(Token::Paren(Paren::Left), span);
+                self.argument("\\frac", span)?;
+                self.push(Token::Operator(Operator::Div), span);
+                self.argument("\\frac", span)?;
+                self.push(Token::Paren(Paren::Right), span);
+            },
+            "sqrt" => {
+                self.skip_whitespace();
+                let function = if self.peek() == Some('[') {
+                    let (index, index_span) = self.bracketed()?;
+                    match index.as_str() {
+                        "2" => "sqrt",
+                        "3" => "cbrt",
+                        _ => return Err(Error::Expected("a square or cube root".to_string(), index_span)),
+                    }
+                } else {
+                    "sqrt"
+                };
+                self.push(Token::Function(function.to_string(), 0), span);
+                self.skip_whitespace();
+                if self.peek() == Some('{') {
+                    self.group()?;
+                } else {
+                    self.push(Token::Paren(Paren::Left), span);
+                    self.argument("\\sqrt", span)?;
+                    self.push(Token::Paren(Paren::Right), span);
+                }
+            },
+            "left" | "right" => {
+                self.skip_whitespace();
+                let (start, delimiter) = self.bump().ok_or_else(|| Error::Expected(format!("a delimiter after \\{}", name), span))?;
+                let span = span.join(Span::new(start, self.position));
+                match (name.as_str(), delimiter) {
+                    ("left", '(') | ("left", '[') => self.push(Token::Paren(Paren::Left), span),
+                    ("right", ')') | ("right", ']') => self.push(Token::Paren(Paren::Right), span),
+                    ("left", '|') => {
+                        self.push(Token::Function("abs".to_string(), 0), span);
+                        self.push(Token::Paren(Paren::Left), span);
+                    },
+                    ("right", '|') => self.push(Token::Paren(Paren::Right), span),
+                    _ => return Err(Error::Expected(format!("a parenthesis, bracket or | after \\{}", name), span)),
+                }
+            },
+            "operatorname" => {
+                let (function, name_span) = self.raw_group(&name, span)?;
+                if !is_identifier(&function) {
+                    return Err(Error::Lex(function, name_span));
+                }
+                self.call(&function, span.join(name_span))?;
+            },
+            "mathrm" | "mathit" | "text" => {
+                let (variable, name_span) = self.raw_group(&name, span)?;
+                if !is_identifier(&variable) {
+                    return Err(Error::Lex(variable, name_span));
+                }
+                self.push(Token::Variable(variable), span.join(name_span));
+            },
+            _ if SPACING.contains(&name.as_str()) => (),
+            _ => {
+                if let Some(&(function, _)) = LATEX_FUNCTIONS.iter().find(|&&(_, command)| command[1..] == name) {
+                    return self.call(function, span);
+                }
+                match symbol(&name) {
+                    Some(variable) => self.push(Token::Variable(variable), span),
+                    None => return Err(Error::UnknownCommand(format!("\\{}", name), span)),
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// The contents of `[...]`, like the index of `\sqrt[3]`
+    fn bracketed(&mut self) -> Result<(String, Span)> {
+        let (start, _) = self.bump().unwrap();
+        match self.source[self.position..].find(']') {
+            Some(offset) => {
+                let text = self.source[self.position..self.position + offset].trim().to_string();
+                let span = Span::new(start, self.position + offset + 1);
+                self.position += offset + 1;
+                Ok((text, span))
+            },
+            None => Err(Error::Expected("']'".to_string(), Span::new(start, start + 1))),
+        }
+    }
+
+    /// Lex until `close` or the end, returning the span of the closing character if it was found
+    fn expression(&mut self, close: Option<char>) -> Result<Option<Span>> {
+        while let Some(c) = self.peek() {
+            let start = self.position;
+            let span = Span::new(start, start + c.len_utf8());
+
+            match c {
+                _ if Some(c) == close => {
+                    self.bump();
+                    return Ok(Some(span));
+                },
+                _ if c.is_whitespace() => { self.bump(); },
+                '\\' => self.backslash()?,
+                '{' => self.group()?,
+                '}' => return Err(Error::MismatchedParen(span)),
+                '0'..='9' | '.' => {
+                    while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                        self.bump();
+                    }
+                    self.push_literal(start)?;
+                },
+                c if c.is_ascii_alphabetic() => {
+                    // Every letter is its own variable, unless it has a subscript
+                    self.bump();
+                    let mut name = c.to_string();
+                    if self.peek() == Some('_') {
+                        self.bump();
+                        let subscript = if self.peek() == Some('{') {
+                            self.raw_group("_", span)?.0
+                        } else {
+                            self.bump().map(|(_, c)| c.to_string()).unwrap_or_default()
+                        };
+                        name = format!("{}_{}", name, subscript);
+                        if !is_identifier(&name) {
+                            return Err(Error::Lex(name, Span::new(start, self.position)));
+                        }
+                    }
+                    self.push(Token::Variable(name), Span::new(start, self.position));
+                },
+                '^' => {
+                    self.bump();
+                    self.push(Token::Operator(Operator::Pow), span);
+                    self.argument("^", span)?;
+                },
+                '-' => {
+                    self.bump();
+                    // Same rule as `tokenize`, a minus is unary unless it follows an operand (or a factorial)
+                    let unary = match self.tokens.last().map(|t| &t.token) {
+                        Some(Token::Operator(Operator::Factorial)) => false,
+                        None | Some(Token::Operator(_)) | Some(Token::Paren(Paren::Left)) | Some(Token::Comma) => true,
+                        _ => false,
+                    };
+                    let op = if unary { Operator::USub } else { Operator::Sub };
+                    self.push(Token::Operator(op), span);
+                },
+                '+' | '*' | '/' | '!' => {
+                    self.bump();
+                    self.push(Token::Operator(Operator::from_char(c).unwrap()), span);
+                },
+                '(' | '[' => {
+                    self.bump();
+                    self.push(Token::Paren(Paren::Left), span);
+                },
+                ')' | ']' => {
+                    self.bump();
+                    self.push(Token::Paren(Paren::Right), span);
+                },
+                ',' => {
+                    self.bump();
+                    self.push(Token::Comma, span);
+                },
+                _ => return Err(Error::UnknownCharacters(vec![(c, span)])),
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// Name of the variable a symbol command like `\pi` stands for
+fn symbol(command: &str) -> Option<String> {
+    SYMBOLS.iter()
+        .find(|&&(_, latex, _)| latex[1..] == *command)
+        .map(|&(name, ..)| name.to_string())
+        .or_else(|| GREEK.iter().find(|&&name| name == command).map(|name| name.to_string()))
+}
+
+/// Lex a practical subset of LaTeX math into the infix token stream `token::tokenize` would make for the
+/// same expression. Supports `\frac`, `\sqrt` (and `\sqrt[3]`), `^`, `\cdot`, `\times`, `\div`, `\bmod`, `!`,
+/// `\left( \right)` and friends, `{}` groups, function commands like `\sin`, `\operatorname` and `\mathrm`,
+/// symbols like `\pi`, subscripts, and implicit multiplication (`2x`, `x(y+1)`).
+/// Spans point into the LaTeX
+pub fn tokenize(s: &str) -> Result<Vec<Spanned>> {
+    let mut lexer = Lexer { source: s, position: 0, tokens: Vec::new(), digit_argument: None };
+    lexer.expression(None)?;
+    Ok(lexer.tokens)
+}
+
+impl Tree {
+    /// Parse LaTeX math, see `latex::tokenize` for what's supported. Operators follow the same precedence
+    /// rules as `Tree::parse`, so `-2^{2}` is `(-2)^2` just like `-2^2` would be
+    /// ```rust
+    /// use pemdrs::Tree;
+    ///
+    /// let tree = Tree::parse_latex("\\frac{1}{2}x^{2} + \\sqrt{4} \\cdot \\pi")?;
+    /// assert_eq!(tree, Tree::parse("(1/2)*x^2 + sqrt(4)*pi")?);
+    /// # Ok::<(), pemdrs::Error>(())
+    /// ```
+    pub fn parse_latex(s: &str) -> Result<Self> {
+        Tree::try_from(shunting_yard(tokenize(s)?)?)
+    }
+}
+
+
+#[test]
+fn test_latex_tokenize() {
+    let tokens = |s: &str| tokenize(s).unwrap().into_iter().map(|t| t.token).collect::<Vec<Token>>();
+    let infix = |s: &str| crate::token::tokenize(s).unwrap().into_iter().map(|t| t.token).collect::<Vec<Token>>();
+
+    assert_eq!(tokens("3 + 4 \\cdot 2"), infix("3 + 4 * 2"));
+    assert_eq!(tokens("\\frac{1}{2}"), infix("((1)/(2))"));
+    assert_eq!(tokens("\\frac12"), infix("(1/2)"));
+    assert_eq!(tokens("2x^{2}"), infix("2*x^(2)"));
+    assert_eq!(tokens("x^23"), infix("x^2*3"));
+    assert_eq!(tokens("-x \\times -2"), infix("-x * -2"));
+    assert_eq!(tokens("\\left(a\\right)\\left[b\\right]"), infix("(a)*(b)"));
+    assert_eq!(tokens("\\sqrt{x} + \\sqrt[3]{8}"), infix("sqrt(x) + cbrt(8)"));
+    assert_eq!(tokens("\\sin x"), infix("sin(x)"));
+    assert_eq!(tokens("\\max\\left(1, y\\right)"), infix("max(1, y)"));
+    assert_eq!(tokens("\\left|x\\right|"), infix("abs(x)"));
+    assert_eq!(tokens("\\operatorname{floor}(x)"), infix("floor(x)"));
+    assert_eq!(tokens("\\mathrm{rate\\_of\\_change} \\, x_1 y_{max}"), infix("rate_of_change * x_1 * y_max"));
+    assert_eq!(tokens("2\\pi r + \\theta"), infix("2 * pi * r + theta"));
+
+    // Spans point into the LaTeX
+    let spans = tokenize("\\frac{1}{x}").unwrap().into_iter().map(|t| t.span).collect::<Vec<Span>>();
+    assert_eq!(spans, vec![
+        Span::new(0, 5), Span::new(5, 6), Span::new(6, 7), Span::new(7, 8),
+        Span::new(0, 5), Span::new(8, 9), Span::new(9, 10), Span::new(10, 11), Span::new(0, 5),
+    ]);
+}
+
+#[test]
+fn test_latex_parse() {
+    let cases = vec![
+        ("\\frac{54}{9}^2 - 4 \\cdot 7 + 7", "(54/9)^2 - 4*7 + 7"),
+        ("\\left(\\frac{54}{9}\\right)^{2}-4\\times7+7", "(54/9)^2 - 4*7 + 7"),
+        ("3 \\div 4 \\div 5", "3 / 4 / 5"),
+        ("n!x - 1 \\bmod 3", "n! * x - 1 % 3"),
+        ("2^{3^{4}}", "2^3^4"),
+        ("2(x+1)(x-1)", "2*(x+1)*(x-1)"),
+        ("\\frac{1}{2}\\frac{3}{4}", "(1/2)*(3/4)"),
+        ("\\frac{-b + \\sqrt{b^2 - 4ac}}{2a}", "(-b + sqrt(b^2 - 4*a*c)) / (2*a)"),
+        ("{1 + 2}3", "(1+2)*3"),
+        ("\\infty", "inf"),
+        ("2\\,x + 3\\pi", "2*x + 3*pi"),
+        ("x^2 3", "x^2*3"),
+    ];
+    for (latex, infix) in cases {
+        assert_eq!(Tree::parse_latex(latex).unwrap(), Tree::parse(infix).unwrap(), "parsing {}", latex);
+    }
+
+    assert_eq!(Tree::parse_latex("\\frac{54}{9}^2 - 4 \\cdot 7 + 7").unwrap().evaluate(), Ok(15.0));
+
+    // Whatever the LaTeX formatter makes parses back into the same tree
+    let inputs = [
+        "1+2*3", "(1+2)*3", "1-(2-3)", "(1+2)/(3-x)", "1/2/3", "2*(3/4)", "(2^3)^4", "2^(1+x)", "(1/2)^2",
+        "-2^2", "-(2^2)", "-(x+1)", "-(1/x)", "3 - -x", "sqrt(x^2+1)", "cbrt(8) + abs(-x)",
+        "sin(x)^2 + max(1, y)", "floor(2.5)", "2*pi*rate_of_change", "((-78/-13)^3-8)*-4+4",
+        "x % 3 * 2", "(n+1)! - 1", "(-3)!", "-3!",
+    ];
+    for input in inputs.iter() {
+        let tree = Tree::parse(input).unwrap();
+        assert_eq!(Tree::parse_latex(&tree.to_latex()).unwrap(), tree, "round tripping {} through {}", input, tree.to_latex());
+    }
+}
+
+#[test]
+fn test_latex_errors() {
+    assert_eq!(Tree::parse_latex("1 + \\foo"), Err(Error::UnknownCommand("\\foo".to_string(), Span::new(4, 8))));
+    assert_eq!(Tree::parse_latex("\\frac{1}"), Err(Error::Expected("an argument for \\frac".to_string(), Span::new(0, 5))));
+    assert_eq!(Tree::parse_latex("\\frac{1}{2"), Err(Error::MismatchedParen(Span::new(8, 9))));
+    assert_eq!(Tree::parse_latex("1 + 2}"), Err(Error::MismatchedParen(Span::new(5, 6))));
+    assert_eq!(Tree::parse_latex("\\left(1 + 2"), Err(Error::MismatchedParen(Span::new(0, 6))));
+    assert_eq!(Tree::parse_latex("2^"), Err(Error::Expected("an argument for ^".to_string(), Span::new(1, 2))));
+    assert_eq!(Tree::parse_latex("\\sqrt[4]{x}"), Err(Error::Expected("a square or cube root".to_string(), Span::new(5, 8))));
+    assert_eq!(Tree::parse_latex("1 + $"), Err(Error::UnknownCharacters(vec![('$', Span::new(4, 5))])));
+    assert_eq!(Tree::parse_latex("3 \\cdot"), Err(Error::MissingOperand(Operator::Mul, Span::new(2, 7))));
+    assert_eq!(Tree::parse_latex("1..2"), Err(Error::Lex("1..2".to_string(), Span::new(0, 4))));
+    assert_eq!(Tree::parse_latex("\\mathrm{2x}"), Err(Error::Lex("2x".to_string(), Span::new(8, 10))));
+
+    // Numbers next to each other are digit grouping gone wrong, not a product
+    assert_eq!(Tree::parse_latex("1\\,000"), Err(Error::Expected("an operator between two numbers".to_string(), Span::new(0, 6))));
+    assert_eq!(Tree::parse_latex("12 345"), Err(Error::Expected("an operator between two numbers".to_string(), Span::new(0, 6))));
+    assert_eq!(Tree::parse_latex("x^2 34 5"), Err(Error::Expected("an operator between two numbers".to_string(), Span::new(4, 8))));
+}
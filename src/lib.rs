@@ -0,0 +1,39 @@
+//! Order of operations aware expression parsing and evaluation
+//!
+//! ```rust
+//! let answer = pemdrs::Expression::parse("3 + 4 * 2 / (1 - 5) ^ 2 ^ 3")?.evaluate()?;
+//! assert_eq!(answer, 3.0001220703125);
+//! # Ok::<(), pemdrs::Error>(())
+//! ```
+
+pub mod bigint;
+pub mod check;
+pub mod constant;
+pub mod context;
+pub mod decimal;
+pub mod derivative;
+pub mod diagnostic;
+pub mod error;
+pub mod export;
+pub mod format;
+pub mod function;
+pub mod generate;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod latex;
+pub mod notation;
+pub mod number;
+pub mod rational;
+pub mod rewrite;
+pub mod sexpr;
+pub mod simplify;
+pub mod step;
+pub mod tree;
+pub mod token;
+
+pub use context::{ Context, Environment };
+pub use error::{ Error, Result };
+pub use tree::{ Node, Tree };
+
+/// Friendlier name for a parsed `Tree`
+pub type Expression = Tree;
@@ -1,6 +1,44 @@
-pub mod tree;
-pub mod token;
+mod cli;
+mod repl;
+
+use cli::{ Action, Input };
+
+use std::{
+    env,
+    io::{ self, IsTerminal },
+    process,
+};
 
 fn main() {
-    println!("Hello, world!");
+    let options = match cli::parse_args(env::args().skip(1), io::stdin().is_terminal()) {
+        Ok(Action::Run(options)) => options,
+        Ok(Action::Worksheet(options)) => match cli::worksheet(&options) {
+            Ok(sheet) => {
+                print!("{}", sheet);
+                return;
+            },
+            Err(e) => {
+                eprintln!("error: {}", e);
+                process::exit(cli::EXIT_USAGE);
+            },
+        },
+        Ok(Action::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, cli::USAGE);
+            process::exit(cli::EXIT_USAGE);
+        },
+    };
+
+    if options.input == Input::Interactive {
+        if let Err(e) = repl::run() {
+            eprintln!("error: {}", e);
+            process::exit(cli::EXIT_IO);
+        }
+        return;
+    }
+
+    process::exit(cli::run(&options));
 }
@@ -0,0 +1,286 @@
+use crate::{
+    error::{ Error, Result },
+    function::{ Arity, builtin },
+    token::{ Token, Operator, Span, Spanned, is_decimal, is_identifier },
+    tree::{ Node, Tree },
+};
+
+use std::convert::TryFrom;
+
+
+/// Turn space separated words into tokens, in whatever order they were written.
+///
+/// Words are numbers (`#inf`, `#-inf` and `#nan` for values that aren't finite), operators (`~` for
+/// unary minus), variables, and functions. Builtin functions
+/// that take a fixed number of arguments can be written by name, anything else needs its argument
+/// count after a colon (e.g. `max:3`)
+pub fn words(s: &str) -> Result<Vec<Spanned>> {
+    let mut tokens = Vec::new();
+    let mut rest = s;
+
+    while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+        let length = rest[start..].find(char::is_whitespace).unwrap_or(rest.len() - start);
+        let word = &rest[start..start + length];
+        let offset = s.len() - rest.len() + start;
+        let span = Span::new(offset, offset + length);
+        tokens.push(match token(word, span)? {
+            Token::Value(_) if is_decimal(word) => Spanned::literal(word, span)?,
+            token => Spanned::new(token, span),
+        });
+        rest = &rest[start + length..];
+    }
+
+    Ok(tokens)
+}
+
+/// The operator `word` stands for, unary minus is `~` so it can't be mixed up with subtraction or a variable
+pub(crate) fn operator(word: &str) -> Option<Operator> {
+    match word {
+        "~" => Some(Operator::USub),
+        _ => word.parse::<Operator>().ok().filter(|&op| op != Operator::USub),
+    }
+}
+
+/// How `op` is written as a word, the inverse of `operator`
+pub(crate) fn operator_word(op: Operator) -> String {
+    match op {
+        Operator::USub => "~".to_string(),
+        op => op.to_string(),
+    }
+}
+
+/// How a value is written, infinities and NaN get a `#` so they can't be mixed up with variables
+/// named `inf` or `nan`
+pub(crate) fn value_word(v: f64) -> String {
+    if v.is_nan() {
+        "#nan".to_string()
+    } else if v == f64::INFINITY {
+        "#inf".to_string()
+    } else if v == f64::NEG_INFINITY {
+        "#-inf".to_string()
+    } else {
+        v.to_string()
+    }
+}
+
+/// The value a `#` word stands for, the inverse of `value_word` for values that aren't finite
+pub(crate) fn special_value(word: &str) -> Option<f64> {
+    match word {
+        "#nan" => Some(f64::NAN),
+        "#inf" => Some(f64::INFINITY),
+        "#-inf" => Some(f64::NEG_INFINITY),
+        _ => None,
+    }
+}
+
+fn token(word: &str, span: Span) -> Result<Token> {
+    if let Some(op) = operator(word) {
+        return Ok(Token::Operator(op));
+    }
+    if let Some(v) = special_value(word) {
+        return Ok(Token::Value(v));
+    }
+
+    if let Some((name, count)) = word.split_once(':') {
+        return match count.parse::<usize>() {
+            Ok(count) if is_identifier(name) => Ok(Token::Function(name.to_string(), count)),
+            _ => Err(Error::Lex(word.to_string(), span)),
+        };
+    }
+    if is_identifier(word) {
+        return Ok(match builtin(word).map(|f| f.arity) {
+            Some(Arity::Fixed(count)) => Token::Function(word.to_string(), count),
+            Some(Arity::Variadic(_)) => {
+                return Err(Error::Expected(format!("an argument count for '{}', like {}:2", word, word), span));
+            },
+            None => Token::Variable(word.to_string()),
+        });
+    }
+
+    word.parse::<f64>()
+        .map(Token::Value)
+        .map_err(|_| Error::Lex(word.to_string(), span))
+}
+
+/// How `node` is written as a word, the inverse of `words`. Values are written the way they were read if possible
+fn word(node: &Node) -> String {
+    if let Some(literal) = &node.literal {
+        return literal.clone();
+    }
+
+    match node.token() {
+        &Token::Operator(op) => operator_word(op),
+        &Token::Value(v) => value_word(v),
+        Token::Function(name, _) => match builtin(name).map(|f| f.arity) {
+            Some(Arity::Fixed(count)) if count == node.args().len() => name.clone(),
+            _ => format!("{}:{}", name, node.args().len()),
+        },
+        token => token.to_string(),
+    }
+}
+
+/// How many operands `token` takes, along with the error for when they aren't there
+fn operands(token: &Spanned) -> (usize, Option<Error>) {
+    match &token.token {
+        &Token::Operator(op) => (if op.is_unary() { 1 } else { 2 }, Some(Error::MissingOperand(op, token.span))),
+        Token::Function(name, count) => (*count, Some(Error::MissingArgument(name.clone(), token.span))),
+        _ => (0, None),
+    }
+}
+
+/// Reorder the prefix expression starting at `words[*position]` into `postfix`
+fn prefix_to_postfix(words: &[Spanned], position: &mut usize, postfix: &mut Vec<Spanned>) -> Result<()> {
+    let word = words[*position].clone();
+    *position += 1;
+
+    let (count, missing) = operands(&word);
+    for _ in 0..count {
+        if *position >= words.len() {
+            return Err(missing.expect("Something went wrong! (operand count without an error to go with it)"));
+        }
+        prefix_to_postfix(words, position, postfix)?;
+    }
+
+    postfix.push(word);
+    Ok(())
+}
+
+impl Tree {
+    /// Parse space separated reverse Polish (postfix) notation, see `notation::words` for what a word can be
+    /// ```rust
+    /// use pemdrs::Tree;
+    ///
+    /// let tree = Tree::parse_rpn("3 4 2 * +")?;
+    /// assert_eq!(tree, Tree::parse("3 + 4 * 2")?);
+    /// assert_eq!(tree.to_rpn(), "3 4 2 * +");
+    /// assert_eq!(tree.to_polish(), "+ 3 * 4 2");
+    /// # Ok::<(), pemdrs::Error>(())
+    /// ```
+    pub fn parse_rpn(s: &str) -> Result<Self> {
+        Tree::try_from(words(s)?)
+    }
+
+    /// Parse space separated Polish (prefix) notation, see `notation::words` for what a word can be
+    pub fn parse_polish(s: &str) -> Result<Self> {
+        let words = words(s)?;
+        if words.is_empty() {
+            return Err(Error::EmptyExpression);
+        }
+
+        let mut position = 0;
+        let mut postfix = Vec::with_capacity(words.len());
+        prefix_to_postfix(&words, &mut position, &mut postfix)?;
+        if let Some(extra) = words[position..].iter().map(|word| word.span).reduce(Span::join) {
+            return Err(Error::ExtraOperand(extra));
+        }
+
+        Tree::try_from(postfix)
+    }
+
+    /// The expression in space separated reverse Polish (postfix) notation
+    pub fn to_rpn(&self) -> String {
+        self.post_order().map(word).collect::<Vec<String>>().join(" ")
+    }
+
+    /// The expression in space separated Polish (prefix) notation
+    pub fn to_polish(&self) -> String {
+        self.pre_order().map(word).collect::<Vec<String>>().join(" ")
+    }
+}
+
+
+#[test]
+fn test_notation_parse() {
+    use std::collections::HashMap;
+
+    let env = [("x", 3.0), ("y", 1.0), ("r", 2.0)].iter().cloned().collect::<HashMap<&str, f64>>();
+    let cases = vec![
+        ("3 4 2 * +", "+ 3 * 4 2", "3 + 4 * 2"),
+        ("54 9 / 2 ^ 4 7 * - 7 +", "+ - ^ / 54 9 2 * 4 7 7", "(54/9)^2-4*7+7"),
+        ("2 3 4 ^ ^", "^ 2 ^ 3 4", "2^3^4"),
+        ("1 2 - 3 -", "- - 1 2 3", "1 - 2 - 3"),
+        ("x ~ 2 ^", "^ ~ x 2", "-x^2"),
+        ("2 ~ ~", "~ ~ 2", "--2"),
+        ("-1.5 2 *", "* -1.5 2", "(0 - 1.5) * 2"),
+        ("x 2 ^ 1 + sqrt", "sqrt + ^ x 2 1", "sqrt(x^2 + 1)"),
+        ("1 2 3 max:3 y atan2", "atan2 max:3 1 2 3 y", "atan2(max(1, 2, 3), y)"),
+        ("  pi   r  * ", "* pi r", "pi * r"),
+    ];
+
+    for (rpn, polish, infix) in cases {
+        let rpn_tree = Tree::parse_rpn(rpn).unwrap();
+        let polish_tree = Tree::parse_polish(polish).unwrap();
+        assert_eq!(rpn_tree, polish_tree, "{} and {}", rpn, polish);
+        assert_eq!(rpn_tree.evaluate_with(&env), Tree::parse(infix).unwrap().evaluate_with(&env), "{}", rpn);
+    }
+
+    // Spans point at the words
+    let tree = Tree::parse_rpn("10  x /").unwrap();
+    assert_eq!(tree.root().span(), Span::new(0, 7));
+    assert_eq!(tree.root().right().unwrap().span(), Span::new(4, 5));
+}
+
+#[test]
+fn test_notation_round_trip() {
+    use crate::constant::Constants;
+
+    let inputs = [
+        "1+2*3", "(1+2)*3", "1-(2-3)", "2^3^4", "(2^3)^4", "-2^2", "-(2^2)", "--x", "3 - -x",
+        "sqrt(x^2+1)", "max(1, (2+3)*x)", "min(4) + hypot(3, 4)", "((-78/-13)^3-8)*-4+4", "nan + inf",
+        "7 // x % 3!", "(-x)! - 1", "u * 2", "neg - -u",
+    ];
+    for input in inputs.iter() {
+        let tree = Tree::parse(input).unwrap();
+        assert_eq!(Tree::parse_rpn(&tree.to_rpn()).unwrap(), tree, "round tripping {} through {}", input, tree.to_rpn());
+        assert_eq!(Tree::parse_polish(&tree.to_polish()).unwrap(), tree, "round tripping {} through {}", input, tree.to_polish());
+    }
+
+    let tree = Tree::parse("max(1, -x) * 2").unwrap();
+    assert_eq!(tree.to_rpn(), "1 x ~ max:2 2 *");
+    assert_eq!(tree.to_polish(), "* max:2 1 ~ x 2");
+
+    // Values are written the way they were read
+    let tree = Tree::parse("0.10 * 12345678901234567890.5").unwrap();
+    assert_eq!(tree.to_rpn(), "0.10 12345678901234567890.5 *");
+    assert_eq!(Tree::parse_rpn(&tree.to_rpn()).unwrap().to_polish(), "* 0.10 12345678901234567890.5");
+
+    // Variables can have any name, unary minus can't be mistaken for one
+    let tree = Tree::parse("-u * 2 - neg").unwrap();
+    assert_eq!(tree.to_rpn(), "u ~ 2 * neg -");
+    assert_eq!(Tree::parse_rpn(&tree.to_rpn()).unwrap(), tree);
+    assert_eq!(Tree::parse_polish(&tree.to_polish()).unwrap(), tree);
+
+    // Folded values that aren't finite don't turn into variables named inf or nan
+    let mut tree = Tree::parse("-inf * x + nan").unwrap();
+    tree.fold_constants(&Constants::new());
+    assert_eq!(tree.to_rpn(), "#inf ~ x * #nan +");
+    assert_eq!(tree.to_polish(), "+ * ~ #inf x #nan");
+    assert_eq!(Tree::parse_rpn(&tree.to_rpn()).unwrap().to_rpn(), tree.to_rpn());
+    assert_eq!(Tree::parse_polish(&tree.to_polish()).unwrap().to_polish(), tree.to_polish());
+    assert_eq!(Tree::parse_rpn("#inf inf -").unwrap().root().left().unwrap().token(), &Token::Value(f64::INFINITY));
+}
+
+#[test]
+fn test_notation_errors() {
+    // Stack underflow
+    assert_eq!(Tree::parse_rpn("1 +"), Err(Error::MissingOperand(Operator::Add, Span::new(2, 3))));
+    assert_eq!(Tree::parse_rpn("~"), Err(Error::MissingOperand(Operator::USub, Span::new(0, 1))));
+    assert_eq!(Tree::parse_rpn("1 2 max:3"), Err(Error::MissingArgument("max".to_string(), Span::new(4, 9))));
+    assert_eq!(Tree::parse_polish("+ 1"), Err(Error::MissingOperand(Operator::Add, Span::new(0, 1))));
+    assert_eq!(Tree::parse_polish("* + 1 2"), Err(Error::MissingOperand(Operator::Mul, Span::new(0, 1))));
+    assert_eq!(Tree::parse_polish("atan2 1"), Err(Error::MissingArgument("atan2".to_string(), Span::new(0, 5))));
+
+    // Leftover operands
+    assert_eq!(Tree::parse_rpn("1 2 3 +"), Err(Error::ExtraOperand(Span::new(0, 1))));
+    assert_eq!(Tree::parse_polish("+ 1 2 3 4"), Err(Error::ExtraOperand(Span::new(6, 9))));
+
+    assert_eq!(Tree::parse_rpn("   "), Err(Error::EmptyExpression));
+    assert_eq!(Tree::parse_polish(""), Err(Error::EmptyExpression));
+
+    assert_eq!(Tree::parse_rpn("1 2 &"), Err(Error::Lex("&".to_string(), Span::new(4, 5))));
+    assert_eq!(Tree::parse_rpn("1 f:x"), Err(Error::Lex("f:x".to_string(), Span::new(2, 5))));
+    assert_eq!(
+        Tree::parse_rpn("1 2 max"),
+        Err(Error::Expected("an argument count for 'max', like max:2".to_string(), Span::new(4, 7))),
+    );
+}
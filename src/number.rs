@@ -0,0 +1,150 @@
+use crate::{
+    error::{ Error, Result },
+    function::Function,
+    token::Span,
+};
+
+use std::fmt::{ Debug, Display };
+
+
+/// What an expression gets evaluated with, `f64` unless asked for something else (see `Tree::evaluate_as`).
+/// Errors have an empty span, evaluation points them at the part of the source they came from
+/// ```rust
+/// use pemdrs::{ Tree, decimal::{ Decimal, Rounding, Settings }, rational::Rational };
+///
+/// let tree = Tree::parse("0.1 + 0.2")?;
+/// assert_eq!(tree.evaluate()?, 0.30000000000000004);
+/// assert_eq!(tree.evaluate_as::<Rational>()?.to_string(), "3/10");
+/// assert_eq!(tree.evaluate_as::<f64>()?, tree.evaluate()?);
+///
+/// let settings = Settings { scale: 4, rounding: Rounding::HalfUp };
+/// assert_eq!(tree.evaluate_with_settings::<Decimal, _>(&(), &settings)?.to_string(), "0.3000");
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub trait Number: Clone + PartialEq + Debug + Display {
+    /// Whatever else it takes to make a number, like how many decimal places to keep. `()` if nothing
+    type Settings: Clone + Debug + Default;
+
+    /// A variable, which contexts keep as an `f64`
+    fn from_f64(v: f64, settings: &Self::Settings) -> Result<Self>;
+
+    /// A literal exactly as it was written, like `0.1`. Goes through `f64` unless overridden
+    fn from_literal(literal: &str, settings: &Self::Settings) -> Result<Self> {
+        match literal.parse::<f64>() {
+            Ok(v) => Self::from_f64(v, settings),
+            Err(_) => Err(Error::Lex(literal.to_string(), Span::default())),
+        }
+    }
+
+    /// The closest `f64`
+    fn to_f64(&self) -> f64;
+
+    /// The constant `name`, which the context says is `value`
+    fn constant(_name: &str, value: f64, settings: &Self::Settings) -> Result<Self> {
+        Self::from_f64(value, settings)
+    }
+
+    fn add(&self, other: &Self) -> Result<Self>;
+
+    fn sub(&self, other: &Self) -> Result<Self>;
+
+    fn mul(&self, other: &Self) -> Result<Self>;
+
+    /// `Error::DivisionByZero` if `other` is zero
+    fn div(&self, other: &Self) -> Result<Self>;
+
+    /// `self / other` rounded down, `Error::DivisionByZero` if `other` is zero
+    fn int_div(&self, other: &Self) -> Result<Self>;
+
+    /// What's left over after `int_div`, which has the sign of `other`
+    fn rem(&self, other: &Self) -> Result<Self>;
+
+    /// `settings` can limit how big the result gets
+    fn pow(&self, other: &Self, settings: &Self::Settings) -> Result<Self>;
+
+    fn neg(&self) -> Self;
+
+    /// Only defined for whole numbers that aren't negative, see `no_factorial`
+    fn factorial(&self, settings: &Self::Settings) -> Result<Self>;
+
+    /// Call `function`, whose arity has already been checked. Goes through `f64` unless overridden
+    fn call(function: &dyn Function, args: &[Self], settings: &Self::Settings) -> Result<Self> {
+        let args = args.iter().map(Number::to_f64).collect::<Vec<f64>>();
+        Self::from_f64(function.eval(&args)?, settings)
+    }
+}
+
+/// The error for taking the factorial of `x`, which isn't a whole number or is negative
+pub fn no_factorial<N: Display>(x: &N) -> Error {
+    Error::custom(&format!("{}! isn't defined, only whole numbers that aren't negative have factorials", x))
+}
+
+/// Plain floating point, infinities and NaN included
+impl Number for f64 {
+    type Settings = ();
+
+    fn from_f64(v: f64, _settings: &()) -> Result<Self> {
+        Ok(v)
+    }
+
+    fn to_f64(&self) -> f64 {
+        *self
+    }
+
+    fn add(&self, other: &Self) -> Result<Self> {
+        Ok(self + other)
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self> {
+        Ok(self - other)
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self> {
+        Ok(self * other)
+    }
+
+    fn div(&self, other: &Self) -> Result<Self> {
+        if *other == 0.0 {
+            Err(Error::DivisionByZero(Span::default()))
+        } else {
+            Ok(self / other)
+        }
+    }
+
+    fn int_div(&self, other: &Self) -> Result<Self> {
+        Ok(self.div(other)?.floor())
+    }
+
+    fn rem(&self, other: &Self) -> Result<Self> {
+        if *other == 0.0 {
+            return Err(Error::DivisionByZero(Span::default()));
+        }
+        // % on f64 takes the sign of the dividend
+        let remainder = self % other;
+        if remainder != 0.0 && (remainder < 0.0) != (*other < 0.0) {
+            Ok(remainder + other)
+        } else {
+            Ok(remainder)
+        }
+    }
+
+    fn pow(&self, other: &Self, _settings: &()) -> Result<Self> {
+        Ok(self.powf(*other))
+    }
+
+    fn neg(&self) -> Self {
+        -self
+    }
+
+    /// Infinite past `170!`
+    fn factorial(&self, _settings: &()) -> Result<Self> {
+        if self.is_nan() || *self < 0.0 || self.fract() != 0.0 {
+            return Err(no_factorial(self));
+        }
+        Ok(if *self > 170.0 { f64::INFINITY } else { (2..=*self as u64).map(|k| k as f64).product() })
+    }
+
+    fn call(function: &dyn Function, args: &[Self], _settings: &()) -> Result<Self> {
+        function.eval(args)
+    }
+}
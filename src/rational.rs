@@ -0,0 +1,465 @@
+use crate::{
+    bigint::{ BigInt, Settings },
+    constant::builtin_constant,
+    error::{ Error, Result },
+    function::Function,
+    number::{ Number, no_factorial },
+    token::Span,
+};
+
+use std::{
+    cmp::Ordering,
+    fmt::{ Debug, Display, Formatter, Result as fmt_Result },
+    str::FromStr,
+};
+
+
+/// Builtin constants that can't be written as a fraction
+const IRRATIONAL_CONSTANTS: &[&str] = &["pi", "e", "tau"];
+
+/// How many times `n` divides by `base` before reaching one, `None` if it isn't a power of `base`
+fn power_of(n: &BigInt, base: i32) -> Option<u64> {
+    let base = BigInt::from(base);
+    let mut n = n.clone();
+    let mut count = 0;
+    while !n.is_one() {
+        let (quotient, remainder) = n.div_rem(&base).ok()?;
+        if n.is_zero() || !remainder.is_zero() {
+            return None;
+        }
+        n = quotient;
+        count += 1;
+    }
+    Some(count)
+}
+
+/// An exact fraction of big integers, always in lowest terms with a positive denominator.
+/// Evaluating with it (see `Tree::evaluate_as`) never rounds, results that aren't fractions are errors
+/// ```rust
+/// use pemdrs::{ Error, Tree, rational::Rational, token::Span };
+///
+/// assert_eq!(Tree::parse("1/3 * 3")?.evaluate_as::<Rational>()?, Rational::from(1));
+/// assert_eq!(Tree::parse("(2/3)^-2 + sqrt(9/4)")?.evaluate_as::<Rational>()?.to_string(), "15/4");
+/// assert_eq!(
+///     Tree::parse("1 + 2^0.5")?.evaluate_as::<Rational>(),
+///     Err(Error::Irrational("2 ^ (1/2)".to_string(), Span::new(4, 9))),
+/// );
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: BigInt,
+    /// Always positive
+    denominator: BigInt,
+} impl Rational {
+    /// `numerator / denominator` in lowest terms
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Result<Self> {
+        if denominator.is_zero() {
+            return Err(Error::DivisionByZero(Span::default()));
+        }
+
+        let gcd = numerator.gcd(&denominator);
+        let (mut numerator, mut denominator) = (numerator.div_rem(&gcd)?.0, denominator.div_rem(&gcd)?.0);
+        if denominator.is_negative() {
+            numerator = -numerator;
+            denominator = -denominator;
+        }
+        Ok(Rational { numerator, denominator })
+    }
+
+    pub fn zero() -> Self {
+        Rational::from(0)
+    }
+
+    pub fn numerator(&self) -> &BigInt {
+        &self.numerator
+    }
+
+    pub fn denominator(&self) -> &BigInt {
+        &self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator.is_one()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.numerator.is_negative()
+    }
+
+    pub fn abs(&self) -> Self {
+        Rational { numerator: self.numerator.abs(), denominator: self.denominator.clone() }
+    }
+
+    /// Biggest integer that isn't bigger
+    pub fn floor(&self) -> BigInt {
+        self.numerator.div_mod_floor(&self.denominator).expect("Something went wrong! (zero denominator)").0
+    }
+
+    /// Smallest integer that isn't smaller
+    pub fn ceil(&self) -> BigInt {
+        -self.neg().floor()
+    }
+
+    /// Rounded towards zero
+    pub fn trunc(&self) -> BigInt {
+        self.numerator.div_rem(&self.denominator).expect("Something went wrong! (zero denominator)").0
+    }
+
+    /// Rounded to the nearest integer, halfway cases away from zero like `f64::round`
+    pub fn round(&self) -> BigInt {
+        let half = Rational { numerator: BigInt::one(), denominator: BigInt::from(2) };
+        let rounded = self.abs().add(&half).expect("Something went wrong! (adding a half)").floor();
+        if self.is_negative() { -rounded } else { rounded }
+    }
+
+    /// The `n`th root, if it's a fraction
+    pub fn root(&self, n: u64) -> Option<Self> {
+        Some(Rational { numerator: self.numerator.root(n)?, denominator: self.denominator.root(n)? })
+    }
+
+    /// `self` to an integer power
+    fn powi(&self, exponent: &BigInt, settings: &Settings) -> Result<Self> {
+        let base = match exponent.is_negative() {
+            true if self.is_zero() => return Err(Error::DivisionByZero(Span::default())),
+            true => Rational::new(self.denominator.clone(), self.numerator.clone())?,
+            false => self.clone(),
+        };
+        // Only zero and one keep their size however big the exponent gets
+        let exponent = match exponent.abs().to_i64() {
+            Some(exponent) => exponent as u64,
+            None if base.is_integer() && base.numerator.bits() <= 1 => if exponent.is_even() { 2 } else { 1 },
+            None => return Err(Error::custom(&format!("{} ^ {} is too big to work out exactly", self, exponent))),
+        };
+        Ok(Rational { numerator: base.numerator.pow(exponent, settings)?, denominator: base.denominator.pow(exponent, settings)? })
+    }
+
+    /// How `self` appears as an operand in error messages, fractions need parentheses
+    fn operand(&self) -> String {
+        if self.is_integer() { self.to_string() } else { format!("({})", self) }
+    }
+} impl Number for Rational {
+    type Settings = Settings;
+
+    /// Exactly the decimal the value is written as, so `0.1` is `1/10` rather than the nearest `f64`
+    fn from_f64(v: f64, _settings: &Settings) -> Result<Self> {
+        if !v.is_finite() {
+            return Err(Error::custom(&format!("{} isn't a finite number", v)));
+        }
+        // Display for f64 is never in scientific notation and gives back the shortest decimal that rounds to v
+        v.to_string().parse()
+    }
+
+    /// Every digit counts, even past what an `f64` could hold
+    fn from_literal(literal: &str, _settings: &Settings) -> Result<Self> {
+        literal.parse()
+    }
+
+    fn to_f64(&self) -> f64 {
+        // Scale up so the quotient has at least 64 significant bits, then scale back down
+        let shift = (self.denominator.bits() + 64).saturating_sub(self.numerator.bits());
+        let quotient = self.numerator.shifted(shift).div_rem(&self.denominator)
+            .expect("Something went wrong! (zero denominator)").0;
+        let mut value = quotient.to_f64();
+        let mut shift = shift;
+        while shift > 0 {
+            let step = shift.min(1000);
+            value /= 2f64.powi(step as i32);
+            shift -= step;
+        }
+        value
+    }
+
+    fn constant(name: &str, value: f64, settings: &Settings) -> Result<Self> {
+        if IRRATIONAL_CONSTANTS.contains(&name) && builtin_constant(name) == Some(value) {
+            return Err(Error::Irrational(name.to_string(), Span::default()));
+        }
+        Rational::from_f64(value, settings)
+    }
+
+    fn add(&self, other: &Self) -> Result<Self> {
+        Rational::new(
+            &(&self.numerator * &other.denominator) + &(&other.numerator * &self.denominator),
+            &self.denominator * &other.denominator,
+        )
+    }
+
+    fn sub(&self, other: &Self) -> Result<Self> {
+        self.add(&other.neg())
+    }
+
+    fn mul(&self, other: &Self) -> Result<Self> {
+        Rational::new(&self.numerator * &other.numerator, &self.denominator * &other.denominator)
+    }
+
+    fn div(&self, other: &Self) -> Result<Self> {
+        Rational::new(&self.numerator * &other.denominator, &self.denominator * &other.numerator)
+    }
+
+    fn int_div(&self, other: &Self) -> Result<Self> {
+        Ok(Rational::from(self.div(other)?.floor()))
+    }
+
+    fn rem(&self, other: &Self) -> Result<Self> {
+        self.sub(&other.mul(&self.int_div(other)?)?)
+    }
+
+    /// Exact for integer exponents, fractional ones need the base to be a perfect power
+    fn pow(&self, other: &Self, settings: &Settings) -> Result<Self> {
+        if other.is_integer() {
+            return self.powi(&other.numerator, settings);
+        }
+
+        let describe = || format!("{} ^ {}", self.operand(), other.operand());
+        let root = other.denominator.to_i64()
+            .and_then(|n| self.root(n as u64))
+            .ok_or_else(|| match self.is_negative() && other.denominator.is_even() {
+                true => Error::custom(&format!("{} isn't a real number", describe())),
+                false => Error::Irrational(describe(), Span::default()),
+            })?;
+        root.powi(&other.numerator, settings)
+    }
+
+    fn neg(&self) -> Self {
+        Rational { numerator: -&self.numerator, denominator: self.denominator.clone() }
+    }
+
+    fn factorial(&self, settings: &Settings) -> Result<Self> {
+        if !self.is_integer() {
+            return Err(no_factorial(self));
+        }
+        Ok(Rational::from(self.numerator.factorial(settings)?))
+    }
+
+    /// Builtins are exact wherever their result is a fraction and `Error::Irrational` everywhere else,
+    /// anything else is called with `f64`s
+    fn call(function: &dyn Function, args: &[Self], settings: &Settings) -> Result<Self> {
+        if function.as_builtin().is_none() {
+            let args = args.iter().map(Number::to_f64).collect::<Vec<f64>>();
+            return Rational::from_f64(function.eval(&args)?, settings);
+        }
+
+        let name = function.name();
+        let describe = || format!(
+            "{}({})",
+            name,
+            args.iter().map(ToString::to_string).collect::<Vec<String>>().join(", "),
+        );
+        let x = &args[0];
+        let one = Rational::from(1);
+        let exact = match name {
+            "abs" => Some(x.abs()),
+            "sign" => Some(Rational::from(match x.numerator.cmp(&BigInt::zero()) {
+                Ordering::Less => -1,
+                Ordering::Equal => 0,
+                Ordering::Greater => 1,
+            })),
+            "floor" => Some(Rational::from(x.floor())),
+            "ceil" => Some(Rational::from(x.ceil())),
+            "round" => Some(Rational::from(x.round())),
+            "trunc" => Some(Rational::from(x.trunc())),
+            "min" => args.iter().min().cloned(),
+            "max" => args.iter().max().cloned(),
+            "sqrt" => x.root(2),
+            "cbrt" => x.root(3),
+            "hypot" => x.mul(x)?.add(&args[1].mul(&args[1])?)?.root(2),
+            // Everything else is transcendental, so only rational at a handful of points
+            "exp" | "cos" | "cosh" if x.is_zero() => Some(one),
+            "sin" | "tan" | "asin" | "atan" | "sinh" | "tanh" if x.is_zero() => Some(Rational::zero()),
+            "ln" | "acos" if *x == one => Some(Rational::zero()),
+            "atan2" if x.is_zero() && !args[1].is_negative() => Some(Rational::zero()),
+            "log" | "log2" => {
+                let base = if name == "log" { 10 } else { 2 };
+                match (power_of(&x.numerator, base), power_of(&x.denominator, base)) {
+                    (Some(power), Some(0)) => Some(Rational::from(BigInt::from(power))),
+                    (Some(0), Some(power)) => Some(Rational::from(-BigInt::from(power))),
+                    _ => None,
+                }
+            },
+            _ => None,
+        };
+        if let Some(exact) = exact {
+            return Ok(exact);
+        }
+
+        // Only worked out once there's no exact answer, arguments past what an f64 holds can still have one
+        let approximate = function.eval(&args.iter().map(Number::to_f64).collect::<Vec<f64>>())?;
+        if !approximate.is_finite() {
+            return Err(Error::custom(&format!("{} isn't a real number", describe())));
+        }
+        Err(Error::Irrational(describe(), Span::default()))
+    }
+} impl From<BigInt> for Rational {
+    fn from(n: BigInt) -> Self {
+        Rational { numerator: n, denominator: BigInt::one() }
+    }
+} impl From<i64> for Rational {
+    fn from(n: i64) -> Self {
+        Rational::from(BigInt::from(n))
+    }
+} impl From<i32> for Rational {
+    fn from(n: i32) -> Self {
+        Rational::from(BigInt::from(n))
+    }
+} impl FromStr for Rational {
+    type Err = Error;
+
+    /// A fraction like `-3/4` or a decimal like `0.75`
+    fn from_str(s: &str) -> Result<Rational> {
+        let error = || Error::Lex(s.to_string(), Span::new(0, s.len()));
+        if let Some((numerator, denominator)) = s.split_once('/') {
+            let (numerator, denominator) = (numerator.parse::<BigInt>(), denominator.parse::<BigInt>());
+            return Rational::new(numerator.map_err(|_| error())?, denominator.map_err(|_| error())?);
+        }
+
+        // Either side of the point can be left off, like `.5` or `5.`
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if (whole.trim_start_matches('-').is_empty() && fraction.is_empty()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(error());
+        }
+        let numerator = format!("{}{}", whole, fraction).parse::<BigInt>().map_err(|_| error())?;
+        Rational::new(numerator, BigInt::from(10).pow(fraction.len() as u64, &Settings::UNLIMITED)?)
+    }
+} impl Display for Rational {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        if self.is_integer() {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+} impl Debug for Rational {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}", self)
+    }
+} impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.numerator * &other.denominator).cmp(&(&other.numerator * &self.denominator))
+    }
+} impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+
+#[test]
+fn test_rational_arithmetic() {
+    let r = |s: &str| s.parse::<Rational>().unwrap();
+
+    assert_eq!(r("2/4"), r("1/2"));
+    assert_eq!(r("-6/-8").to_string(), "3/4");
+    assert_eq!(r("3/-6").to_string(), "-1/2");
+    assert_eq!(r("0.75"), r("3/4"));
+    assert_eq!(r("-0.050"), r("-1/20"));
+    assert_eq!(r("12"), Rational::from(12));
+    assert_eq!(r("0/5"), Rational::zero());
+
+    assert_eq!(r("1/3").add(&r("1/6")), Ok(r("1/2")));
+    assert_eq!(r("1/3").sub(&r("1/2")), Ok(r("-1/6")));
+    assert_eq!(r("2/3").mul(&r("9/4")), Ok(r("3/2")));
+    assert_eq!(r("2/3").div(&r("-4/9")), Ok(r("-3/2")));
+    assert_eq!(r("1").div(&Rational::zero()), Err(Error::DivisionByZero(Span::default())));
+
+    let limits = Settings::default();
+    assert_eq!(r("2/3").pow(&r("3"), &limits), Ok(r("8/27")));
+    assert_eq!(r("2/3").pow(&r("-2"), &limits), Ok(r("9/4")));
+    assert_eq!(r("8/27").pow(&r("2/3"), &limits), Ok(r("4/9")));
+    assert_eq!(r("-8").pow(&r("1/3"), &limits), Ok(r("-2")));
+    assert_eq!(r("5").pow(&Rational::zero(), &limits), Ok(r("1")));
+    assert_eq!(Rational::zero().pow(&r("-1"), &limits), Err(Error::DivisionByZero(Span::default())));
+    assert_eq!(r("2").pow(&r("1/2"), &limits), Err(Error::Irrational("2 ^ (1/2)".to_string(), Span::default())));
+    assert!(matches!(r("-4").pow(&r("1/2"), &limits), Err(Error::Custom(..))));
+    assert!(matches!(r("2").pow(&r("100000000000"), &limits), Err(Error::Custom(..))));
+    assert_eq!(r("-1").pow(&r("100000000000000000000001"), &limits), Ok(r("-1")));
+
+    assert_eq!((r("-7/2").floor(), r("-7/2").ceil()), (BigInt::from(-4), BigInt::from(-3)));
+    assert_eq!((r("-7/2").trunc(), r("-7/2").round(), r("5/2").round()), (BigInt::from(-3), BigInt::from(-4), BigInt::from(3)));
+    assert!(r("-1/2") < r("1/3") && r("1/3") < r("1/2"));
+
+    let closest = [
+        ("1/3", 1.0 / 3.0),
+        ("-22/7", -22.0 / 7.0),
+        ("0.1", 0.1),
+        ("123456789/1000", 123456.789),
+        ("1/1000000000000000000000000", 1e-24),
+    ];
+    for (input, expected) in closest.iter() {
+        assert_eq!(r(input).to_f64(), *expected, "{}", input);
+    }
+    assert_eq!(Rational::from_f64(0.1, &Settings::default()), Ok(r("1/10")));
+    assert_eq!(Rational::from_f64(-2.5e-7, &Settings::default()), Ok(r("-1/4000000")));
+    assert!(Rational::from_f64(f64::NAN, &Settings::default()).is_err());
+
+    assert!("1/0".parse::<Rational>().is_err());
+    assert!("1.2.3".parse::<Rational>().is_err());
+    assert!(".".parse::<Rational>().is_err());
+    assert!("-.".parse::<Rational>().is_err());
+    assert_eq!("-.5".parse::<Rational>(), Ok(r("-1/2")));
+    assert_eq!("5.".parse::<Rational>(), Ok(r("5")));
+    assert!("x".parse::<Rational>().is_err());
+}
+
+#[test]
+fn test_rational_evaluate() {
+    use crate::{ context::Environment, tree::Tree };
+
+    let exact = |s: &str| Tree::parse(s).unwrap().evaluate_as::<Rational>().map(|r| r.to_string());
+    let cases = [
+        ("1/3 * 3", "1"),
+        ("0.1 + 0.2", "3/10"),
+        ("(54/9)^2-4*7+7", "15"),
+        ("2^100", "1267650600228229401496703205376"),
+        ("2^-3 - 1/8", "0"),
+        ("-(1/3)^3", "-1/27"),
+        ("sqrt(16/9) + cbrt(-27)", "-5/3"),
+        ("hypot(3, 4) * abs(-1/2)", "5/2"),
+        ("max(1/3, 0.3, 1/4) - min(1/3, 0.3)", "1/30"),
+        ("floor(-7/2) + ceil(7/2) + round(5/2) + trunc(-7/2)", "0"),
+        ("sign(-1/3)", "-1"),
+        ("log(1000) + log2(1/8) + ln(1) + exp(0) + sin(0)", "1"),
+        ("(7/2) // (1/3) + (-7/2) % (1/3)", "61/6"),
+        ("25! / 24! - 5!", "-95"),
+        ("0.1234567890123456789 * 10^19", "1234567890123456789"),
+        // Bigger than an f64 can hold
+        ("abs(-(10^400)) / sqrt(10^400) - 10^200", "0"),
+        ("max(10^400, 1) - floor(10^400/3) * 3", "1"),
+        ("log(10^400) + log2(2^-2000) + sign(-(10^400))", "-1601"),
+    ];
+    for (input, expected) in cases.iter() {
+        assert_eq!(exact(input).as_deref(), Ok(*expected), "{}", input);
+    }
+
+    // Irrational results point at whatever produced them
+    let error = |s: &str| Tree::parse(s).unwrap().evaluate_as::<Rational>().unwrap_err();
+    assert_eq!(error("1 + sqrt(2)"), Error::Irrational("sqrt(2)".to_string(), Span::new(4, 11)));
+    assert_eq!(error("2 * pi"), Error::Irrational("pi".to_string(), Span::new(4, 6)));
+    assert_eq!(error("sin(1/2)"), Error::Irrational("sin(1/2)".to_string(), Span::new(0, 8)));
+    assert_eq!(error("(1/2)^(1/3)"), Error::Irrational("(1/2) ^ (1/3)".to_string(), Span::new(1, 10)));
+    assert_eq!(error("1 / (1 - 1)"), Error::DivisionByZero(Span::new(5, 10)));
+    assert_eq!(error("2^(1/2)").to_string(), "2 ^ (1/2) is irrational, so it has no exact value");
+    assert!(matches!(error("ln(0)"), Error::Custom(_, span) if span == Span::new(0, 5)));
+    assert!(matches!(error("inf"), Error::Custom(..)));
+    assert!(matches!(error("(1/2)!"), Error::Custom(_, span) if span == Span::new(1, 6)));
+
+    // Variables and user defined functions still work, by way of f64
+    let mut env = Environment::new();
+    env.set("x", 0.25);
+    env.constants.define("g", 9.81);
+    env.functions.register_fn("double", crate::function::Arity::Fixed(1), |args| Ok(args[0] * 2.0));
+    assert_eq!(Tree::parse("x / 3 + g + double(x)").unwrap().evaluate_as_with::<Rational, _>(&env), "1559/150".parse());
+
+    // Builtins looked up in an environment are just as exact
+    let env = Environment::new();
+    let with_env = |s: &str| Tree::parse(s).unwrap().evaluate_as_with::<Rational, _>(&env).map(|r| r.to_string());
+    assert_eq!(with_env("sqrt(2)"), Err(Error::Irrational("sqrt(2)".to_string(), Span::new(0, 7))));
+    assert_eq!(with_env("sin(1/2)"), Err(Error::Irrational("sin(1/2)".to_string(), Span::new(0, 8))));
+    assert_eq!(with_env("sqrt((10^20 + 1)^2)").as_deref(), Ok("100000000000000000001"));
+
+    // Plain evaluation hasn't changed
+    assert_eq!(Tree::parse("2 * pi").unwrap().evaluate_as::<f64>(), Ok(std::f64::consts::TAU));
+}
@@ -0,0 +1,261 @@
+use pemdrs::{
+    Environment, Tree,
+    diagnostic,
+    export::{ self, Annotate },
+    token::is_identifier,
+};
+
+use std::{
+    env,
+    fs::{ self, OpenOptions },
+    io::{ self, BufRead, Write },
+    path::PathBuf,
+};
+
+
+const HELP: &str = "\
+Enter an expression to evaluate it, e.g. 3 + 4 * 2 / (1 - 5) ^ 2
+
+    x = <expr>     evaluate <expr> and store the result in x
+    ans            the previous result
+    :vars          list every variable
+    :tree <expr>   show how <expr> was parsed
+    :draw <expr>   draw <expr> as a tree, with the value of every part
+    :steps <expr>  evaluate <expr> one step at a time
+    :history       show previously entered lines
+    :help          show this message
+    :quit          leave (so does ctrl-d)
+";
+
+/// What to show the user after they enter a line
+#[derive(Clone, PartialEq, Debug)]
+pub enum Reply {
+    Value(f64),
+    Assigned(String, f64),
+    Text(String),
+    /// Already rendered with `diagnostic::render`
+    Error(String),
+    Nothing,
+    Quit,
+}
+
+/// Everything the REPL remembers between lines
+pub struct Session {
+    env: Environment,
+    history: Vec<String>,
+} impl Session {
+    pub fn new() -> Self {
+        Session {
+            env: Environment::new(),
+            history: Vec::new(),
+        }
+    }
+
+    /// Handle one line of input
+    pub fn handle(&mut self, line: &str) -> Reply {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            return Reply::Nothing;
+        }
+        self.history.push(trimmed.to_string());
+
+        if let Some(command) = trimmed.strip_prefix(':') {
+            return self.command(command);
+        }
+        if trimmed == "exit" || trimmed == "quit" {
+            return Reply::Quit;
+        }
+
+        match line.find('=') {
+            Some(eq) => {
+                let name = line[..eq].trim();
+                if !is_identifier(name) {
+                    return Reply::Error(format!("error: Can't assign to {:?}, expected a variable name\n", name));
+                }
+                // Blank out everything up to the `=` so error spans still line up with what was typed
+                let source = format!("{}{}", " ".repeat(eq + 1), &line[eq + 1..]);
+                match self.evaluate(line, &source) {
+                    Ok(value) => {
+                        self.env.set(name, value);
+                        Reply::Assigned(name.to_string(), value)
+                    },
+                    Err(e) => Reply::Error(e),
+                }
+            },
+            None => match self.evaluate(line, line) {
+                Ok(value) => Reply::Value(value),
+                Err(e) => Reply::Error(e),
+            },
+        }
+    }
+
+    /// Evaluate `source`, rendering errors against `line`, and remember the result as `ans`
+    fn evaluate(&mut self, line: &str, source: &str) -> Result<f64, String> {
+        let value = Tree::parse(source)
+                    .and_then(|tree| tree.evaluate_with(&self.env))
+                    .map_err(|e| diagnostic::render(line, &e))?;
+        self.env.set("ans", value);
+        Ok(value)
+    }
+
+    fn command(&mut self, command: &str) -> Reply {
+        let (name, rest) = match command.find(char::is_whitespace) {
+            Some(idx) => (&command[..idx], command[idx..].trim()),
+            None => (command, ""),
+        };
+
+        match name {
+            "help" | "h" => Reply::Text(HELP.to_string()),
+            "quit" | "q" => Reply::Quit,
+            "vars" => {
+                let mut vars = self.env.variables.iter().collect::<Vec<(&String, &f64)>>();
+                vars.sort_by(|a, b| a.0.cmp(b.0));
+                if vars.is_empty() {
+                    return Reply::Text("No variables yet\n".to_string());
+                }
+                Reply::Text(vars.iter().map(|(name, value)| format!("{} = {}\n", name, value)).collect())
+            },
+            "tree" => match Tree::parse(rest) {
+                Ok(tree) => Reply::Text(format!("{:?}\n", tree)),
+                Err(e) => Reply::Error(diagnostic::render(rest, &e)),
+            },
+            "draw" => match Tree::parse(rest) {
+                Ok(tree) => Reply::Text(export::ascii(&tree, Annotate { value: true, depth: false }, &self.env)),
+                Err(e) => Reply::Error(diagnostic::render(rest, &e)),
+            },
+            "steps" => {
+                let tree = match Tree::parse(rest) {
+                    Ok(tree) => tree,
+                    Err(e) => return Reply::Error(diagnostic::render(rest, &e)),
+                };
+                let mut text = format!("{}\n", tree);
+                for step in tree.steps_with(&self.env) {
+                    match step {
+                        Ok(step) => text.push_str(&format!("{}\n", step)),
+                        Err(e) => return Reply::Error(format!("{}{}", text, diagnostic::render(rest, &e))),
+                    }
+                }
+                Reply::Text(text)
+            },
+            "history" => {
+                // Don't count the `:history` we're handling right now
+                let lines = &self.history[..self.history.len() - 1];
+                Reply::Text(lines.iter().enumerate().map(|(i, line)| format!("{:>4}  {}\n", i + 1, line)).collect())
+            },
+            _ => Reply::Error(format!("error: Unknown command ':{}', try :help\n", name)),
+        }
+    }
+}
+
+/// `~/.pemdrs_history`, if we can figure out where home is
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".pemdrs_history"))
+}
+
+/// Read lines from stdin until the user quits
+pub fn run() -> io::Result<()> {
+    let mut session = Session::new();
+
+    let path = history_path();
+    if let Some(path) = &path {
+        if let Ok(contents) = fs::read_to_string(path) {
+            session.history.extend(contents.lines().map(str::to_string));
+        }
+    }
+    let mut history_file = path.and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
+
+    println!("pemdrs {}, :help for help", env!("CARGO_PKG_VERSION"));
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        stdout.flush()?;
+
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            // ctrl-d
+            println!();
+            return Ok(());
+        }
+
+        let reply = session.handle(&line);
+        if let (Some(file), false) = (history_file.as_mut(), line.trim().is_empty()) {
+            // Losing history isn't worth interrupting the session over
+            let _ = writeln!(file, "{}", line.trim());
+        }
+
+        match reply {
+            Reply::Value(value) => println!("{}", value),
+            Reply::Assigned(name, value) => println!("{} = {}", name, value),
+            Reply::Text(text) => print!("{}", text),
+            Reply::Error(e) => eprint!("{}", e),
+            Reply::Nothing => (),
+            Reply::Quit => return Ok(()),
+        }
+    }
+}
+
+
+#[test]
+fn test_session() {
+    let mut session = Session::new();
+
+    assert_eq!(session.handle("3 + 4 * 2\n"), Reply::Value(11.0));
+    assert_eq!(session.handle("ans * 2"), Reply::Value(22.0));
+    assert_eq!(session.handle("x = 3*4"), Reply::Assigned("x".to_string(), 12.0));
+    assert_eq!(session.handle("ans"), Reply::Value(12.0));
+    assert_eq!(session.handle("x / 2 + ans"), Reply::Value(18.0));
+    assert_eq!(session.handle("  "), Reply::Nothing);
+
+    assert_eq!(session.handle(":vars"), Reply::Text("ans = 18\nx = 12\n".to_string()));
+    assert_eq!(session.handle(":tree 1 + 2 * 3"), Reply::Text("(1 + (2 * 3))\n".to_string()));
+    assert_eq!(session.handle(":draw x * 2"), Reply::Text(concat!(
+        "* (= 24)\n",
+        "├── x (= 12)\n",
+        "└── 2 (= 2)\n",
+    ).to_string()));
+    assert_eq!(session.handle(":steps 2 * (x - 9)"), Reply::Text(concat!(
+        "2 * (x - 9)\n",
+        "2 * 3  (parentheses: x - 9 = 3)\n",
+        "6  (multiply/divide: 2 * 3 = 6)\n",
+    ).to_string()));
+    assert_eq!(session.handle(":history"), Reply::Text(concat!(
+        "   1  3 + 4 * 2\n",
+        "   2  ans * 2\n",
+        "   3  x = 3*4\n",
+        "   4  ans\n",
+        "   5  x / 2 + ans\n",
+        "   6  :vars\n",
+        "   7  :tree 1 + 2 * 3\n",
+        "   8  :draw x * 2\n",
+        "   9  :steps 2 * (x - 9)\n",
+    ).to_string()));
+
+    assert_eq!(session.handle(":q"), Reply::Quit);
+    assert_eq!(session.handle("exit"), Reply::Quit);
+    assert!(matches!(session.handle(":help"), Reply::Text(_)));
+    assert!(matches!(session.handle(":nope"), Reply::Error(_)));
+}
+
+#[test]
+fn test_session_errors() {
+    let mut session = Session::new();
+
+    // Errors in an assignment still point at the right place in the line
+    assert_eq!(
+        session.handle("y = 2 * z"),
+        Reply::Error("error: Unbound variable 'z'\n  |\n  | y = 2 * z\n  |         ^\n".to_string()),
+    );
+    assert!(matches!(session.handle("2 = 3"), Reply::Error(_)));
+    assert!(matches!(session.handle(":tree 1 +"), Reply::Error(_)));
+    assert!(matches!(session.handle(":steps 1 / (1 - 1)"), Reply::Error(_)));
+
+    // Failed assignments don't bind anything, or touch ans
+    assert_eq!(session.handle("7"), Reply::Value(7.0));
+    assert!(matches!(session.handle("y = 1/0"), Reply::Error(_)));
+    assert_eq!(session.handle(":vars"), Reply::Text("ans = 7\n".to_string()));
+}
@@ -0,0 +1,363 @@
+use crate::{
+    constant::builtin_constant,
+    error::{ Error, Result },
+    token::{ Token, Operator, Span, tokenize, shunting_yard },
+    tree::{ Node, Tree },
+};
+
+use std::{
+    convert::TryFrom,
+    fmt::{ self, Display, Formatter },
+    str::FromStr,
+};
+
+
+/// What each pattern variable matched so far, in the order they were first seen
+type Bindings = Vec<(String, Node)>;
+
+/// Variables named after a builtin constant (`pi`, `e`, ...) only match themselves rather than anything
+fn is_pattern_variable(name: &str) -> bool {
+    builtin_constant(name).is_none()
+}
+
+/// Parse one side of a rule, `s` is padded so spans still point into the whole rule
+fn side(s: &str, what: &str, arrow: Span) -> Result<Tree> {
+    let tokens = tokenize(s)?;
+    if tokens.is_empty() {
+        return Err(Error::Expected(what.to_string(), arrow));
+    }
+    Tree::try_from(shunting_yard(tokens)?)
+}
+
+/// Does `node` look like `pattern`, adding whatever pattern variables it binds to `bindings`. `rest` is
+/// called with each way the match can bind them until it accepts one, so a mismatch further along can
+/// undo a choice made here (like which way around a `+` matched). Leaves `bindings` alone if none were accepted
+fn matches(pattern: &Node, node: &Node, bindings: &mut Bindings, rest: &mut dyn FnMut(&mut Bindings) -> bool) -> bool {
+    match &pattern.token {
+        Token::Variable(name) if is_pattern_variable(name) => match bindings.iter().find(|(n, _)| n == name) {
+            Some((_, bound)) => bound == node && rest(bindings),
+            None => {
+                bindings.push((name.clone(), node.clone()));
+                if rest(bindings) {
+                    return true;
+                }
+                bindings.pop();
+                false
+            },
+        },
+        // Literals match by value, so `-1` matches a folded `-1` as well as a negated `1`
+        Token::Value(_) => pattern.as_value() == node.as_value() && rest(bindings),
+        Token::Operator(Operator::USub) if pattern.as_value().is_some() => {
+            pattern.as_value() == node.as_value() && rest(bindings)
+        },
+        &Token::Operator(op) if node.token == pattern.token => match (pattern.left(), node.left()) {
+            (Some(pattern_left), Some(node_left)) => {
+                let (pattern_right, node_right) = (pattern.right().unwrap(), node.right().unwrap());
+                let mut orders = vec![(node_left, node_right)];
+                if matches!(op, Operator::Add | Operator::Mul) {
+                    orders.push((node_right, node_left));
+                }
+                for (left, right) in orders {
+                    if matches(pattern_left, left, bindings, &mut |bindings| matches(pattern_right, right, bindings, rest)) {
+                        return true;
+                    }
+                }
+                false
+            },
+            _ => matches(pattern.right().unwrap(), node.right().unwrap(), bindings, rest),
+        },
+        Token::Function(..) if node.token == pattern.token => matches_all(&pattern.args, &node.args, bindings, rest),
+        token => node.token == *token && rest(bindings),
+    }
+}
+
+/// `matches` for each pattern against the node in the same place, see `matches`
+fn matches_all(patterns: &[Node], nodes: &[Node], bindings: &mut Bindings, rest: &mut dyn FnMut(&mut Bindings) -> bool) -> bool {
+    match (patterns.split_first(), nodes.split_first()) {
+        (Some((pattern, patterns)), Some((node, nodes))) => {
+            matches(pattern, node, bindings, &mut |bindings| matches_all(patterns, nodes, bindings, rest))
+        },
+        (None, None) => rest(bindings),
+        _ => false,
+    }
+}
+
+/// `replacement` with its pattern variables filled in, new nodes span whatever was matched
+fn substitute(replacement: &Node, bindings: &Bindings, span: Span) -> Node {
+    if let Token::Variable(name) = &replacement.token {
+        if let Some((_, bound)) = bindings.iter().find(|(n, _)| n == name) {
+            return bound.clone();
+        }
+    }
+
+    let mut node = Node::new(replacement.token.clone(), span);
+    node.left = replacement.left.as_ref().map(|left| Box::new(substitute(left, bindings, span)));
+    node.right = replacement.right.as_ref().map(|right| Box::new(substitute(right, bindings, span)));
+    node.args = replacement.args.iter().map(|arg| substitute(arg, bindings, span)).collect();
+    node
+}
+
+/// A rewrite rule like `x^0 -> 1` or `a*(b+c) -> a*b + a*c`. Variables in the pattern match any subtree,
+/// if one shows up more than once it has to match the same subtree every time. Constants like `pi` only
+/// match themselves, and `+` and `*` match their operands either way around
+/// ```rust
+/// use pemdrs::{ Tree, rewrite::Rule };
+///
+/// let rule: Rule = "a*(b+c) -> a*b + a*c".parse()?;
+/// let tree = Tree::parse("(x + 1) * 2")?;
+/// assert_eq!(rule.apply(tree.root()).unwrap().to_string(), "2 * x + 2 * 1");
+/// assert_eq!(rule.apply(Tree::parse("x * y")?.root()), None);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rule {
+    pattern: Tree,
+    replacement: Tree,
+} impl Rule {
+    /// Parse `pattern -> replacement`, both sides are infix expressions and the replacement can only use
+    /// variables from the pattern
+    pub fn parse(s: &str) -> Result<Self> {
+        let arrow = match s.find("->") {
+            Some(i) => Span::new(i, i + 2),
+            None => return Err(Error::Expected(
+                "'->' between the pattern and its replacement".to_string(),
+                Span::new(s.len(), s.len()),
+            )),
+        };
+        let pattern = side(&s[..arrow.start], "a pattern before '->'", arrow)?;
+        let padded = format!("{}{}", " ".repeat(arrow.end), &s[arrow.end..]);
+        let replacement = side(&padded, "a replacement after '->'", arrow)?;
+
+        let rule = Rule { pattern, replacement };
+        let bound = rule.pattern.pre_order().filter_map(|node| match &node.token {
+            Token::Variable(name) => Some(name),
+            _ => None,
+        }).collect::<Vec<&String>>();
+        for node in rule.replacement.pre_order() {
+            match &node.token {
+                Token::Variable(name) if is_pattern_variable(name.as_str()) && !bound.contains(&name) => {
+                    return Err(Error::UnboundVariable(name.clone(), node.span));
+                },
+                _ => {},
+            }
+        }
+        Ok(rule)
+    }
+
+    pub fn pattern(&self) -> &Node {
+        &self.pattern.root
+    }
+
+    pub fn replacement(&self) -> &Node {
+        &self.replacement.root
+    }
+
+    /// What `node` itself is rewritten to, `None` if the pattern doesn't match it
+    pub fn apply(&self, node: &Node) -> Option<Node> {
+        let mut bindings = Vec::new();
+        matches(&self.pattern.root, node, &mut bindings, &mut |_| true)
+            .then(|| substitute(&self.replacement.root, &bindings, node.span))
+    }
+} impl FromStr for Rule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Rule> {
+        Rule::parse(s)
+    }
+} impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} -> {}", self.pattern, self.replacement)
+    }
+}
+
+/// Which order a `Rewriter` visits nodes in
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Strategy {
+    /// Children before their parent, so inner expressions are rewritten first
+    BottomUp,
+    /// A parent before its children, which are whatever the parent was rewritten to
+    TopDown,
+}
+
+/// A rule firing once, rewriting `before` to `after`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Fired {
+    /// Index of the rule in `Rewriter::rules`
+    pub rule: usize,
+    pub before: Node,
+    pub after: Node,
+}
+
+/// What a `Rewriter` did to a tree
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rewrite {
+    pub tree: Tree,
+    /// Every rule that fired, in the order they did
+    pub fired: Vec<Fired>,
+}
+
+/// Applies rules all over a tree until none of them match anymore. Each node gets rewritten by the first
+/// rule that matches it, at most once per pass
+/// ```rust
+/// use pemdrs::{ Tree, rewrite::{ Rewriter, Strategy } };
+///
+/// let mut rewriter = Rewriter::parse(&["x^0 -> 1", "x*1 -> x"])?;
+/// rewriter.strategy = Strategy::TopDown;
+///
+/// let rewrite = rewriter.rewrite(&Tree::parse("y * (a + b)^0")?);
+/// assert_eq!(rewrite.tree.to_string(), "y");
+/// assert_eq!(rewrite.fired.iter().map(|fired| fired.rule).collect::<Vec<usize>>(), vec![0, 1]);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+#[derive(Clone, PartialEq, Debug)]
+pub struct Rewriter {
+    /// Tried in order, the first one to match a node wins
+    pub rules: Vec<Rule>,
+    /// Bottom up by default
+    pub strategy: Strategy,
+    /// Passes over the tree before giving up on reaching a fixed point, which rules like `a+b -> b+a` never do
+    pub max_passes: usize,
+} impl Rewriter {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Rewriter { rules, strategy: Strategy::BottomUp, max_passes: 100 }
+    }
+
+    /// Parse every rule with `Rule::parse`
+    pub fn parse(rules: &[&str]) -> Result<Self> {
+        Ok(Rewriter::new(rules.iter().map(|rule| Rule::parse(rule)).collect::<Result<Vec<Rule>>>()?))
+    }
+
+    /// Rewrite `tree` until no rule matches anywhere, or `max_passes` runs out
+    pub fn rewrite(&self, tree: &Tree) -> Rewrite {
+        let mut root = tree.root.clone();
+        let mut fired = Vec::new();
+        for _ in 0..self.max_passes {
+            if !self.pass(&mut root, &mut fired) {
+                break;
+            }
+        }
+        Rewrite { tree: Tree { root }, fired }
+    }
+
+    /// Visit every node once, returns whether anything changed
+    fn pass(&self, node: &mut Node, fired: &mut Vec<Fired>) -> bool {
+        let mut changed = false;
+        if self.strategy == Strategy::BottomUp {
+            for child in node.children_mut() {
+                changed |= self.pass(child, fired);
+            }
+        }
+
+        let rewritten = self.rules.iter().enumerate().find_map(|(i, rule)| rule.apply(node).map(|after| (i, after)));
+        if let Some((rule, after)) = rewritten {
+            fired.push(Fired { rule, before: node.clone(), after: after.clone() });
+            *node = after;
+            changed = true;
+        }
+
+        if self.strategy == Strategy::TopDown {
+            for child in node.children_mut() {
+                changed |= self.pass(child, fired);
+            }
+        }
+        changed
+    }
+}
+
+impl Tree {
+    /// Rewrite the tree with `rewriter`, see `Rewriter::rewrite`
+    pub fn rewrite(&self, rewriter: &Rewriter) -> Rewrite {
+        rewriter.rewrite(self)
+    }
+}
+
+
+#[test]
+fn test_rewrite_rule() {
+    let cases = [
+        // Distribution, either way around
+        ("a*(b+c) -> a*b + a*c", "2 * (x + y)", Some("2 * x + 2 * y")),
+        ("a*(b+c) -> a*b + a*c", "(x + y) * 2", Some("2 * x + 2 * y")),
+        ("a*(b+c) -> a*b + a*c", "2 * (x - y)", None),
+        ("x^0 -> 1", "(a + b)^0", Some("1")),
+        ("x^0 -> 1", "0^a", None),
+        // Repeated variables have to match the same thing
+        ("x - x -> 0", "y*2 - y*2", Some("0")),
+        ("x - x -> 0", "y*2 - 2*y", None),
+        ("x + x -> 2*x", "sin(a) + sin(a)", Some("2 * sin(a)")),
+        ("x*y + x*z -> x*(y+z)", "a*b + c*a", Some("a * (b + c)")),
+        // A binding that makes a later operand fail is undone, and the other way around is tried
+        ("x*y + x*z -> x*(y+z)", "b*a + c*a", Some("a * (b + c)")),
+        ("a*b + a*c -> a*(b+c)", "x*2 + y*2", Some("2 * (x + y)")),
+        // Literals match by value
+        ("x*1 -> x", "1 * y", Some("y")),
+        ("x*1 -> x", "y * 2", None),
+        ("x*-1 -> -x", "y * -1", Some("-y")),
+        // Constants and functions only match themselves
+        ("ln(e) -> 1", "ln(e)", Some("1")),
+        ("ln(e) -> 1", "ln(x)", None),
+        ("sin(pi*n) -> 0", "sin(3*pi)", Some("0")),
+        ("max(a, b) -> a", "max(1, 2, 3)", None),
+        ("sin(x)^2 + cos(x)^2 -> 1", "cos(2*t)^2 + sin(2*t)^2", Some("1")),
+        ("sin(x)^2 + cos(x)^2 -> 1", "cos(2*t)^2 + sin(t*2)^2", None),
+    ];
+    for (rule, input, expected) in cases.iter() {
+        let rule = Rule::parse(rule).unwrap();
+        let rewritten = rule.apply(Tree::parse(input).unwrap().root());
+        assert_eq!(rewritten.map(|node| node.to_string()).as_deref(), *expected, "{} on {}", rule, input);
+    }
+
+    assert_eq!(Rule::parse("a * (b+c)->a*b+a*c").unwrap().to_string(), "a * (b + c) -> a * b + a * c");
+    assert_eq!("x^0 -> -1".parse::<Rule>().unwrap().replacement(), Tree::parse("-1").unwrap().root());
+}
+
+#[test]
+fn test_rewrite_errors() {
+    assert_eq!(
+        Rule::parse("a * b"),
+        Err(Error::Expected("'->' between the pattern and its replacement".to_string(), Span::new(5, 5))),
+    );
+    assert_eq!(Rule::parse(" -> 1"), Err(Error::Expected("a pattern before '->'".to_string(), Span::new(1, 3))));
+    assert_eq!(Rule::parse("x ->  "), Err(Error::Expected("a replacement after '->'".to_string(), Span::new(2, 4))));
+    assert_eq!(Rule::parse("a -> a + b"), Err(Error::UnboundVariable("b".to_string(), Span::new(9, 10))));
+    assert_eq!(Rule::parse("x -> x $ 1"), Err(Error::UnknownCharacters(vec![('$', Span::new(7, 8))])));
+    assert!(Rule::parse("a -> b -> c").is_err());
+    assert!(Rule::parse("(a -> a").is_err());
+
+    // Constants don't need binding
+    assert!(Rule::parse("x -> x * pi").is_ok());
+}
+
+#[test]
+fn test_rewriter() {
+    // Earlier rules win, otherwise the trailing `* 1` would be distributed too
+    let rewriter = Rewriter::parse(&["x*1 -> x", "a*(b+c) -> a*b + a*c"]).unwrap();
+    let rewrite = Tree::parse("x * (y + 1) * 1").unwrap().rewrite(&rewriter);
+    assert_eq!(rewrite.tree.to_string(), "x * y + x");
+    assert_eq!(rewrite.fired.iter().map(|fired| fired.rule).collect::<Vec<usize>>(), vec![1, 0, 0]);
+
+    // Strategy decides which nodes are rewritten first
+    let rewriter = Rewriter::parse(&["f(x) -> x"]).unwrap();
+    let tree = Tree::parse("f(f(1)) + f(2)").unwrap();
+    let order = |rewriter: &Rewriter| rewriter.rewrite(&tree).fired.iter()
+        .map(|fired| fired.before.to_string())
+        .collect::<Vec<String>>();
+    assert_eq!(order(&rewriter), vec!["f(1)", "f(1)", "f(2)"]);
+    let top_down = Rewriter { strategy: Strategy::TopDown, ..rewriter.clone() };
+    assert_eq!(order(&top_down), vec!["f(f(1))", "f(2)", "f(1)"]);
+    assert_eq!(top_down.rewrite(&tree).tree, rewriter.rewrite(&tree).tree);
+    assert_eq!(rewriter.rewrite(&tree).tree.to_string(), "1 + 2");
+
+    // Rules that never settle stop after max_passes
+    let mut rewriter = Rewriter::parse(&["a+b -> b+a"]).unwrap();
+    rewriter.max_passes = 3;
+    let rewrite = rewriter.rewrite(&Tree::parse("x + y").unwrap());
+    assert_eq!(rewrite.fired.len(), 3);
+    assert_eq!(rewrite.tree.to_string(), "y + x");
+
+    // Nothing matching leaves the tree alone
+    let tree = Tree::parse("x - y").unwrap();
+    let rewrite = Rewriter::parse(&["x^0 -> 1"]).unwrap().rewrite(&tree);
+    assert_eq!(rewrite.tree, tree);
+    assert!(rewrite.fired.is_empty());
+}
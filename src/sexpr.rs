@@ -0,0 +1,250 @@
+use crate::{
+    error::{ Error, Result },
+    notation::{ operator, operator_word, special_value, value_word },
+    token::{ Token, Span, is_decimal, is_identifier },
+    tree::{ Node, Tree },
+};
+
+
+/// What an atom outside of operator/function position is, a variable or a value
+fn leaf(atom: &str, span: Span) -> Result<Token> {
+    if let Some(v) = special_value(atom) {
+        return Ok(Token::Value(v));
+    }
+    match atom {
+        _ if is_identifier(atom) => Ok(Token::Variable(atom.to_string())),
+        _ if atom.contains(|c: char| c.is_ascii_digit()) => atom.parse::<f64>()
+            .map(Token::Value)
+            .map_err(|_| Error::Lex(atom.to_string(), span)),
+        _ => Err(Error::Lex(atom.to_string(), span)),
+    }
+}
+
+/// `tree` as an S-expression, operators and functions are lists headed by their name (`~` for unary minus).
+/// Values are written the way they were read, or exactly if they weren't, so parsing the result gives back the same tree
+/// ```rust
+/// use pemdrs::{ Tree, sexpr };
+///
+/// let tree = Tree::parse("3 + 4 * -x")?;
+/// assert_eq!(sexpr::write(&tree), "(+ 3 (* 4 (~ x)))");
+/// assert_eq!(sexpr::parse("(+ 3 (* 4 (~ x)))")?, tree);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
+pub fn write(tree: &Tree) -> String {
+    fn visit(node: &Node, out: &mut String) {
+        match node.token() {
+            Token::Value(v) => match &node.literal {
+                Some(literal) => out.push_str(literal),
+                None => out.push_str(&value_word(*v)),
+            },
+            Token::Variable(name) => out.push_str(name),
+            token => {
+                out.push('(');
+                out.push_str(&match token {
+                    &Token::Operator(op) => operator_word(op),
+                    token => token.to_string(),
+                });
+                for child in node.children() {
+                    out.push(' ');
+                    visit(child, out);
+                }
+                out.push(')');
+            },
+        }
+    }
+
+    let mut out = String::new();
+    visit(tree.root(), &mut out);
+    out
+}
+
+/// Reads an S-expression one atom (or parenthesis) at a time
+struct Parser<'a> {
+    /// Every atom and parenthesis in the source, along with where it is
+    atoms: Vec<(Span, &'a str)>,
+    /// Index of the next one in `atoms`
+    position: usize,
+} impl<'a> Parser<'a> {
+    fn new(source: &'a str) -> Self {
+        let mut atoms = Vec::new();
+        let mut start = None;
+        for (i, c) in source.char_indices() {
+            if c.is_whitespace() || c == '(' || c == ')' {
+                if let Some(start) = start.take() {
+                    atoms.push((Span::new(start, i), &source[start..i]));
+                }
+                if !c.is_whitespace() {
+                    atoms.push((Span::new(i, i + 1), &source[i..i + 1]));
+                }
+            } else if start.is_none() {
+                start = Some(i);
+            }
+        }
+        if let Some(start) = start {
+            atoms.push((Span::new(start, source.len()), &source[start..]));
+        }
+
+        Parser { atoms, position: 0 }
+    }
+
+    fn next(&mut self) -> Option<(Span, &'a str)> {
+        let atom = self.atoms.get(self.position).copied();
+        self.position += 1;
+        atom
+    }
+
+    /// Read the expression starting at the next atom, which has to exist
+    fn expression(&mut self) -> Result<Node> {
+        let (open, atom) = self.next().expect("Something went wrong! (read an expression at the end of the source)");
+        match atom {
+            "(" => {},
+            ")" => return Err(Error::MismatchedParen(open)),
+            atom => {
+                let mut node = Node::new(leaf(atom, open)?, open);
+                if is_decimal(atom) {
+                    node.literal = Some(atom.to_string());
+                }
+                return Ok(node);
+            },
+        }
+
+        let token = match self.next() {
+            Some((span, head)) if head == "(" || head == ")" => {
+                return Err(Error::Expected("an operator or function name".to_string(), span));
+            },
+            Some((span, head)) => match operator(head) {
+                Some(op) => Token::Operator(op),
+                None if is_identifier(head) => Token::Function(head.to_string(), 0),
+                None => return Err(Error::Lex(head.to_string(), span)),
+            },
+            None => return Err(Error::MismatchedParen(open)),
+        };
+
+        let mut operands = Vec::new();
+        loop {
+            match self.atoms.get(self.position) {
+                Some(&(close, ")")) => {
+                    self.position += 1;
+                    return Node::with_operands(token, open.join(close), operands);
+                },
+                Some(_) => operands.push(self.expression()?),
+                None => return Err(Error::MismatchedParen(open)),
+            }
+        }
+    }
+}
+
+/// Parse an S-expression as written by `write`. Operators have to get exactly as many operands as they take,
+/// and every node spans the part of `s` it came from
+pub fn parse(s: &str) -> Result<Tree> {
+    let mut parser = Parser::new(s);
+    if parser.atoms.is_empty() {
+        return Err(Error::EmptyExpression);
+    }
+
+    let root = parser.expression()?;
+    if let Some(extra) = parser.atoms[parser.position..].iter().map(|&(span, _)| span).reduce(Span::join) {
+        return Err(Error::ExtraOperand(extra));
+    }
+    Ok(Tree { root })
+}
+
+impl Tree {
+    /// Parse an S-expression, see `sexpr::parse`
+    pub fn parse_sexpr(s: &str) -> Result<Self> {
+        parse(s)
+    }
+
+    /// The expression as an S-expression, see `sexpr::write`
+    pub fn to_sexpr(&self) -> String {
+        write(self)
+    }
+}
+
+
+#[test]
+fn test_sexpr_write() {
+    let cases = [
+        ("3 + 4 * 2", "(+ 3 (* 4 2))"),
+        ("(54/9)^2-4*7+7", "(+ (- (^ (/ 54 9) 2) (* 4 7)) 7)"),
+        ("2^3^4", "(^ 2 (^ 3 4))"),
+        ("--x", "(~ (~ x))"),
+        ("-2.5 * 0.0000001", "(* (~ 2.5) 0.0000001)"),
+        ("sqrt(x^2 + 1)", "(sqrt (+ (^ x 2) 1))"),
+        ("max(1, 2, y) - atan2(y, x)", "(- (max 1 2 y) (atan2 y x))"),
+        ("inf + nan", "(+ inf nan)"),
+        ("x", "x"),
+    ];
+    for (infix, sexpr) in cases.iter() {
+        assert_eq!(Tree::parse(infix).unwrap().to_sexpr(), *sexpr, "{}", infix);
+    }
+
+    // Folded constants are values rather than variables
+    let mut tree = Tree::parse("-inf * nan + inf").unwrap();
+    tree.fold_constants(&crate::constant::Constants::new());
+    assert_eq!(tree.to_sexpr(), "(+ (* (~ #inf) #nan) #inf)");
+}
+
+#[test]
+fn test_sexpr_round_trip() {
+    use crate::constant::Constants;
+
+    let inputs = [
+        "1+2*3", "(1+2)*3", "1-(2-3)", "2^3^4", "(2^3)^4", "-2^2", "-(2^2)", "3 - -x", "0.1 + 0.2",
+        "1/3", "123456789.123456789 * 2^1000", "sqrt(x^2+1)", "max(1, (2+3)*x)", "min(4) + hypot(3, 4)",
+        "-inf * nan + inf", "7 // x % 3!", "(-x)! - 1", "u(3) - neg(-u)",
+    ];
+    for input in inputs.iter() {
+        let mut tree = Tree::parse(input).unwrap();
+        tree.fold_constants(&Constants::new());
+        let parsed = Tree::parse_sexpr(&tree.to_sexpr()).unwrap();
+        assert_eq!(parsed.to_sexpr(), tree.to_sexpr(), "{}", input);
+        assert_eq!(parsed.to_string(), tree.to_string(), "{}", input);
+    }
+
+    // Functions named like unary minus used to be called stay functions
+    let tree = Tree::parse("u(3)").unwrap();
+    assert_eq!(tree.to_sexpr(), "(u 3)");
+    assert_eq!(Tree::parse_sexpr("(u 3)").unwrap(), tree);
+
+    // Values come back exactly, not just close
+    let tree = Tree::parse_sexpr("(+ 0.1 0.30000000000000004)").unwrap();
+    assert_eq!(tree.root().left().unwrap().token(), &Token::Value(0.1));
+    assert_eq!(tree.root().right().unwrap().token(), &Token::Value(0.1 + 0.2));
+
+    // Spans point into the S-expression
+    let tree = Tree::parse_sexpr(" (* (~ x)    2.5)").unwrap();
+    assert_eq!(tree.root().span(), Span::new(1, 17));
+    assert_eq!(tree.root().left().unwrap().span(), Span::new(4, 9));
+    assert_eq!(tree.root().right().unwrap().span(), Span::new(13, 16));
+    assert_eq!(tree, Tree::parse("-x * 2.5").unwrap());
+
+    // Functions take as many arguments as they're given, it's up to evaluation to complain
+    let tree = Tree::parse_sexpr("(f)").unwrap();
+    assert_eq!(tree.root().token(), &Token::Function("f".to_string(), 0));
+    assert_eq!(Tree::parse_sexpr("(max 1 2 3)").unwrap().evaluate(), Ok(3.0));
+}
+
+#[test]
+fn test_sexpr_errors() {
+    use crate::token::Operator;
+
+    // Operators have to get the right number of operands
+    assert_eq!(Tree::parse_sexpr("(+ 1)"), Err(Error::MissingOperand(Operator::Add, Span::new(0, 5))));
+    assert_eq!(Tree::parse_sexpr("(~)"), Err(Error::MissingOperand(Operator::USub, Span::new(0, 3))));
+    assert_eq!(Tree::parse_sexpr("(* 1 2 3 (+ 4 5))"), Err(Error::ExtraOperand(Span::new(7, 16))));
+    assert_eq!(Tree::parse_sexpr("(~ 1 2)"), Err(Error::ExtraOperand(Span::new(5, 6))));
+
+    assert_eq!(Tree::parse_sexpr(""), Err(Error::EmptyExpression));
+    assert_eq!(Tree::parse_sexpr("(+ 1 2) 3"), Err(Error::ExtraOperand(Span::new(8, 9))));
+    assert_eq!(Tree::parse_sexpr("(+ 1 (* 2 3)"), Err(Error::MismatchedParen(Span::new(0, 1))));
+    assert_eq!(Tree::parse_sexpr("("), Err(Error::MismatchedParen(Span::new(0, 1))));
+    assert_eq!(Tree::parse_sexpr(")"), Err(Error::MismatchedParen(Span::new(0, 1))));
+    assert_eq!(
+        Tree::parse_sexpr("((+) 1 2)"),
+        Err(Error::Expected("an operator or function name".to_string(), Span::new(1, 2))),
+    );
+    assert_eq!(Tree::parse_sexpr("(& 1 2)"), Err(Error::Lex("&".to_string(), Span::new(1, 2))));
+    assert_eq!(Tree::parse_sexpr("(+ 1 2x)"), Err(Error::Lex("2x".to_string(), Span::new(5, 7))));
+    assert_eq!(Tree::parse_sexpr("(+ 1 -inf)"), Err(Error::Lex("-inf".to_string(), Span::new(5, 9))));
+}
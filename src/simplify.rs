@@ -0,0 +1,506 @@
+use crate::{
+    token::{ Token, Operator, Span },
+    tree::{ Node, Tree },
+};
+
+
+/// Passes over the tree before giving up on reaching a fixed point. No rule undoes what another one did,
+/// so this is only a backstop
+const MAX_PASSES: usize = 100;
+
+/// Rewrites tried on every node, in order. Each one gives back a simpler node that evaluates to the same
+/// thing wherever the original evaluates to a finite number, or `None` if it doesn't apply
+const RULES: &[fn(&Node) -> Option<Node>] = &[
+    fold_constants,
+    double_negation,
+    identity,
+    annihilator,
+    like_terms,
+    merge_powers,
+];
+
+/// Operands of a binary operator, `None` for everything else
+fn operands(node: &Node) -> Option<(Operator, &Node, &Node)> {
+    match (&node.token, node.left(), node.right()) {
+        (&Token::Operator(op), Some(left), Some(right)) => Some((op, left, right)),
+        _ => None,
+    }
+}
+
+fn is_value(node: &Node, v: f64) -> bool {
+    node.as_value() == Some(v)
+}
+
+/// An operator applied to nothing but literals becomes a literal, as long as the result is finite
+fn fold_constants(node: &Node) -> Option<Node> {
+    let value = match (&node.token, node.left(), node.right()?) {
+        // Already a (negative) literal
+        (Token::Operator(Operator::USub), None, right) if matches!(right.token, Token::Value(_)) => return None,
+        (Token::Operator(Operator::USub), None, right) => -right.as_value()?,
+        (Token::Operator(Operator::Factorial), None, right) => Operator::Factorial.evaluate(0.0, right.as_value()?).ok()?,
+        (&Token::Operator(op), Some(left), right) => op.evaluate(left.as_value()?, right.as_value()?).ok()?,
+        _ => return None,
+    };
+    value.is_finite().then(|| Node::value(value, node.span))
+}
+
+/// `--x` is `x`
+fn double_negation(node: &Node) -> Option<Node> {
+    match (&node.token, &node.right()?.token) {
+        (Token::Operator(Operator::USub), Token::Operator(Operator::USub)) => node.right()?.right().cloned(),
+        _ => None,
+    }
+}
+
+/// Operations that don't do anything, like `x + 0`, `x * 1` or `x ^ 1`
+fn identity(node: &Node) -> Option<Node> {
+    let (op, left, right) = operands(node)?;
+    Some(match op {
+        Operator::Add if is_value(left, 0.0) => right.clone(),
+        Operator::Add | Operator::Sub if is_value(right, 0.0) => left.clone(),
+        Operator::Sub if is_value(left, 0.0) => Node::negative(right.clone(), node.span),
+        Operator::Mul if is_value(left, 1.0) => right.clone(),
+        Operator::Mul | Operator::Div | Operator::Pow if is_value(right, 1.0) => left.clone(),
+        Operator::Mul if is_value(left, -1.0) => Node::negative(right.clone(), node.span),
+        Operator::Mul | Operator::Div if is_value(right, -1.0) => Node::negative(left.clone(), node.span),
+        _ => return None,
+    })
+}
+
+/// Operations that come out the same no matter the other operand, like `x * 0` or `x ^ 0`
+fn annihilator(node: &Node) -> Option<Node> {
+    let (op, left, right) = operands(node)?;
+    Some(match op {
+        Operator::Mul if is_value(left, 0.0) || is_value(right, 0.0) => Node::value(0.0, node.span),
+        Operator::Div if is_value(left, 0.0) && !is_value(right, 0.0) => Node::value(0.0, node.span),
+        Operator::Pow if is_value(right, 0.0) || is_value(left, 1.0) => Node::value(1.0, node.span),
+        _ => return None,
+    })
+}
+
+/// Something raised to a numeric power, as part of a `Product`
+#[derive(Clone)]
+struct Factor {
+    base: Node,
+    exponent: f64,
+    /// Whether any of it came from the right hand side of a `/`. Only those can go back under a division,
+    /// `0 ^ -1` is infinity but `1 / 0` doesn't evaluate at all
+    divided: bool,
+}
+
+/// A product flattened out into a numeric coefficient and factors raised to numeric powers,
+/// `2 * x / (4 * x ^ 3)` is `2 / 4` with factors `x ^ 1` and `x ^ -3`
+struct Product {
+    numerator: f64,
+    denominator: f64,
+    factors: Vec<Factor>,
+} impl Product {
+    fn of(node: &Node) -> Self {
+        let mut product = Product { numerator: 1.0, denominator: 1.0, factors: Vec::new() };
+        product.push(node, 1.0, false);
+        product
+    }
+
+    /// Multiply by `node ^ exponent`, where `exponent` is 1 or -1
+    fn push(&mut self, node: &Node, exponent: f64, divided: bool) {
+        match operands(node) {
+            Some((Operator::Mul, left, right)) => {
+                self.push(left, exponent, divided);
+                self.push(right, exponent, divided);
+                return;
+            },
+            Some((Operator::Div, left, right)) => {
+                self.push(left, exponent, divided);
+                self.push(right, -exponent, true);
+                return;
+            },
+            Some((Operator::Pow, base, power)) => {
+                if let Some(power) = power.as_value().filter(|p| p.is_finite()) {
+                    self.factors.push(Factor { base: base.clone(), exponent: power * exponent, divided });
+                    return;
+                }
+            },
+            _ => {},
+        }
+
+        match (node.as_value(), &node.token) {
+            // Dividing by zero stays a factor so it still fails to evaluate
+            (Some(v), _) if v.is_finite() && !(v == 0.0 && exponent < 0.0) => {
+                if exponent > 0.0 {
+                    self.numerator *= v;
+                } else {
+                    self.denominator *= v;
+                }
+            },
+            (None, Token::Operator(Operator::USub)) => {
+                self.numerator = -self.numerator;
+                self.push(node.right().expect("Something went wrong! (unary minus with no operand)"), exponent, divided);
+            },
+            _ => self.factors.push(Factor { base: node.clone(), exponent, divided }),
+        }
+    }
+
+    /// Identifies the factors regardless of their order, so `x * y` and `y * x` have the same key
+    fn key(&self) -> String {
+        let mut keys = self.factors.iter()
+                        .map(|factor| format!("{:?}^{}", factor.base, factor.exponent))
+                        .collect::<Vec<String>>();
+        keys.sort();
+        keys.join(" ")
+    }
+
+    fn coefficient(&self) -> f64 {
+        self.numerator / self.denominator
+    }
+
+    /// Combine factors with the same base, keeping them in the order they first appeared
+    fn merge(&mut self) {
+        let mut merged: Vec<Factor> = Vec::with_capacity(self.factors.len());
+        for factor in self.factors.drain(..) {
+            match merged.iter_mut().find(|other| other.base == factor.base) {
+                Some(other) => {
+                    other.exponent += factor.exponent;
+                    other.divided |= factor.divided;
+                },
+                None => merged.push(factor),
+            }
+        }
+        self.factors = merged.into_iter().filter(|factor| factor.exponent != 0.0).collect();
+    }
+
+    /// The product as a tree, a coefficient in front and anything that was divided by underneath
+    fn build(&self, span: Span) -> Node {
+        let (mut numerator, mut denominator) = (self.numerator, self.denominator);
+        if numerator.fract() == 0.0 && denominator.fract() == 0.0 && numerator.abs().max(denominator.abs()) < 2f64.powi(53) {
+            let divisor = gcd(numerator.abs() as u64, denominator.abs() as u64).max(1) as f64;
+            numerator /= divisor * denominator.signum();
+            denominator = denominator.abs() / divisor;
+        }
+        if numerator == 0.0 {
+            return Node::value(0.0, span);
+        }
+
+        let power = |base: &Node, exponent: f64| if exponent == 1.0 {
+            base.clone()
+        } else {
+            Node::binary(Operator::Pow, base.clone(), Node::value(exponent, span), span)
+        };
+        let chain = |nodes: Vec<Node>| nodes.into_iter().reduce(|a, b| Node::binary(Operator::Mul, a, b, span));
+
+        let (mut top, mut bottom) = (Vec::new(), Vec::new());
+        for factor in self.factors.iter() {
+            if factor.exponent < 0.0 && factor.divided {
+                bottom.push(power(&factor.base, -factor.exponent));
+            } else {
+                top.push(power(&factor.base, factor.exponent));
+            }
+        }
+        if numerator.abs() != 1.0 || top.is_empty() {
+            top.insert(0, Node::value(numerator.abs(), span));
+        }
+        if denominator != 1.0 {
+            bottom.insert(0, Node::value(denominator, span));
+        }
+
+        let top = chain(top).expect("Something went wrong! (product with an empty numerator)");
+        let node = match chain(bottom) {
+            Some(bottom) => Node::binary(Operator::Div, top, bottom, span),
+            None => top,
+        };
+        if numerator < 0.0 {
+            negate(node, span)
+        } else {
+            node
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+/// `-node`, folded into a leading coefficient if there is one
+fn negate(node: Node, span: Span) -> Node {
+    match operands(&node) {
+        Some((op @ Operator::Mul, left, right)) | Some((op @ Operator::Div, left, right)) if left.as_value().is_some() => {
+            let coefficient = -left.as_value().expect("Something went wrong! (checked for a coefficient)");
+            Node::binary(op, Node::value(coefficient, span), right.clone(), span)
+        },
+        _ => Node::negative(node, span),
+    }
+}
+
+/// `x * x ^ 2 / x` is `x ^ 2`, and constants scattered through a product are multiplied together in front
+fn merge_powers(node: &Node) -> Option<Node> {
+    match operands(node) {
+        Some((Operator::Mul, ..)) | Some((Operator::Div, ..)) => {
+            let mut product = Product::of(node);
+            product.merge();
+            let built = product.build(node.span);
+            (built != *node).then_some(built)
+        },
+        // (x ^ a) ^ n is x ^ (a * n) as long as n is a whole number
+        Some((Operator::Pow, inner, power)) => {
+            let n = power.as_value().filter(|n| n.fract() == 0.0)?;
+            match operands(inner) {
+                Some((Operator::Pow, base, a)) => {
+                    let exponent = a.as_value()? * n;
+                    Some(Node::binary(Operator::Pow, base.clone(), Node::value(exponent, node.span), node.span))
+                },
+                _ => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// A term of a sum, along with whether it's added or subtracted
+struct Term {
+    node: Node,
+    sign: f64,
+    product: Product,
+}
+
+fn terms(node: &Node, sign: f64, terms: &mut Vec<Term>) {
+    match (&node.token, node.left(), node.right()) {
+        (Token::Operator(Operator::Add), Some(left), Some(right)) => {
+            self::terms(left, sign, terms);
+            self::terms(right, sign, terms);
+        },
+        (Token::Operator(Operator::Sub), Some(left), Some(right)) => {
+            self::terms(left, sign, terms);
+            self::terms(right, -sign, terms);
+        },
+        (Token::Operator(Operator::USub), None, Some(right)) if node.as_value().is_none() => self::terms(right, -sign, terms),
+        _ => terms.push(Term { node: node.clone(), sign, product: Product::of(node) }),
+    }
+}
+
+/// `2 * x + y - x` is `x + y`, terms that only differ by their coefficient are added together
+fn like_terms(node: &Node) -> Option<Node> {
+    if !matches!(node.token, Token::Operator(Operator::Add) | Token::Operator(Operator::Sub)) {
+        return None;
+    }
+    let span = node.span;
+
+    let mut all = Vec::new();
+    terms(node, 1.0, &mut all);
+    let count = all.len();
+
+    // Group terms by key, remembering the first of each group so it can be left alone if nothing joins it
+    let mut groups: Vec<(String, Vec<Term>)> = Vec::new();
+    for term in all {
+        let key = term.product.key();
+        match groups.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, group)) => group.push(term),
+            None => groups.push((key, vec![term])),
+        }
+    }
+    if groups.len() == count {
+        return None;
+    }
+
+    let mut signed = Vec::new();
+    for (_, mut group) in groups {
+        if group.len() == 1 {
+            let term = group.pop().expect("Something went wrong! (empty group of terms)");
+            signed.push((term.sign, term.node));
+            continue;
+        }
+
+        let coefficient = group.iter().map(|term| term.sign * term.product.coefficient()).sum::<f64>();
+        if coefficient == 0.0 {
+            continue;
+        }
+        let product = &mut group[0].product;
+        product.numerator = coefficient.abs();
+        product.denominator = 1.0;
+        signed.push((coefficient.signum(), product.build(span)));
+    }
+
+    let mut signed = signed.into_iter();
+    let first = match signed.next() {
+        Some((sign, node)) if sign < 0.0 => negate(node, span),
+        Some((_, node)) => node,
+        None => return Some(Node::value(0.0, span)),
+    };
+    Some(signed.fold(first, |sum, (sign, node)| {
+        Node::binary(if sign < 0.0 { Operator::Sub } else { Operator::Add }, sum, node, span)
+    }))
+}
+
+/// Apply the first rule that fits to every node, children first. Returns whether anything changed
+fn pass(node: &mut Node) -> bool {
+    let mut changed = false;
+    for child in node.children_mut() {
+        changed |= pass(child);
+    }
+    if let Some(simpler) = RULES.iter().find_map(|rule| rule(node)) {
+        *node = simpler;
+        changed = true;
+    }
+    changed
+}
+
+impl Tree {
+    /// An equivalent, simpler expression. Folds constants, drops operations that don't do anything,
+    /// cancels double negatives, collects like terms and merges powers of the same base until none of
+    /// that changes anything. Wherever the original evaluates to a finite number, so does the result
+    /// ```rust
+    /// use pemdrs::Tree;
+    ///
+    /// let tree = Tree::parse("0 + y * 1 - --x^1 + 2 * 3 * x")?;
+    /// assert_eq!(tree.simplify().to_string(), "y + 5 * x");
+    ///
+    /// let tree = Tree::parse("x * x ^ 2 / x * (3 + 4)")?;
+    /// assert_eq!(tree.simplify().to_string(), "7 * x ^ 2");
+    /// # Ok::<(), pemdrs::Error>(())
+    /// ```
+    pub fn simplify(&self) -> Tree {
+        let mut root = self.root.clone();
+        for _ in 0..MAX_PASSES {
+            if !pass(&mut root) {
+                break;
+            }
+        }
+        Tree { root }
+    }
+}
+
+
+#[test]
+fn test_simplify() {
+    let cases = [
+        // Constant folding
+        ("2 * 3 + 4", "10"),
+        ("x + 2 * 3", "x + 6"),
+        ("-(2 + 3)", "-5"),
+        ("1 / 0", "1 / 0"),
+        ("2 ^ 2000", "2 ^ 2000"),
+        // Identities and annihilators
+        ("x * 1", "x"),
+        ("0 + y", "y"),
+        ("a ^ 1", "a"),
+        ("x / 1 - 0", "x"),
+        ("0 - x", "-x"),
+        ("x * -1", "-x"),
+        ("x * 0", "0"),
+        ("0 / x", "0"),
+        ("x ^ 0 + 1 ^ x", "2"),
+        ("1 / 0 + x * 0", "1 / 0"),
+        // Double negation
+        ("--a", "a"),
+        ("----a", "a"),
+        ("---a", "-a"),
+        ("x - -y", "x - -y"),
+        // Like terms
+        ("x + x", "2 * x"),
+        ("2*x + 3*x - x", "4 * x"),
+        ("x - x", "0"),
+        ("x + y - x", "y"),
+        ("x*y + y*x", "2 * x * y"),
+        ("x/2 + x/2", "x"),
+        ("2 + x + 3", "5 + x"),
+        ("sin(x) + 2*sin(x)", "3 * sin(x)"),
+        ("x + 1", "x + 1"),
+        ("-x - x", "-2 * x"),
+        // Power merging
+        ("x * x", "x ^ 2"),
+        ("x^2 * x^3", "x ^ 5"),
+        ("x^3 / x", "x ^ 2"),
+        ("x / x", "1"),
+        ("(x^2)^3", "x ^ 6"),
+        ("(x^0.5)^2", "x"),
+        ("(x^2)^0.5", "(x ^ 2) ^ 0.5"),
+        ("2 * x * 3", "6 * x"),
+        ("2 * x / 4", "x / 2"),
+        ("x * y / (y * 2)", "x / 2"),
+        ("-x * 3 * x", "-3 * x ^ 2"),
+        ("x / y", "x / y"),
+        // Everything at once
+        ("(x + 0) * (x * 1) + x^2 - --(2*x*x)", "0"),
+        ("sqrt(0 + x * x) * 2 * (y - y + 1)", "2 * sqrt(x ^ 2)"),
+    ];
+    for (input, expected) in cases.iter() {
+        assert_eq!(Tree::parse(input).unwrap().simplify().to_string(), *expected, "{}", input);
+    }
+}
+
+/// A random expression over `x`, `y` and small integers
+#[cfg(test)]
+fn random_tree(rng: &mut crate::generate::Rng, depth: u16) -> String {
+    if depth == 0 || rng.below(4) == 0 {
+        return match rng.below(3) {
+            0 => "x".to_string(),
+            1 => "y".to_string(),
+            _ => rng.range(0, 4).to_string(),
+        };
+    }
+
+    match rng.below(9) {
+        0 => format!("-({})", random_tree(rng, depth - 1)),
+        // Small whole exponents, so there's something left that's defined
+        1 => format!("({}) ^ {}", random_tree(rng, depth - 1), rng.range(-2, 3)),
+        2 => format!("sin({})", random_tree(rng, depth - 1)),
+        n => {
+            let op = ["+", "-", "*", "/", "+", "*"][n as usize - 3];
+            format!("({}) {} ({})", random_tree(rng, depth - 1), op, random_tree(rng, depth - 1))
+        },
+    }
+}
+
+#[test]
+fn test_simplify_preserves_value() {
+    use crate::{ check::Tolerance, generate::Rng };
+    use std::collections::HashMap;
+
+    let tolerance = Tolerance::new(1e-9, 1e-9);
+    let mut rng = Rng::new(2021);
+    let mut checked = 0;
+    for _ in 0..2000 {
+        let input = random_tree(&mut rng, 5);
+        let tree = Tree::parse(&input).unwrap();
+        let simplified = tree.simplify();
+
+        // Simplifying again doesn't do anything
+        assert_eq!(simplified.simplify(), simplified, "{} simplified to {}", input, simplified);
+
+        for _ in 0..5 {
+            let (x, y) = (rng.range(-30, 30) as f64 / 10.0, rng.range(-30, 30) as f64 / 10.0);
+            let at = |x: f64, y: f64| -> HashMap<&str, f64> { [("x", x), ("y", y)].iter().cloned().collect() };
+            let env = at(x, y);
+
+            // Skip points where the original is ill-conditioned, e.g. dividing by rounding error in something
+            // that should be zero. The answer comes out huge, or nudging the inputs changes it completely,
+            // and no equivalent expression can be expected to match it
+            let expected = match tree.evaluate_with(&env) {
+                Ok(v) if v.is_finite() && v.abs() < 1e8 => v,
+                _ => continue,
+            };
+            let nudged = tree.evaluate_with(&at(x * (1.0 + 1e-12), y * (1.0 - 1e-12)));
+            if !nudged.is_ok_and(|nudged| Tolerance::new(1e-9, 1e-6).matches(nudged, expected)) {
+                continue;
+            }
+            let actual = simplified.evaluate_with(&env);
+            assert!(
+                actual.as_ref().is_ok_and(|&actual| tolerance.matches(actual, expected)),
+                "{} simplified to {}, at {:?} that's {:?} instead of {}", input, simplified, env, actual, expected,
+            );
+            checked += 1;
+        }
+    }
+    // Make sure the test is actually testing something
+    assert!(checked > 2000, "only {} checks", checked);
+}
+
+#[test]
+fn test_simplify_folds_problems() {
+    use crate::generate::{ Generator, Constraints, Difficulty };
+
+    let mut generator = Generator::new(Constraints::new(Difficulty::Hard), 21);
+    for problem in generator.worksheet(200).unwrap() {
+        let simplified = problem.tree.simplify();
+        assert_eq!(simplified.evaluate(), Ok(problem.answer), "{} simplified to {}", problem.tree, simplified);
+        assert!(simplified.root().is_leaf() || simplified.root().as_value().is_some(), "{} simplified to {}", problem.tree, simplified);
+    }
+}
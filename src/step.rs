@@ -0,0 +1,268 @@
+use crate::{
+    context::Context,
+    error::Result,
+    token::{ Token, Operator, precedence },
+    tree::{ Node, Tree },
+};
+
+use std::fmt::{ Display, Formatter, Result as fmt_Result };
+
+
+/// Which part of PEMDAS a `Step` carried out
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rule {
+    Parentheses,
+    Function,
+    Factorial,
+    Negation,
+    Exponent,
+    MultiplyDivide,
+    AddSubtract,
+} impl Rule {
+    /// The rule for reducing `node` on its own, ignoring any parentheses around it
+    fn of(node: &Node) -> Self {
+        match node.token() {
+            Token::Function(..) => Rule::Function,
+            Token::Operator(Operator::Factorial) => Rule::Factorial,
+            Token::Operator(Operator::USub) => Rule::Negation,
+            Token::Operator(Operator::Pow) => Rule::Exponent,
+            Token::Operator(Operator::Mul) | Token::Operator(Operator::Div)
+                | Token::Operator(Operator::IntDiv) | Token::Operator(Operator::Mod) => Rule::MultiplyDivide,
+            _ => Rule::AddSubtract,
+        }
+    }
+} impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        let name = match self {
+            Rule::Parentheses => "parentheses",
+            Rule::Function => "function",
+            Rule::Factorial => "factorial",
+            Rule::Negation => "negation",
+            Rule::Exponent => "exponent",
+            Rule::MultiplyDivide => "multiply/divide",
+            Rule::AddSubtract => "add/subtract",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// One reduction made while evaluating an expression by hand, see `Tree::steps`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Step {
+    pub rule: Rule,
+    /// The part of the expression that was evaluated
+    pub reduced: Node,
+    /// What it evaluated to
+    pub value: f64,
+    /// The whole expression after the reduction
+    pub tree: Tree,
+} impl Display for Step {
+    /// `36 - 4 * 7 + 7  (exponent: 6 ^ 2 = 36)`
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}  ({}: {} = {})", self.tree, self.rule, self.reduced, self.value)
+    }
+}
+
+/// Reduces an expression one node at a time, see `Tree::steps`
+pub struct Steps<'c, C: ?Sized> {
+    /// `None` once there's nothing left to do, or something went wrong
+    current: Option<Tree>,
+    ctx: &'c C,
+} impl<'c, C> Iterator for Steps<'c, C>
+where
+    C: Context + ?Sized,
+{
+    type Item = Result<Step>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tree = self.current.as_mut()?;
+        let (path, rule) = match next_reduction(&tree.root) {
+            Some(next) => next,
+            None => {
+                self.current = None;
+                return None;
+            },
+        };
+
+        let node = path.iter().fold(&mut tree.root, |node, &i| node.children_mut().nth(i).unwrap());
+        let value = match node.evaluate(self.ctx) {
+            Ok(value) => value,
+            Err(e) => {
+                self.current = None;
+                return Some(Err(e));
+            },
+        };
+        let reduced = std::mem::replace(node, Node::new(Token::Value(value), node.span()));
+        fold_negative_literals(&mut tree.root);
+
+        Some(Ok(Step { rule, reduced, value, tree: tree.clone() }))
+    }
+}
+
+/// Negating a literal prints the same as the negative literal, so it doesn't count as a step
+fn fold_negative_literals(node: &mut Node) {
+    for child in node.children_mut() {
+        fold_negative_literals(child);
+    }
+
+    if node.token == Token::Operator(Operator::USub) {
+        if let Some(&Token::Value(v)) = node.right().map(Node::token) {
+            if !v.is_sign_negative() {
+                *node = Node::new(Token::Value(-v), node.span());
+            }
+        }
+    }
+}
+
+/// Path (as indices into `Node::children`) to the node that should be reduced next, and the rule that reduces it.
+///
+/// Only nodes whose children are all leaves can be reduced. Of those, the one nested inside the most
+/// parentheses goes first, then the one with the highest precedence, then the leftmost
+fn next_reduction(root: &Node) -> Option<(Vec<usize>, Rule)> {
+    // Node, its path, how many parentheses it's inside of and whether it's directly wrapped in them
+    let mut stack = vec![(root, Vec::new(), 0_usize, false)];
+    let mut best: Option<((usize, u32), Vec<usize>, Rule)> = None;
+
+    while let Some((node, path, depth, wrapped)) = stack.pop() {
+        if node.is_leaf() {
+            continue;
+        }
+
+        if node.children().all(Node::is_leaf) {
+            let binding = match node.token() {
+                Token::Operator(_) => precedence(node.token()),
+                _ => u32::MAX,
+            };
+            let key = (depth, binding);
+            if best.as_ref().is_none_or(|(best, ..)| key > *best) {
+                let rule = if wrapped { Rule::Parentheses } else { Rule::of(node) };
+                best = Some((key, path.clone(), rule));
+            }
+            continue;
+        }
+
+        // Arguments are inside the call's parentheses, but aren't wrapped in a set of their own
+        let (left_parens, right_parens) = node.parenthesized();
+        let children = node.children().enumerate().map(|(i, child)| {
+            let parens = match node.token() {
+                Token::Function(..) => false,
+                _ if node.left().is_some() && i == 0 => left_parens,
+                _ => right_parens,
+            };
+            let inside = match node.token() {
+                Token::Function(..) => 1,
+                _ => parens as usize,
+            };
+            let mut path = path.clone();
+            path.push(i);
+            (child, path, depth + inside, parens)
+        }).collect::<Vec<_>>();
+        // Reversed so the leftmost child is visited first, ties go to whichever was seen first
+        stack.extend(children.into_iter().rev());
+    }
+
+    best.map(|(_, path, rule)| (path, rule))
+}
+
+impl Tree {
+    /// Evaluate the expression the way it'd be done by hand, one reduction at a time. Each step evaluates a
+    /// single operator or function call whose operands are already values, working inside parentheses first
+    /// and then in order of precedence, left to right
+    /// ```rust
+    /// use pemdrs::{ Tree, step::Rule };
+    ///
+    /// let steps = Tree::parse("(54/9)^2-4*7+7")?.steps().collect::<pemdrs::Result<Vec<_>>>()?;
+    /// let shown = steps.iter().map(|step| step.tree.to_string()).collect::<Vec<String>>();
+    /// assert_eq!(shown, vec!["6 ^ 2 - 4 * 7 + 7", "36 - 4 * 7 + 7", "36 - 28 + 7", "8 + 7", "15"]);
+    /// assert_eq!(steps[0].rule, Rule::Parentheses);
+    /// assert_eq!(steps[1].rule, Rule::Exponent);
+    /// # Ok::<(), pemdrs::Error>(())
+    /// ```
+    pub fn steps(&self) -> Steps<'static, ()> {
+        self.steps_with(&())
+    }
+
+    /// Same as `steps`, looking up variables in `ctx`. Variables are substituted when the operator using them is
+    pub fn steps_with<'c, C: Context + ?Sized>(&self, ctx: &'c C) -> Steps<'c, C> {
+        let mut tree = self.clone();
+        fold_negative_literals(&mut tree.root);
+        Steps { current: Some(tree), ctx }
+    }
+}
+
+
+#[test]
+fn test_steps() {
+    use crate::error::Error;
+    use crate::token::Span;
+
+    let trace = |input: &str| {
+        Tree::parse(input).unwrap()
+            .steps()
+            .map(|step| step.map(|step| (step.tree.to_string(), step.rule)))
+            .collect::<Result<Vec<(String, Rule)>>>()
+    };
+    let expected = |steps: &[(&str, Rule)]| {
+        Ok(steps.iter().map(|&(tree, rule)| (tree.to_string(), rule)).collect::<Vec<(String, Rule)>>())
+    };
+
+    assert_eq!(trace("(54/9)^2-4*7+7"), expected(&[
+        ("6 ^ 2 - 4 * 7 + 7", Rule::Parentheses),
+        ("36 - 4 * 7 + 7", Rule::Exponent),
+        ("36 - 28 + 7", Rule::MultiplyDivide),
+        ("8 + 7", Rule::AddSubtract),
+        ("15", Rule::AddSubtract),
+    ]));
+
+    // Innermost parentheses first, and PEMDAS applies inside them too
+    assert_eq!(trace("(4-(-2^2-4))*(-2-8)"), expected(&[
+        ("(4 - (4 - 4)) * (-2 - 8)", Rule::Exponent),
+        ("(4 - 0) * (-2 - 8)", Rule::Parentheses),
+        ("4 * (-2 - 8)", Rule::Parentheses),
+        ("4 * -10", Rule::Parentheses),
+        ("-40", Rule::MultiplyDivide),
+    ]));
+
+    // Right associative operators reduce from the right
+    assert_eq!(trace("2^3^2"), expected(&[("2 ^ 9", Rule::Exponent), ("512", Rule::Exponent)]));
+
+    assert_eq!(trace("1 + sqrt(3*3 + 16)"), expected(&[
+        ("1 + sqrt(9 + 16)", Rule::MultiplyDivide),
+        ("1 + sqrt(25)", Rule::AddSubtract),
+        ("1 + 5", Rule::Function),
+        ("6", Rule::AddSubtract),
+    ]));
+
+    assert_eq!(trace("1 + 3!^2 % 10 // 2"), expected(&[
+        ("1 + 6 ^ 2 % 10 // 2", Rule::Factorial),
+        ("1 + 36 % 10 // 2", Rule::Exponent),
+        ("1 + 6 // 2", Rule::MultiplyDivide),
+        ("1 + 3", Rule::MultiplyDivide),
+        ("4", Rule::AddSubtract),
+    ]));
+
+    assert_eq!(trace("--3"), expected(&[("3", Rule::Negation)]));
+    assert_eq!(trace("-3"), expected(&[]));
+    assert_eq!(trace("7"), expected(&[]));
+
+    // Every step is a valid expression with the same value
+    let tree = Tree::parse("((-78/-13)^3-8)*-4+4").unwrap();
+    for step in tree.steps() {
+        let step = step.unwrap();
+        assert_eq!(Tree::parse(&step.tree.to_string()).unwrap().evaluate(), tree.evaluate());
+    }
+
+    // Errors stop the trace
+    let mut steps = Tree::parse("2*3 + 1/(2-2)").unwrap().steps();
+    assert_eq!(steps.next().unwrap().unwrap().to_string(), "2 * 3 + 1 / 0  (parentheses: 2 - 2 = 0)");
+    assert_eq!(steps.next().unwrap().unwrap().tree.to_string(), "6 + 1 / 0");
+    assert_eq!(steps.next(), Some(Err(Error::DivisionByZero(Span::new(9, 12)))));
+    assert_eq!(steps.next(), None);
+
+    let mut env = std::collections::HashMap::new();
+    env.insert("x", 2.0);
+    let tree = Tree::parse("x^2 + y").unwrap();
+    let mut steps = tree.steps_with(&env);
+    assert_eq!(steps.next().unwrap().unwrap().tree.to_string(), "4 + y");
+    assert_eq!(steps.next(), Some(Err(Error::UnboundVariable("y".to_string(), Span::new(6, 7)))));
+}
@@ -1,5 +1,11 @@
 
+use crate::{
+    error::{ Error, Result },
+    number::Number,
+};
+
 use std::{
+    convert::TryFrom,
     str::{ FromStr },
     // string::{ ToString },
     fmt::{ Debug, Display, Formatter, Result as fmt_Result }
@@ -12,64 +18,84 @@ pub enum Operator {
     Sub,
     Mul,
     Div,
+    /// `//`, division rounded down
+    IntDiv,
+    /// `%`, the remainder that goes with `IntDiv`
+    Mod,
     Pow,
     USub,
+    /// `!`, written after its operand
+    Factorial,
 } impl Operator {
+    /// The operator written as `c`, `IntDiv` is two characters so it never comes out of this
     pub fn from_char(c: char) -> Option<Self> {
         match c {
             '+' => Some(Self::Add),
             '-' => Some(Self::Sub),
             '*' => Some(Self::Mul),
             '/' => Some(Self::Div),
+            '%' => Some(Self::Mod),
             '^' => Some(Self::Pow),
             'u' => Some(Self::USub),
+            '!' => Some(Self::Factorial),
             _ => None,
         }
     }
 
-    pub fn to_char(&self) -> char {
+    pub fn symbol(&self) -> &'static str {
         match self {
-            Self::Add => '+',
-            Self::Sub => '-',
-            Self::Mul => '*',
-            Self::Div => '/',
-            Self::Pow => '^',
-            Self::USub => 'u',
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::IntDiv => "//",
+            Self::Mod => "%",
+            Self::Pow => "^",
+            Self::USub => "u",
+            Self::Factorial => "!",
         }
     }
 
-    pub fn evaluate(&self, left: f64, right: f64) -> f64 {
+    /// Whether the operator takes a single operand, which nodes keep on the right
+    pub fn is_unary(&self) -> bool {
+        matches!(self, Self::USub | Self::Factorial)
+    }
+
+    /// Apply the operator to `left` and `right` (`left` is ignored for unary operators)
+    ///
+    /// Errors have an empty span, it's up to the caller to point them at the source
+    pub fn evaluate<N: Number>(&self, left: N, right: N) -> Result<N> {
+        self.evaluate_with_settings(left, right, &N::Settings::default())
+    }
+
+    /// Same as `evaluate`, with `settings` for the operators that need them
+    pub fn evaluate_with_settings<N: Number>(&self, left: N, right: N, settings: &N::Settings) -> Result<N> {
         match self {
-            Self::Add => left + right,
-            Self::Sub => left - right,
-            Self::Mul => left * right,
-            Self::Div => {
-                if right == 0.0 {
-                    panic!("Divide by zero");
-                } else {
-                    left / right
-                }
-            },
-            Self::Pow => left.powf(right),
-            Self::USub => -right,
+            Self::Add => left.add(&right),
+            Self::Sub => left.sub(&right),
+            Self::Mul => left.mul(&right),
+            Self::Div => left.div(&right),
+            Self::IntDiv => left.int_div(&right),
+            Self::Mod => left.rem(&right),
+            Self::Pow => left.pow(&right, settings),
+            Self::USub => Ok(right.neg()),
+            Self::Factorial => right.factorial(settings),
         }
     }
 } impl FromStr for Operator {
-    type Err = &'static str;
-
-    fn from_str(s: &str) -> Result<Operator, Self::Err> {
-        match Operator::from_char(s
-                                .chars()
-                                .next()
-                                .expect("Cannot parse empty string into operator")
-                            ) {
-            Some(n) => Ok(n),
-            None => Err("Unknown operator"),
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Operator> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(Operator::from_char), chars.next()) {
+            _ if s == "//" => Ok(Operator::IntDiv),
+            (Some(n), None) => Ok(n),
+            _ => Err(Error::Lex(s.to_string(), Span::new(0, s.len()))),
         }
     }
-} impl ToString for Operator {
-    fn to_string(&self) -> String {
-        format!("{}", self.to_char())
+} impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}", self.symbol())
     }
 }
 
@@ -93,49 +119,61 @@ pub enum Paren {
         }
     }
 } impl FromStr for Paren {
-    type Err = &'static str;
-
-    fn from_str(s: &str) -> Result<Paren, Self::Err> {
-        match Paren::from_char(s
-                                .chars()
-                                .next()
-                                .expect("Cannot parse empty string into paren")
-                            ) {
-            Some(n) => Ok(n),
-            None => Err("Unknown literal"),
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Paren> {
+        let mut chars = s.chars();
+        match (chars.next().and_then(Paren::from_char), chars.next()) {
+            (Some(n), None) => Ok(n),
+            _ => Err(Error::Lex(s.to_string(), Span::new(0, s.len()))),
         }
     }
-} impl ToString for Paren {
-    fn to_string(&self) -> String {
-        format!("{}", self.to_char())
+} impl Display for Paren {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}", self.to_char())
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug)]
 pub enum Token {
     Operator(Operator),
     Value(f64),
     Paren(Paren),
+    /// A name to be looked up when the expression is evaluated, see `context::Context`
+    Variable(String),
+    /// A function name and how many arguments it was called with.
+    /// `tokenize` doesn't know the count yet and leaves it at 0, `shunting_yard` fills it in
+    Function(String, usize),
+    /// Separates function arguments
+    Comma,
 } impl Token {
+    /// Shorthand for `literal.parse::<Token>()`, for literals known to be valid
+    ///
+    /// # Panics
+    /// Panics if `literal` isn't a valid token, use `str::parse` to handle that case
     pub fn new(literal: &str) -> Self {
         match literal.parse::<Token>() {
             Ok(t) => t,
-            Err(e) => panic!(e)
+            Err(e) => panic!("{}", e)
         }
     }
 
 } impl FromStr for Token {
-    type Err = &'static str;
+    type Err = Error;
 
-    fn from_str(s: &str) -> Result<Token, Self::Err> {
+    fn from_str(s: &str) -> Result<Token> {
         if let Ok(v) = s.parse::<f64>() {
             Ok(Token::Value(v))
         } else if let Ok(op) = s.parse::<Operator>() {
             Ok(Token::Operator(op))
         } else if let Ok(p) = s.parse::<Paren>() {
             Ok(Token::Paren(p))
+        } else if s == "," {
+            Ok(Token::Comma)
+        } else if is_identifier(s) {
+            Ok(Token::Variable(s.to_string()))
         } else {
-            Err("Unexpected literal")
+            Err(Error::Lex(s.to_string(), Span::new(0, s.len())))
         }
     } 
 
@@ -147,20 +185,120 @@ pub enum Token {
                 Token::Operator(op) => op.to_string(),
                 Token::Paren(p) => p.to_string(),
                 Token::Value(v) => v.to_string(),
+                Token::Variable(name) => name.clone(),
+                Token::Function(name, _) => name.clone(),
+                Token::Comma => ",".to_string(),
             }
         })
     }
-} impl Into<f64> for Token {
-    fn into(self) -> f64 {
-        match self {
-            Token::Value(v) => v,
-            _ => panic!("Attempt to coerce non-value Token to f64"),
+} impl TryFrom<Token> for f64 {
+    type Error = Error;
+
+    fn try_from(token: Token) -> Result<f64> {
+        match token {
+            Token::Value(v) => Ok(v),
+            t => {
+                let literal = t.to_string();
+                let span = Span::new(0, literal.len());
+                Err(Error::Lex(literal, span))
+            },
         }
     }
 }
 
-/// Parse the string `s` into a Token stream
+/// Byte range `start..end` of a token (or group of tokens) in the source string
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+} impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Smallest span covering both `self` and `other`
+    pub fn join(self, other: Span) -> Self {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// A token along with where it came from in the source string
+///
+/// Compares equal to a plain `Token` if the tokens match, regardless of the span
+#[derive(Clone, PartialEq, Debug)]
+pub struct Spanned {
+    pub token: Token,
+    pub span: Span,
+    /// How a value was written, for numbers that can hold more than an `f64` (see `Number::from_literal`)
+    pub literal: Option<String>,
+} impl Spanned {
+    pub fn new(token: Token, span: Span) -> Self {
+        Spanned { token, span, literal: None }
+    }
+
+    /// A number literal like `12.5`, which keeps its text as well as its value
+    pub fn literal(literal: &str, span: Span) -> Result<Self> {
+        match literal.parse::<f64>() {
+            Ok(v) => Ok(Spanned { token: Token::Value(v), span, literal: Some(literal.to_string()) }),
+            Err(_) => Err(Error::Lex(literal.to_string(), span)),
+        }
+    }
+} impl PartialEq<Token> for Spanned {
+    fn eq(&self, other: &Token) -> bool {
+        self.token == *other
+    }
+} impl PartialEq<Spanned> for Token {
+    fn eq(&self, other: &Spanned) -> bool {
+        *self == other.token
+    }
+} impl Display for Spanned {
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        write!(f, "{}", self.token)
+    }
+}
+
+/// How `tokenize_with` treats characters that aren't part of the grammar
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum LexMode {
+    /// Unknown characters are an error, whitespace separates tokens
+    #[default]
+    Strict,
+    /// Anything unknown (whitespace included) is silently dropped, so `2x+3` lexes as `23`.
+    /// Only here for callers that relied on the old behaviour
+    Legacy,
+}
+
+const GRAMMAR_CHARS: &str = ".0123456789/*-+^%!()";
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `s` is a valid variable name, a letter or `_` followed by letters, digits or `_`
+pub fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if is_identifier_start(c) => chars.all(is_identifier_continue),
+        _ => false,
+    }
+}
+
+/// Whether `s` is a plain decimal like `-12.5`, which every `Number` can read digit for digit
+pub(crate) fn is_decimal(s: &str) -> bool {
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    digits.chars().any(|c| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && digits.matches('.').count() <= 1
+}
+
+/// Parse the string `s` into a Token stream, rejecting anything that isn't part of the grammar
 /// ```rust
+/// # use pemdrs::token::{ Token, tokenize };
 /// let tokens = vec![
 ///     Token::new("("),
 ///     Token::new("10"),
@@ -168,72 +306,141 @@ pub enum Token {
 ///     Token::new("5"),
 ///     Token::new(")"),
 /// ];
-/// assert!(tokens == tokenize("(10+5)"));
+/// assert!(tokens == tokenize("(10+5)")?);
+/// # Ok::<(), pemdrs::Error>(())
 /// ```
-pub fn tokenize(s: &str) -> Vec<Token> {
+pub fn tokenize(s: &str) -> Result<Vec<Spanned>> {
+    tokenize_with(s, LexMode::Strict)
+}
+
+/// Parse the string `s` into a Token stream, see `LexMode`
+pub fn tokenize_with(s: &str, mode: LexMode) -> Result<Vec<Spanned>> {
     // /*DEBUG:*/ eprintln!("Begin tokenization");
-    let mut buffer = String::new();
-    let mut tokens: Vec<Token> = Vec::new();
+    if mode == LexMode::Strict {
+        // Digits other than 0-9 (like `²` or `½`) only count as part of a name, never on their own
+        let mut in_identifier = false;
+        let unknown = s.char_indices()
+                    .filter(|&(_, c)| {
+                        in_identifier = if in_identifier { is_identifier_continue(c) } else { is_identifier_start(c) };
+                        !in_identifier && !c.is_whitespace() && c != ',' && !GRAMMAR_CHARS.contains(c)
+                    })
+                    .map(|(idx, c)| (c, Span::new(idx, idx + c.len_utf8())))
+                    .collect::<Vec<(char, Span)>>();
+        if !unknown.is_empty() {
+            return Err(Error::UnknownCharacters(unknown));
+        }
+    }
 
-    let cleaned = s.chars()
-                .filter(|&c| ".0123456789/*-+^()".contains(c))
-                .collect::<String>();
+    let mut buffer = String::new();
+    let mut buffer_span = Span::default();
+    let mut tokens: Vec<Spanned> = Vec::new();
 
-    let mut idx = 0;
+    let commit = |buffer: &str, span: Span| -> Result<Spanned> {
+        // /*DEBUG:*/ eprintln!("Commit number: {}", buffer);
+        Spanned::literal(buffer, span)
+    };
 
-    while let Some(c) = cleaned.chars().nth(idx) {
+    let mut chars = s.char_indices().peekable();
+    while let Some((idx, c)) = chars.next() {
         // /*DEBUG:*/ eprint!("C: {}, IDX: {} -> ", c, idx);
-        
-        // check for unary operators (will always be first or directly following another operator (thanks greg!))
-        // unwrap or will make this evalute true if it's the first item in the expression
-        match tokens.last().unwrap_or(&Token::Operator(Operator::Add)) {
-            Token::Operator(_) | Token::Paren(Paren::Left) => {
-                if buffer.is_empty() && c == '-' && buffer.is_empty() {
-                    // /*DEBUG:*/ eprintln!("Unary minus");
-                    tokens.push(Token::Operator(Operator::USub));
-                    idx += 1;
-                    continue;
+
+        // Variables and function calls are a strict mode thing, legacy mode drops letters and commas
+        if mode == LexMode::Strict && (is_identifier_start(c) || c == ',') {
+            if !buffer.is_empty() {
+                tokens.push(commit(&buffer, buffer_span)?);
+                buffer.clear();
+            }
+
+            if c == ',' {
+                // /*DEBUG:*/ eprintln!("Comma");
+                tokens.push(Spanned::new(Token::Comma, Span::new(idx, idx + 1)));
+                continue;
+            }
+
+            let mut name = c.to_string();
+            let mut end = idx + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if !is_identifier_continue(c) {
+                    break;
                 }
-            },
-            _ => ()
+                name.push(c);
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            // A name directly followed by a paren is a function call
+            let token = match chars.peek() {
+                Some(&(_, '(')) => Token::Function(name, 0),
+                _ => Token::Variable(name),
+            };
+            // /*DEBUG:*/ eprintln!("Identifier: {:?}", token);
+            tokens.push(Spanned::new(token, Span::new(idx, end)));
+            continue;
         }
 
+        if !GRAMMAR_CHARS.contains(c) {
+            // Only whitespace can get here in strict mode, and it ends whatever number we were reading
+            if mode == LexMode::Strict && !buffer.is_empty() {
+                tokens.push(commit(&buffer, buffer_span)?);
+                buffer.clear();
+            }
+            continue;
+        }
+        let span = Span::new(idx, idx + c.len_utf8());
+
         // c is a number (0-9 or .), push it to the buffer
-        if c.is_numeric() || c == '.' {
+        if c.is_ascii_digit() || c == '.' {
             // /*DEBUG:*/ eprintln!("Number: {}", c);
+            if buffer.is_empty() {
+                buffer_span = span;
+            } else {
+                buffer_span.end = span.end;
+            }
             buffer.push(c);
+            continue;
         }
+
         // if c is not a number, but there is something in the buffer, push the buffer to output
-        else if !buffer.is_empty() {
-            // /*DEBUG:*/ eprintln!("Commit number: {}", buffer);
-            tokens.push(buffer.parse().expect(&format!("Failed to parse buffer: {:?}", buffer)));
-            buffer = String::new();
-            idx -= 1;
+        if !buffer.is_empty() {
+            tokens.push(commit(&buffer, buffer_span)?);
+            buffer.clear();
+        }
+
+        // check for unary operators (will always be first or directly following another operator (thanks greg!))
+        // unwrap or will make this evalute true if it's the first item in the expression
+        match tokens.last().map(|t| &t.token).unwrap_or(&Token::Operator(Operator::Add)) {
+            // Factorial comes after its operand, so a minus after one is subtraction
+            Token::Operator(op) if c == '-' && *op == Operator::Factorial => (),
+            Token::Operator(_) | Token::Paren(Paren::Left) | Token::Comma if c == '-' => {
+                // /*DEBUG:*/ eprintln!("Unary minus");
+                tokens.push(Spanned::new(Token::Operator(Operator::USub), span));
+                continue;
+            },
+            _ => ()
         }
+
         // Handle operators and parens normally
-        else if let Some(op) = Operator::from_char(c) {
+        if c == '/' && matches!(chars.peek(), Some(&(_, '/'))) {
+            chars.next();
+            tokens.push(Spanned::new(Token::Operator(Operator::IntDiv), Span::new(idx, idx + 2)));
+        } else if let Some(op) = Operator::from_char(c) {
             // /*DEBUG:*/ eprintln!("Operator: {:?}", op);
-            tokens.push(Token::Operator(op));
+            tokens.push(Spanned::new(Token::Operator(op), span));
         } else if let Some(p) = Paren::from_char(c) {
             // /*DEBUG:*/ eprintln!("Paren: {:?}", p);
-            tokens.push(Token::Paren(p));
+            tokens.push(Spanned::new(Token::Paren(p), span));
         }
-
-        idx += 1;
     }
     
     if !buffer.is_empty() {
-        tokens.push(buffer
-            .parse()
-            .expect("Failed to parse token from buffer")
-        );
+        tokens.push(commit(&buffer, buffer_span)?);
     }
     // /*DEBUG*/ eprintln!("End tokenization\n");
 
-    tokens
+    Ok(tokens)
 }
 
-fn precedence(token: &Token) -> u32 {
+/// How tightly an operator binds, non-operators get 0
+pub(crate) fn precedence(token: &Token) -> u32 {
     match token {
         Token::Operator(o) => {
             match o {
@@ -241,8 +448,11 @@ fn precedence(token: &Token) -> u32 {
                 Operator::Sub => 2,
                 Operator::Mul => 3,
                 Operator::Div => 3,
+                Operator::IntDiv => 3,
+                Operator::Mod => 3,
                 Operator::Pow => 4,
                 Operator::USub => 5,
+                Operator::Factorial => 6,
             }
         },
         _ => 0,
@@ -250,7 +460,7 @@ fn precedence(token: &Token) -> u32 {
 }
 
 #[derive(Copy, Clone, PartialEq)]
-enum OperatorAssociativity {
+pub(crate) enum OperatorAssociativity {
     Left, Right
 } impl From<Token> for OperatorAssociativity {
     fn from(token: Token) -> Self {
@@ -271,24 +481,32 @@ where
 
 
 /// Takes an infix notated token stream and converts it to postfix notation
-pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
+///
+/// Function calls come out as a single `Token::Function` after their arguments, with the argument count filled in
+pub fn shunting_yard(tokens: Vec<Spanned>) -> Result<Vec<Spanned>> {
     // /*DEBUG:*/ eprintln!("Begin reverse poilsh conversion");
-    let mut output: Vec<Token> = Vec::new();
-    let mut opstack: Vec<Token> = Vec::new();
+    let mut output: Vec<Spanned> = Vec::new();
+    let mut opstack: Vec<Spanned> = Vec::new();
+    // Number of commas seen so far in each function call we're currently inside of
+    let mut calls: Vec<usize> = Vec::new();
+    let mut previous: Option<Token> = None;
     
-    for token in tokens {
+    for spanned in tokens {
+        let token = spanned.token.clone();
         // /*DEBUG:*/ eprintln!("\nCurrent state:\n\tOperator stack: {:?}\n\tOutput: {:?}", opstack, output);
         // /*DEBUG:*/ eprint!("Encountered {:?} -> ", token);
-        match token {
-            Token::Value(_v) => {
-                // /*DEBUG:*/ eprintln!("pushing token with value {} to the output", _v);
-                output.push(token);
+        match &token {
+            Token::Value(_) | Token::Variable(_) => {
+                // /*DEBUG:*/ eprintln!("pushing operand {} to the output", token);
+                output.push(spanned);
             },
+            // Postfix, so its operand is already complete and nothing on the stack binds tighter
+            Token::Operator(Operator::Factorial) => output.push(spanned),
             Token::Operator(_op) => {
                 let p = precedence(&token);
                 // /*DEBUG:*/ eprintln!("Operator {:?} -> Popping tokens from stack: ", _op);
                 while !opstack.is_empty() {
-                    match opstack.last() {
+                    match opstack.last().map(|t| &t.token) {
                         Some(&Token::Paren(_)) => {
                             // /*DEBUG:*/ eprintln!("\tEncountered paren, breaking");
                             break
@@ -315,49 +533,92 @@ pub fn shunting_yard(tokens: Vec<Token>) -> Vec<Token> {
                         _ => unreachable!()
                     }
                 }
-                opstack.push(token.clone());
+                opstack.push(spanned);
+            },
+            Token::Function(..) => {
+                // /*DEBUG:*/ eprintln!("Function, push to operator stack");
+                calls.push(0);
+                opstack.push(spanned);
+            },
+            Token::Comma => {
+                // /*DEBUG:*/ eprintln!("Comma, popping operator stack to output until we see a left paren");
+                if let Some(Token::Comma) | Some(Token::Paren(Paren::Left)) = previous {
+                    return Err(Error::EmptyArgument(spanned.span));
+                }
+                loop {
+                    match opstack.last().map(|t| &t.token) {
+                        Some(Token::Paren(Paren::Left)) => break,
+                        Some(_) => output.push(opstack.pop().unwrap()),
+                        None => return Err(Error::MisplacedComma(spanned.span)),
+                    }
+                }
+                // The paren has to be the one opening a function call, `(1, 2)` on its own means nothing
+                match opstack.len().checked_sub(2).map(|i| &opstack[i].token) {
+                    Some(Token::Function(..)) => *calls.last_mut().unwrap() += 1,
+                    _ => return Err(Error::MisplacedComma(spanned.span)),
+                }
             },
             Token::Paren(p) => {
                 // /*DEBUG:*/ eprint!("Encountered paren -> ");
                 match p {
                     Paren::Left => {
                         // /*DEBUG:*/ eprintln!("Left paren, push to operator stack");
-                        opstack.push(token.clone())
+                        opstack.push(spanned)
                     },
                     Paren::Right => {
                         // /*DEBUG:*/ eprintln!("Right paren, popping operator stack to output until we see a left paren");
-                        while !opstack.is_empty() {
-                            if let Some(top) = opstack.pop() {
-                                match top {
-                                    Token::Paren(Paren::Left) => {
-                                        // /*DEBUG:*/ eprintln!("Encountered left paren, breaking");
-                                        break
-                                    },
-                                    o => {
-                                        // /*DEBUG:*/ eprintln!("\tpopping {} to the output", o);
-                                        output.push(o)
-                                    },
-                                }
-                            } else {
-                                unreachable!()
+                        loop {
+                            match opstack.pop() {
+                                Some(Spanned { token: Token::Paren(Paren::Left), .. }) => {
+                                    // /*DEBUG:*/ eprintln!("Encountered left paren, breaking");
+                                    break
+                                },
+                                Some(o) => {
+                                    // /*DEBUG:*/ eprintln!("\tpopping {} to the output", o);
+                                    output.push(o)
+                                },
+                                // Ran out of operators before finding the matching left paren
+                                None => return Err(Error::MismatchedParen(spanned.span)),
+                            }
+                        }
+
+                        // Closing a function call, now we know how many arguments it got
+                        if let Some(Token::Function(..)) = opstack.last().map(|t| &t.token) {
+                            let commas = calls.pop().unwrap();
+                            let arity = match previous {
+                                Some(Token::Paren(Paren::Left)) => 0,
+                                Some(Token::Comma) => return Err(Error::EmptyArgument(spanned.span)),
+                                _ => commas + 1,
+                            };
+                            let mut function = opstack.pop().unwrap();
+                            if let Token::Function(_, n) = &mut function.token {
+                                *n = arity;
                             }
+                            function.span = function.span.join(spanned.span);
+                            // /*DEBUG:*/ eprintln!("\tcalling {} with {} arguments", function, arity);
+                            output.push(function);
                         }
                     },
                 }
             },
         }
 
+        previous = Some(token);
     }
 
     // /*DEBUG:*/ eprintln!("Clearing operator stack");
     while let Some(top) = opstack.pop() {
         // /*DEBUG:*/ eprintln!("Popping {} to output", top);
+        if let Token::Paren(_) = top.token {
+            // Left paren that never got closed
+            return Err(Error::MismatchedParen(top.span));
+        }
         output.push(top);
     }
 
     // /*DEBUG:*/ eprintln!("\nEnd reverse poilsh conversion\n");
 
-    output
+    Ok(output)
 }
 
 #[test]
@@ -370,7 +631,7 @@ fn test_tokenize() {
         Token::new("5"),
         Token::new(")"),
     ];
-    assert!(tokens == tokenize("(10+5)"));
+    assert!(tokens == tokenize("(10+5)").unwrap());
 
     // Complex
     let tokens = vec![
@@ -392,7 +653,7 @@ fn test_tokenize() {
         Token::new("2"),
         Token::new(")"),
     ];
-    assert!(tokens == tokenize("((10.0 * 2) / 4 + (2.5 * 4) * 2)"));
+    assert!(tokens == tokenize("((10.0 * 2) / 4 + (2.5 * 4) * 2)").unwrap());
 
     // No parens
     let tokens = vec![
@@ -400,7 +661,7 @@ fn test_tokenize() {
         Token::new("+"),
         Token::new("5"),
     ];
-    assert!(tokens == tokenize("10.0 + 5"));
+    assert!(tokens == tokenize("10.0 + 5").unwrap());
 
     // Unary minus
     let tokens = vec![
@@ -410,13 +671,27 @@ fn test_tokenize() {
         Token::new("u"),
         Token::new("5"),
     ];
-    assert!(tokens == tokenize("-10 + -5"));
+    assert!(tokens == tokenize("-10 + -5").unwrap());
+
+    // Integer division, modulo and factorial, a minus after a factorial subtracts
+    let tokens = vec![
+        Token::new("7"),
+        Token::new("//"),
+        Token::new("2"),
+        Token::new("%"),
+        Token::new("3"),
+        Token::new("!"),
+        Token::new("-"),
+        Token::new("u"),
+        Token::new("1"),
+    ];
+    assert!(tokens == tokenize("7 // 2 % 3! - -1").unwrap());
     
 }   
 
 #[test]
 fn test_shunting_yard() {
-    let tokens = tokenize("3 + 4 * 2 / ( 1 - 5 ) ^ 2 ^ 3");
+    let tokens = tokenize("3 + 4 * 2 / ( 1 - 5 ) ^ 2 ^ 3").unwrap();
     let expected = vec![
         Token::new("3"),
         Token::new("4"),
@@ -433,9 +708,9 @@ fn test_shunting_yard() {
         Token::new("+"),
     ];
 
-    assert_eq!(shunting_yard(tokens), expected);
+    assert_eq!(shunting_yard(tokens).unwrap(), expected);
 
-    let tokens = tokenize("((15 / (7 -(1 + 1))) * 3) - (2 + (1 + 1))");
+    let tokens = tokenize("((15 / (7 -(1 + 1))) * 3) - (2 + (1 + 1))").unwrap();
     let expected = vec![
         Token::new("15"),
         Token::new("7"),
@@ -454,45 +729,210 @@ fn test_shunting_yard() {
         Token::new("-"),
     ];
 
-    assert_eq!(shunting_yard(tokens), expected);
+    assert_eq!(shunting_yard(tokens).unwrap(), expected);
 
     // unary minus
-    let tokens = tokenize("-10 + 5");
+    let tokens = tokenize("-10 + 5").unwrap();
     let expected = vec![
         Token::new("10"),
         Token::new("u"),
         Token::new("5"),
         Token::new("+"),
     ];
-    assert_eq!(shunting_yard(tokens), expected);
+    assert_eq!(shunting_yard(tokens).unwrap(), expected);
+
+    // factorial binds tighter than anything, unary minus included
+    let tokens = tokenize("-2^3! % 4").unwrap();
+    let expected = vec![
+        Token::new("2"),
+        Token::new("u"),
+        Token::new("3"),
+        Token::new("!"),
+        Token::new("^"),
+        Token::new("4"),
+        Token::new("%"),
+    ];
+    assert_eq!(shunting_yard(tokens).unwrap(), expected);
 
 }
 
 #[test]
 fn test_operator_evaluate() {
 
-    assert_eq!(Operator::Add.evaluate(1.0 , 10.0), 1.0  + 10.0);
-    assert_eq!(Operator::Add.evaluate(15.0, 15.0), 15.0 + 15.0);
-    assert_eq!(Operator::Add.evaluate(10.0, 20.0), 10.0 + 20.0);
+    assert_eq!(Operator::Add.evaluate(1.0 , 10.0).unwrap(), 1.0  + 10.0);
+    assert_eq!(Operator::Add.evaluate(15.0, 15.0).unwrap(), 15.0 + 15.0);
+    assert_eq!(Operator::Add.evaluate(10.0, 20.0).unwrap(), 10.0 + 20.0);
+
+    assert_eq!(Operator::Sub.evaluate(1.0 , 10.0).unwrap(), 1.0  - 10.0);
+    assert_eq!(Operator::Sub.evaluate(15.0, 15.0).unwrap(), 15.0 - 15.0);
+    assert_eq!(Operator::Sub.evaluate(10.0, 20.0).unwrap(), 10.0 - 20.0);
+
+    assert_eq!(Operator::Mul.evaluate(1.0 , 10.0).unwrap(), 1.0  * 10.0);
+    assert_eq!(Operator::Mul.evaluate(15.0, 15.0).unwrap(), 15.0 * 15.0);
+    assert_eq!(Operator::Mul.evaluate(10.0, 20.0).unwrap(), 10.0 * 20.0);
+
+    assert_eq!(Operator::Div.evaluate(1.0 , 10.0).unwrap(), 1.0  / 10.0);
+    assert_eq!(Operator::Div.evaluate(15.0, 15.0).unwrap(), 15.0 / 15.0);
+    assert_eq!(Operator::Div.evaluate(10.0, 20.0).unwrap(), 10.0 / 20.0);
+
+    assert_eq!(Operator::Pow.evaluate(1.0 , 10.0).unwrap(), 1.0_f64.powf(10.0));
+    assert_eq!(Operator::Pow.evaluate(15.0, 15.0).unwrap(), 15.0_f64.powf(15.0));
+    assert_eq!(Operator::Pow.evaluate(10.0, 20.0).unwrap(), 10.0_f64.powf(20.0));
+
+    assert_eq!(Operator::USub.evaluate(0.0 , 10.0).unwrap(), -10.0);
+    assert_eq!(Operator::USub.evaluate(0.0, 15.0).unwrap(), -15.0);
+    assert_eq!(Operator::USub.evaluate(0.0, 10.0).unwrap(), -10.0);
+
+    assert_eq!(Operator::IntDiv.evaluate(7.0, 2.0).unwrap(), 3.0);
+    assert_eq!(Operator::IntDiv.evaluate(-7.0, 2.0).unwrap(), -4.0);
+    assert_eq!(Operator::Mod.evaluate(7.0, 3.0).unwrap(), 1.0);
+    assert_eq!(Operator::Mod.evaluate(-7.0, 3.0).unwrap(), 2.0);
+    assert_eq!(Operator::Mod.evaluate(7.0, -3.0).unwrap(), -2.0);
+    assert_eq!(Operator::Mod.evaluate(7.5, 2.0).unwrap(), 1.5);
+
+    assert_eq!(Operator::Factorial.evaluate(0.0, 0.0).unwrap(), 1.0);
+    assert_eq!(Operator::Factorial.evaluate(0.0, 10.0).unwrap(), 3628800.0);
+    assert_eq!(Operator::Factorial.evaluate(0.0, 171.0).unwrap(), f64::INFINITY);
+    assert!(Operator::Factorial.evaluate(0.0, 2.5).is_err());
+    assert!(Operator::Factorial.evaluate(0.0, -1.0).is_err());
+
+    assert_eq!(Operator::Div.evaluate(10.0, 0.0), Err(Error::DivisionByZero(Span::default())));
+    assert_eq!(Operator::IntDiv.evaluate(10.0, 0.0), Err(Error::DivisionByZero(Span::default())));
+    assert_eq!(Operator::Mod.evaluate(10.0, 0.0), Err(Error::DivisionByZero(Span::default())));
+
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(tokenize("2 * 1..5"), Err(Error::Lex("1..5".to_string(), Span::new(4, 8))));
+    assert_eq!("10".parse::<Operator>(), Err(Error::Lex("10".to_string(), Span::new(0, 2))));
+    assert_eq!("".parse::<Paren>(), Err(Error::Lex("".to_string(), Span::new(0, 0))));
+
+    assert_eq!(shunting_yard(tokenize("(1 + 2").unwrap()), Err(Error::MismatchedParen(Span::new(0, 1))));
+    assert_eq!(shunting_yard(tokenize("1 + 2)").unwrap()), Err(Error::MismatchedParen(Span::new(5, 6))));
+}
+
+#[test]
+fn test_tokenize_strict() {
+    let unknown = vec![
+        ('$', Span::new(1, 2)),
+        ('#', Span::new(6, 7)),
+    ];
+    assert_eq!(tokenize("2$+3 1#000"), Err(Error::UnknownCharacters(unknown)));
+
+    // Superscripts, vulgar fractions and non-ASCII digits aren't numbers, but can be part of a name
+    assert_eq!(tokenize("2²"), Err(Error::UnknownCharacters(vec![('²', Span::new(1, 3))])));
+    assert_eq!(tokenize("½+1"), Err(Error::UnknownCharacters(vec![('½', Span::new(0, 2))])));
+    assert_eq!(tokenize("1٣+1"), Err(Error::UnknownCharacters(vec![('٣', Span::new(1, 3))])));
+    assert_eq!(tokenize("x² + 1").unwrap()[0].token, Token::Variable("x²".to_string()));
+
+    // Whitespace separates numbers instead of being dropped
+    let tokens = vec![
+        Token::new("1"),
+        Token::new("2"),
+    ];
+    assert!(tokens == tokenize("1 2").unwrap());
 
-    assert_eq!(Operator::Sub.evaluate(1.0 , 10.0), 1.0  - 10.0);
-    assert_eq!(Operator::Sub.evaluate(15.0, 15.0), 15.0 - 15.0);
-    assert_eq!(Operator::Sub.evaluate(10.0, 20.0), 10.0 - 20.0);
+    // Legacy mode keeps the old filtering behaviour
+    let tokens = vec![
+        Token::new("23"),
+    ];
+    assert!(tokens == tokenize_with("2x 3", LexMode::Legacy).unwrap());
 
-    assert_eq!(Operator::Mul.evaluate(1.0 , 10.0), 1.0  * 10.0);
-    assert_eq!(Operator::Mul.evaluate(15.0, 15.0), 15.0 * 15.0);
-    assert_eq!(Operator::Mul.evaluate(10.0, 20.0), 10.0 * 20.0);
+    let tokens = vec![
+        Token::new("1000"),
+    ];
+    assert!(tokens == tokenize_with("1,000", LexMode::Legacy).unwrap());
+}
 
-    assert_eq!(Operator::Div.evaluate(1.0 , 10.0), 1.0  / 10.0);
-    assert_eq!(Operator::Div.evaluate(15.0, 15.0), 15.0 / 15.0);
-    assert_eq!(Operator::Div.evaluate(10.0, 20.0), 10.0 / 20.0);
+#[test]
+fn test_tokenize_variables() {
+    let tokens = vec![
+        Token::new("2"),
+        Token::new("*"),
+        Token::new("total_cost"),
+        Token::new("-"),
+        Token::new("x1"),
+        Token::new("^"),
+        Token::new("u"),
+        Token::new("rate"),
+    ];
+    assert!(tokens == tokenize("2*total_cost - x1^-rate").unwrap());
+
+    // A number running into a name is two separate tokens
+    let tokens = vec![
+        Token::new("2"),
+        Token::new("x"),
+    ];
+    assert!(tokens == tokenize("2x").unwrap());
 
-    assert_eq!(Operator::Pow.evaluate(1.0 , 10.0), (1.0 as f64).powf(10.0));
-    assert_eq!(Operator::Pow.evaluate(15.0, 15.0), (15.0 as f64).powf(15.0));
-    assert_eq!(Operator::Pow.evaluate(10.0, 20.0), (10.0 as f64).powf(20.0));
+    // Variable names are identifiers, not operators
+    assert_eq!(tokenize("u").unwrap(), vec![Token::Variable("u".to_string())]);
+
+    assert!(is_identifier("_tmp2"));
+    assert!(!is_identifier("2tmp"));
+    assert!(!is_identifier("a-b"));
+}
+
+#[test]
+fn test_shunting_yard_functions() {
+    let call = |name: &str, arity: usize| Token::Function(name.to_string(), arity);
+
+    let tokens = tokenize("max(1, -2 * 3, sqrt(4)) + min(x)").unwrap();
+    assert_eq!(tokens[0], call("max", 0));
+    assert_eq!(tokens[2], Token::new("1"));
+    assert_eq!(tokens[3], Token::Comma);
+    assert_eq!(tokens[4], Token::new("u"));
+
+    let expected = vec![
+        Token::new("1"),
+        Token::new("2"),
+        Token::new("u"),
+        Token::new("3"),
+        Token::new("*"),
+        Token::new("4"),
+        call("sqrt", 1),
+        call("max", 3),
+        Token::new("x"),
+        call("min", 1),
+        Token::new("+"),
+    ];
+    let postfix = shunting_yard(tokens).unwrap();
+    assert_eq!(postfix, expected);
+    // The call covers the name through the closing paren
+    assert_eq!(postfix[6].span, Span::new(15, 22));
+
+    assert_eq!(shunting_yard(tokenize("max()").unwrap()).unwrap(), vec![call("max", 0)]);
+
+    assert_eq!(shunting_yard(tokenize("(1, 2)").unwrap()), Err(Error::MisplacedComma(Span::new(2, 3))));
+    assert_eq!(shunting_yard(tokenize("1, 2").unwrap()), Err(Error::MisplacedComma(Span::new(1, 2))));
+    assert_eq!(shunting_yard(tokenize("max((1, 2))").unwrap()), Err(Error::MisplacedComma(Span::new(6, 7))));
+    assert_eq!(shunting_yard(tokenize("max(1,)").unwrap()), Err(Error::EmptyArgument(Span::new(6, 7))));
+    assert_eq!(shunting_yard(tokenize("max(,1)").unwrap()), Err(Error::EmptyArgument(Span::new(4, 5))));
+    assert_eq!(shunting_yard(tokenize("max(1,,2)").unwrap()), Err(Error::EmptyArgument(Span::new(6, 7))));
+}
+
+#[test]
+fn test_tokenize_spans() {
+    let spans = tokenize("(10.5 + -3)")
+        .unwrap()
+        .into_iter()
+        .map(|t| t.span)
+        .collect::<Vec<Span>>();
+    let expected = vec![
+        Span::new(0, 1),
+        Span::new(1, 5),
+        Span::new(6, 7),
+        Span::new(8, 9),
+        Span::new(9, 10),
+        Span::new(10, 11),
+    ];
+    assert_eq!(spans, expected);
 
-    assert_eq!(Operator::USub.evaluate(0.0 , 10.0), -10.0);
-    assert_eq!(Operator::USub.evaluate(0.0, 15.0), -15.0);
-    assert_eq!(Operator::USub.evaluate(0.0, 10.0), -10.0);
+    let spans = tokenize("7//2!").unwrap().into_iter().map(|t| t.span).collect::<Vec<Span>>();
+    assert_eq!(spans, vec![Span::new(0, 1), Span::new(1, 3), Span::new(3, 4), Span::new(4, 5)]);
 
+    // Literals keep their digits, even the ones an f64 can't hold
+    let literals = tokenize("0.10 + 12345678901234567890.5").unwrap().into_iter().map(|t| t.literal).collect::<Vec<Option<String>>>();
+    assert_eq!(literals, vec![Some("0.10".to_string()), None, Some("12345678901234567890.5".to_string())]);
 }
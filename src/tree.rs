@@ -1,75 +1,348 @@
 
-use crate::token::{ Token, Operator, shunting_yard, tokenize };
+use crate::{
+    constant::Constants,
+    context::Context,
+    error::{ Error, Result },
+    number::Number,
+    token::{
+        Token, Operator, OperatorAssociativity, Span, Spanned, LexMode,
+        precedence, shunting_yard, tokenize_with,
+    },
+};
 
 use std::{
     fmt::{ Display, Debug, Formatter, Result as fmt_Result },
-    collections::HashMap,
+    collections::BTreeSet,
+    convert::TryFrom,
+    str::FromStr,
 };
 
 
+/// A single operator or value in an expression `Tree`
 #[derive(Clone)]
-struct Node {
-    token: Token,
-    left: Option<Box<Node>>,
-    right: Option<Box<Node>>,
+pub struct Node {
+    pub(crate) token: Token,
+    /// Covers this node and all of its children in the source
+    pub(crate) span: Span,
+    pub(crate) left: Option<Box<Node>>,
+    pub(crate) right: Option<Box<Node>>,
+    /// Arguments of a function call, empty for everything else
+    pub(crate) args: Vec<Node>,
+    /// How a value was written in the source, if it came from there (see `Number::from_literal`)
+    pub(crate) literal: Option<String>,
 } impl Node {
-    fn new(token: Token) -> Self {
+    pub(crate) fn new(token: Token, span: Span) -> Self {
         Node {
             token,
+            span,
             left: None,
             right: None,
+            args: Vec::new(),
+            literal: None,
+        }
+    }
+
+    /// `token` applied to `operands`, for building trees from formats that spell out the structure.
+    /// Operators have to get exactly as many operands as they take, functions take however many they're given
+    pub(crate) fn with_operands(token: Token, span: Span, mut operands: Vec<Node>) -> Result<Self> {
+        let expected = match token {
+            Token::Operator(op) if op.is_unary() => 1,
+            Token::Operator(_) => 2,
+            Token::Function(..) => operands.len(),
+            _ => 0,
+        };
+        if let Token::Operator(op) = token {
+            if operands.len() < expected {
+                return Err(Error::MissingOperand(op, span));
+            }
+        }
+        if let Some(extra) = operands.drain(expected..).map(|node| node.span).reduce(Span::join) {
+            return Err(Error::ExtraOperand(extra));
+        }
+
+        let mut node = Node::new(token, span);
+        match &mut node.token {
+            Token::Function(_, count) => {
+                *count = operands.len();
+                node.args = operands;
+            },
+            Token::Operator(_) => {
+                node.right = operands.pop().map(Box::new);
+                node.left = operands.pop().map(Box::new);
+            },
+            _ => {},
         }
+        Ok(node)
     }
 
-    fn evaluate(&self) -> f64 {
+    pub(crate) fn value(v: f64, span: Span) -> Self {
+        Node::new(Token::Value(v), span)
+    }
+
+    /// `left op right`, for building trees that didn't come from a source string
+    pub(crate) fn binary(op: Operator, left: Node, right: Node, span: Span) -> Self {
+        let mut node = Node::new(Token::Operator(op), span);
+        node.left = Some(Box::new(left));
+        node.right = Some(Box::new(right));
+        node
+    }
+
+    /// Unary minus of `operand`
+    pub(crate) fn negative(operand: Node, span: Span) -> Self {
+        let mut node = Node::new(Token::Operator(Operator::USub), span);
+        node.right = Some(Box::new(operand));
+        node
+    }
+
+    /// The value of a literal, negative literals (which parse as unary minus of a value) included
+    pub(crate) fn as_value(&self) -> Option<f64> {
         match self.token {
-            Token::Value(v) => v,
-            Token::Operator(op) => {
+            Token::Value(v) => Some(v),
+            Token::Operator(Operator::USub) => self.right().and_then(Node::as_value).map(|v| -v),
+            _ => None,
+        }
+    }
+
+    /// The operator, value or variable this node holds
+    pub fn token(&self) -> &Token {
+        &self.token
+    }
+
+    /// Where this node and its children came from in the source
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Left operand, `None` for values and unary operators
+    pub fn left(&self) -> Option<&Node> {
+        self.left.as_deref()
+    }
+
+    /// Right operand (the only operand of a unary operator), `None` for values
+    pub fn right(&self) -> Option<&Node> {
+        self.right.as_deref()
+    }
+
+    /// Arguments of a function call, empty for everything else
+    pub fn args(&self) -> &[Node] {
+        &self.args
+    }
+
+    /// Operands (or arguments) of this node, left to right
+    pub fn children(&self) -> impl Iterator<Item = &Node> {
+        self.left().into_iter().chain(self.right()).chain(self.args.iter())
+    }
+
+    /// Same as `children`, but mutable
+    pub(crate) fn children_mut(&mut self) -> impl Iterator<Item = &mut Node> {
+        self.left.iter_mut()
+            .chain(self.right.iter_mut())
+            .map(|node| node.as_mut())
+            .chain(self.args.iter_mut())
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children().next().is_none()
+    }
+
+    /// Evaluate this node, looking up variables in `ctx`
+    pub fn evaluate<C: Context + ?Sized>(&self, ctx: &C) -> Result<f64> {
+        self.evaluate_as(ctx)
+    }
+
+    /// Evaluate this node with `N` rather than `f64`, see `number::Number`
+    pub fn evaluate_as<N: Number, C: Context + ?Sized>(&self, ctx: &C) -> Result<N> {
+        self.evaluate_with_settings(ctx, &N::Settings::default())
+    }
+
+    /// Evaluate this node with `N`, making numbers with `settings`. Values read from text (by `Tree::parse`,
+    /// `parse_rpn`, `parse_sexpr`, ...) are made from exactly what was written, anything else (like values
+    /// from JSON or worked out by `fold_constants`, `simplify` or `derivative`) is made from its `f64`
+    pub fn evaluate_with_settings<N: Number, C: Context + ?Sized>(&self, ctx: &C, settings: &N::Settings) -> Result<N> {
+        // Numbers don't know where their operands came from, errors without a span get this node's
+        let locate = |e: Error| match e {
+            Error::Irrational(what, span) if span == Span::default() => Error::Irrational(what, self.span),
+            Error::Custom(message, span) if span == Span::default() => Error::Custom(message, self.span),
+            e => e,
+        };
+
+        match &self.token {
+            &Token::Value(v) => match &self.literal {
+                Some(literal) => N::from_literal(literal, settings).map_err(locate),
+                None => N::from_f64(v, settings).map_err(locate),
+            },
+            Token::Variable(name) => match (ctx.variable(name), ctx.constant(name)) {
+                (Some(value), _) => N::from_f64(value, settings).map_err(locate),
+                (None, Some(value)) => N::constant(name, value, settings).map_err(locate),
+                (None, None) => Err(Error::UnboundVariable(name.clone(), self.span)),
+            },
+            &Token::Operator(op) => {
+                let missing = || Error::MissingOperand(op, self.span);
+                let right = self.right.as_ref().ok_or_else(missing)?;
                 match op {
-                    Operator::USub => -self.right.as_ref().expect("Something went wrong! (evaluate unary minus without right child)").evaluate(),
+                    Operator::USub => Ok(right.evaluate_with_settings::<N, C>(ctx, settings)?.neg()),
+                    Operator::Factorial => right.evaluate_with_settings::<N, C>(ctx, settings)?.factorial(settings).map_err(locate),
                     _ => {
-                        op.evaluate(
-                            self.left.as_ref().expect("Something went wrong! (evaluate non unary operator node without left child").evaluate(),
-                            self.right.as_ref().expect("Something went wrong! (evaluate non unary operator node without right child").evaluate(),
-                        )
+                        op.evaluate_with_settings(
+                            self.left.as_ref().ok_or_else(missing)?.evaluate_with_settings(ctx, settings)?,
+                            right.evaluate_with_settings(ctx, settings)?,
+                            settings,
+                        ).map_err(|e| match e {
+                            // Point division by zero at the divisor
+                            Error::DivisionByZero(_) => Error::DivisionByZero(right.span),
+                            e => locate(e),
+                        })
                     }
                 }
             },
-            _ => unreachable!()
+            Token::Function(name, _) => {
+                let function = ctx.function(name).ok_or_else(|| Error::UnknownFunction(name.clone(), self.span))?;
+                let arity = function.arity();
+                if !arity.accepts(self.args.len()) {
+                    return Err(Error::ArityMismatch(name.clone(), arity, self.args.len(), self.span));
+                }
+
+                let args = self.args.iter()
+                            .map(|arg| arg.evaluate_with_settings(ctx, settings))
+                            .collect::<Result<Vec<N>>>()?;
+                // Point errors the function made up at the call
+                N::call(function, &args, settings).map_err(locate)
+            },
+            // Parens and commas never make it past shunting_yard
+            Token::Paren(_) => Err(Error::MismatchedParen(self.span)),
+            Token::Comma => Err(Error::MisplacedComma(self.span)),
+        }
+    }
+
+    fn fold_constants(&mut self, constants: &Constants) {
+        if let Token::Variable(name) = &self.token {
+            if let Some(value) = constants.get(name) {
+                self.token = Token::Value(value);
+            }
+        }
+
+        for child in self.children_mut() {
+            child.fold_constants(constants);
+        }
+    }
+
+    /// How tightly this node holds together when printed, see `Display`.
+    /// Anything that isn't an operator never needs parentheses
+    fn binding(&self) -> u32 {
+        match self.token {
+            Token::Operator(_) => precedence(&self.token),
+            // A negative literal prints with a minus sign, so it binds like one
+            Token::Value(v) if v.is_sign_negative() && !v.is_nan() => precedence(&Token::Operator(Operator::USub)),
+            _ => u32::MAX,
         }
     }
 
-    fn depth(&self) -> u16 {
-        let l = match self.left.as_ref() {
-            Some(node) => node.depth(),
-            None       => 0,
+    /// Whether the left and right operands need parentheses when this node is printed, `(false, false)`
+    /// for anything that isn't an operator
+    pub(crate) fn parenthesized(&self) -> (bool, bool) {
+        let (left, right) = match (&self.token, &self.left, &self.right) {
+            (Token::Operator(_), left, Some(right)) => (left, right),
+            _ => return (false, false),
         };
-        let r = match self.right.as_ref() {
-            Some(node) => node.depth(),
-            None       => 0,
+        let p = precedence(&self.token);
+        let left = match left {
+            Some(left) => left,
+            // Unary minus or factorial
+            None => return (false, right.binding() < p),
         };
 
-        l.max(r) + 1
+        // A child that binds just as tightly still needs parens on the side the operator doesn't associate towards
+        match OperatorAssociativity::from(&self.token) {
+            OperatorAssociativity::Left => (left.binding() < p, right.binding() <= p),
+            OperatorAssociativity::Right => (left.binding() <= p, right.binding() < p),
+        }
     }
-} impl From<Token> for Node {
-    fn from(token: Token) -> Self {
-        Node::new(token)
+
+    /// Number of nodes on the longest path from this node down to a leaf
+    pub fn depth(&self) -> u16 {
+        self.children()
+            .map(|node| node.depth())
+            .max()
+            .unwrap_or(0) + 1
+    }
+} impl From<Spanned> for Node {
+    fn from(spanned: Spanned) -> Self {
+        let mut node = Node::new(spanned.token, spanned.span);
+        node.literal = spanned.literal;
+        node
+    }
+
+} impl PartialEq for Node {
+    /// Structural equality, spans are ignored
+    fn eq(&self, other: &Node) -> bool {
+        self.token == other.token
+            && self.left == other.left
+            && self.right == other.right
+            && self.args == other.args
+    }
+
+} impl Display for Node {
+    /// Infix with only the parentheses needed to parse back into the same tree. Values that weren't parsed
+    /// (like those from `fold_constants` or `simplify`) are the exception, negative ones parse back as a
+    /// negation and infinities and NaN as the constants `inf` and `nan`, which evaluate the same
+    fn fmt(&self, f: &mut Formatter) -> fmt_Result {
+        match &self.token {
+            Token::Value(v) if v.is_nan() => write!(f, "nan"),
+            Token::Value(v) => write!(f, "{}", v),
+            Token::Variable(name) => write!(f, "{}", name),
+            Token::Function(name, _) => {
+                let args = self.args.iter()
+                            .map(|arg| arg.to_string())
+                            .collect::<Vec<String>>();
+                write!(f, "{}({})", name, args.join(", "))
+            },
+            &Token::Operator(op) => {
+                let (left_parens, right_parens) = self.parenthesized();
+                let wrap = |node: &Node, parens: bool| {
+                    if parens {
+                        format!("({})", node)
+                    } else {
+                        node.to_string()
+                    }
+                };
+                let right = self.right.as_ref().expect("Something went wrong! (format operator node with no right child)");
+
+                match op {
+                    Operator::USub => return write!(f, "-{}", wrap(right, right_parens)),
+                    Operator::Factorial => return write!(f, "{}!", wrap(right, right_parens)),
+                    _ => {},
+                }
+
+                let left = self.left.as_ref().expect("Something went wrong! (format operator node with no left child)");
+                write!(f, "{} {} {}", wrap(left, left_parens), op, wrap(right, right_parens))
+            },
+            Token::Paren(_) | Token::Comma => unreachable!(),
+        }
     }
 
 } impl Debug for Node {
+    /// Fully parenthesized infix
     fn fmt(&self, f: &mut Formatter) -> fmt_Result {
-        match self.token {
+        match &self.token {
             Token::Value(v) => write!(f, "{}", v),
+            Token::Variable(name) => write!(f, "{}", name),
+            Token::Function(name, _) => {
+                let args = self.args.iter()
+                            .map(|arg| format!("{:?}", arg))
+                            .collect::<Vec<String>>();
+                write!(f, "{}({})", name, args.join(", "))
+            },
             Token::Operator(op) => {
                 match op {
                     Operator::USub => write!(f, "u{:?}", self.right.as_ref().expect("Something went wrong! (format unary minus without right child)")),
+                    Operator::Factorial => write!(f, "{:?}!", self.right.as_ref().expect("Something went wrong! (format factorial without right child)")),
                     _ => {
                         write!(
                             f, 
                             "({:?} {} {:?})", 
 
                             self.left.as_ref().expect("Something went wrong! (format operator node with no left child)"),
-                            op.to_string(),
+                            op,
                             self.right.as_ref().expect("Something went wrong! (format operator node with no right child)"),
                         )
                     },
@@ -80,65 +353,210 @@ struct Node {
     }
 }
 
+/// Visits a node before its children, see `Tree::pre_order`
+pub struct PreOrder<'a> {
+    stack: Vec<&'a Node>,
+} impl<'a> Iterator for PreOrder<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        let children = node.children().collect::<Vec<&Node>>();
+        self.stack.extend(children.into_iter().rev());
+        Some(node)
+    }
+}
+
+/// Visits a node after its children, see `Tree::post_order`
+pub struct PostOrder<'a> {
+    /// Nodes along with whether their children have already been pushed
+    stack: Vec<(&'a Node, bool)>,
+} impl<'a> Iterator for PostOrder<'a> {
+    type Item = &'a Node;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((node, expanded)) = self.stack.pop() {
+            if expanded || node.is_leaf() {
+                return Some(node);
+            }
+            self.stack.push((node, true));
+            let children = node.children().collect::<Vec<&Node>>();
+            self.stack.extend(children.into_iter().rev().map(|n| (n, false)));
+        }
+        None
+    }
+}
+
+/// A parsed expression, ready to be evaluated
+/// ```rust
+/// use pemdrs::Tree;
+///
+/// let tree = Tree::parse("(54/9)^2-4*7+7")?;
+/// assert_eq!(tree.evaluate()?, 15.0);
+/// assert_eq!(tree.depth(), 5);
+/// # Ok::<(), pemdrs::Error>(())
+/// ```
 #[derive(Clone)]
-struct Tree {
-    root: Node,
+pub struct Tree {
+    pub(crate) root: Node,
 } impl Tree {
-    fn new(s: &str) -> Self {
-        shunting_yard(tokenize(s)).into()
+    /// Parse an infix expression
+    pub fn parse(s: &str) -> Result<Self> {
+        Tree::parse_with(s, LexMode::Strict)
+    }
+
+    /// Parse an infix expression, lexing it with `mode`
+    pub fn parse_with(s: &str, mode: LexMode) -> Result<Self> {
+        Tree::try_from(shunting_yard(tokenize_with(s, mode)?)?)
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    /// Evaluate an expression that doesn't use any variables
+    pub fn evaluate(&self) -> Result<f64> {
+        self.root.evaluate(&())
+    }
+
+    /// Evaluate the expression, looking up variables in `ctx`
+    pub fn evaluate_with<C: Context + ?Sized>(&self, ctx: &C) -> Result<f64> {
+        self.root.evaluate(ctx)
+    }
+
+    /// Evaluate the expression with `N` rather than `f64`, see `number::Number`
+    pub fn evaluate_as<N: Number>(&self) -> Result<N> {
+        self.root.evaluate_as(&())
+    }
+
+    /// Evaluate the expression with `N` rather than `f64`, looking up variables in `ctx`
+    pub fn evaluate_as_with<N: Number, C: Context + ?Sized>(&self, ctx: &C) -> Result<N> {
+        self.root.evaluate_as(ctx)
+    }
+
+    /// Evaluate the expression with `N`, looking up variables in `ctx` and making numbers with `settings`,
+    /// see `Node::evaluate_with_settings` for where values come from
+    pub fn evaluate_with_settings<N: Number, C: Context + ?Sized>(&self, ctx: &C, settings: &N::Settings) -> Result<N> {
+        self.root.evaluate_with_settings(ctx, settings)
+    }
+
+    /// Replace every variable named after one of `constants` with its value, so it no longer
+    /// needs to be looked up at evaluation time
+    pub fn fold_constants(&mut self, constants: &Constants) {
+        self.root.fold_constants(constants);
+    }
+
+    /// Names of every variable the expression refers to, each listed once.
+    /// Includes names that will resolve to constants unless they've been folded
+    pub fn variables(&self) -> BTreeSet<&str> {
+        self.pre_order()
+            .filter_map(|node| match &node.token {
+                Token::Variable(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn depth(&self) -> u16 {
+        self.root.depth()
     }
 
-    fn evaluate(&self) -> f64 {
-        self.root.evaluate()
+    /// Iterate over every node, parents before children
+    pub fn pre_order(&self) -> PreOrder<'_> {
+        PreOrder { stack: vec![&self.root] }
     }
-} impl From<Vec<Token>> for Tree {
-    fn from(stream: Vec<Token>) -> Self {
+
+    /// Iterate over every node, children before parents (i.e. in postfix order)
+    pub fn post_order(&self) -> PostOrder<'_> {
+        PostOrder { stack: vec![(&self.root, false)] }
+    }
+
+    /// The tree as a postfix token stream, the inverse of `Tree::try_from`
+    pub fn to_postfix(&self) -> Vec<Spanned> {
+        self.post_order()
+            .map(|node| Spanned { token: node.token.clone(), span: node.span, literal: node.literal.clone() })
+            .collect()
+    }
+} impl FromStr for Tree {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Tree> {
+        Tree::parse(s)
+    }
+} impl TryFrom<Vec<Spanned>> for Tree {
+    type Error = Error;
+
+    /// Build a tree from a postfix token stream, as produced by `shunting_yard`
+    fn try_from(stream: Vec<Spanned>) -> Result<Self> {
         let mut stack: Vec<Node> = Vec::new();
 
-        for token in stream {
-            match token {
-                Token::Value(_) => stack.push(token.into()),
+        for spanned in stream {
+            match spanned.token {
+                Token::Value(_) | Token::Variable(_) => stack.push(spanned.into()),
                 Token::Operator(op) => {
+                    let span = spanned.span;
+                    let missing = || Error::MissingOperand(op, span);
                     match op {
-                        Operator::USub => {
-                            let mut node: Node = token.clone().into();
-                            let value = stack.pop().expect("Unable to pop from empty stack");
+                        _ if op.is_unary() => {
+                            let mut node: Node = spanned.into();
+                            let value = stack.pop().ok_or_else(missing)?;
+                            node.span = node.span.join(value.span);
                             node.right = Some(Box::new(value));
                             stack.push(node);
                         },
                         _ => {
-                            let mut node: Node = token.clone().into();
-                            let a: Node = stack.pop().expect("Stack shouldn't be empty? :(");
-                            let b: Node = stack.pop().expect("Stack shouldn't be empty? :(");
+                            let mut node: Node = spanned.into();
+                            let a: Node = stack.pop().ok_or_else(missing)?;
+                            let b: Node = stack.pop().ok_or_else(missing)?;
+                            node.span = node.span.join(a.span).join(b.span);
                             node.right = Some(Box::new(a));
                             node.left = Some(Box::new(b));
                             stack.push(node);
                         }
                     }
                 },
-                _ => unreachable!()
+                Token::Function(ref name, arity) => {
+                    if stack.len() < arity {
+                        return Err(Error::MissingArgument(name.clone(), spanned.span));
+                    }
+                    let args = stack.split_off(stack.len() - arity);
+                    let mut node: Node = spanned.into();
+                    node.span = args.iter().fold(node.span, |span, arg| span.join(arg.span));
+                    node.args = args;
+                    stack.push(node);
+                },
+                Token::Paren(_) => return Err(Error::MismatchedParen(spanned.span)),
+                Token::Comma => return Err(Error::MisplacedComma(spanned.span)),
             }
         }
 
-        Tree {
-            root: stack.pop().expect("Empty string? maybe? (stack empty)")
+        let root = stack.pop().ok_or(Error::EmptyExpression)?;
+        if let Some(extra) = stack.into_iter().map(|node| node.span).reduce(Span::join) {
+            return Err(Error::ExtraOperand(extra));
         }
+
+        Ok(Tree { root })
     }
 } impl Debug for Tree {
     fn fmt(&self, f: &mut Formatter) -> fmt_Result {
         write!(f, "{:?}", self.root)
     }
 } impl Display for Tree {
+    /// Infix with only the parentheses needed to parse back into the same tree, see `Node`'s `Display`
     fn fmt(&self, f: &mut Formatter) -> fmt_Result {
-        write!(f, "")
+        write!(f, "{}", self.root)
+    }
+} impl PartialEq for Tree {
+    /// Structural equality, spans are ignored
+    fn eq(&self, other: &Tree) -> bool {
+        self.root == other.root
     }
-} 
+}
 
 
 #[test]
 fn test_tree_evaluate() {
-
-    std::thread::sleep_ms(100);
+    use std::collections::HashMap;
 
     let mut problems: HashMap<&str, f64> = HashMap::new();
 
@@ -164,14 +582,22 @@ fn test_tree_evaluate() {
     problems.insert("((4^2+-6)*4)-3+6",      43.0);
     problems.insert("(4-(-2^2-4))*(-2-8)",  -40.0);
     problems.insert("((-78/-13)^3-8)*-4+4", -828.0);
+
+    // integer division, modulo and factorial
+    problems.insert("7//2*2+7%2",   7.0);
+    problems.insert("-7//2",       -4.0);
+    problems.insert("-7%3",         2.0);
+    problems.insert("-3!",         -6.0);
+    problems.insert("2^3!-4!/3",   56.0);
+    problems.insert("(1+2)!!",    720.0);
     
 
     
     for (problem, answer) in problems.iter() {
 
         // eprintln!("Evaluating {}; expectms: u32ed: {}", problem, answer);
-        let tree = Tree::new(problem);
-        assert_eq!(&tree.evaluate(), answer);
+        let tree = Tree::parse(problem).unwrap();
+        assert_eq!(&tree.evaluate().unwrap(), answer);
 
         /* // DEBUG
         eprintln!(
@@ -186,4 +612,223 @@ fn test_tree_evaluate() {
         )
         */
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_tree_errors() {
+    assert_eq!(Tree::parse("").unwrap_err(), Error::EmptyExpression);
+    assert_eq!(Tree::parse("()").unwrap_err(), Error::EmptyExpression);
+    assert_eq!(Tree::parse("2*").unwrap_err(), Error::MissingOperand(Operator::Mul, Span::new(1, 2)));
+    assert_eq!(Tree::parse("*2").unwrap_err(), Error::MissingOperand(Operator::Mul, Span::new(0, 1)));
+    assert_eq!(Tree::parse("(2+3").unwrap_err(), Error::MismatchedParen(Span::new(0, 1)));
+    assert_eq!(Tree::parse("2+3)").unwrap_err(), Error::MismatchedParen(Span::new(3, 4)));
+    assert_eq!(Tree::parse("(2)(3)").unwrap_err(), Error::ExtraOperand(Span::new(1, 2)));
+    assert_eq!(Tree::parse("1.2.3+4").unwrap_err(), Error::Lex("1.2.3".to_string(), Span::new(0, 5)));
+
+    assert_eq!(Tree::parse("4/(2-2)").unwrap().evaluate().unwrap_err(), Error::DivisionByZero(Span::new(3, 6)));
+    assert_eq!(Tree::parse("5 % (1-1)").unwrap().evaluate().unwrap_err(), Error::DivisionByZero(Span::new(5, 8)));
+    assert_eq!(Tree::parse("!3").unwrap_err(), Error::MissingOperand(Operator::Factorial, Span::new(0, 1)));
+    assert_eq!(
+        Tree::parse("3 + 2.5!").unwrap().evaluate().unwrap_err(),
+        Error::Custom("2.5! isn't defined, only whole numbers that aren't negative have factorials".to_string(), Span::new(4, 8)),
+    );
+}
+
+#[test]
+fn test_tree_spans() {
+    let tree = Tree::parse("1 + 2 * -34").unwrap();
+    assert_eq!(tree.root.span, Span::new(0, 11));
+    assert_eq!(tree.root.left.as_ref().unwrap().span, Span::new(0, 1));
+    assert_eq!(tree.root.right.as_ref().unwrap().span, Span::new(4, 11));
+    assert_eq!(tree.root.right.as_ref().unwrap().right.as_ref().unwrap().span, Span::new(8, 11));
+}
+
+#[test]
+fn test_tree_traversal() {
+    let tree = Tree::parse("1 + 2 * -3").unwrap();
+
+    let pre = tree.pre_order().map(|n| n.token().clone()).collect::<Vec<Token>>();
+    let expected = vec![
+        Token::new("+"),
+        Token::new("1"),
+        Token::new("*"),
+        Token::new("2"),
+        Token::new("u"),
+        Token::new("3"),
+    ];
+    assert_eq!(pre, expected);
+
+    let post = tree.post_order().map(|n| n.token().clone()).collect::<Vec<Token>>();
+    let expected = vec![
+        Token::new("1"),
+        Token::new("2"),
+        Token::new("3"),
+        Token::new("u"),
+        Token::new("*"),
+        Token::new("+"),
+    ];
+    assert_eq!(post, expected);
+
+    // Round trip through postfix
+    let rebuilt = Tree::try_from(tree.to_postfix()).unwrap();
+    assert_eq!(rebuilt, tree);
+    assert_eq!(format!("{:?}", tree), "(1 + (2 * u3))");
+
+    assert_eq!(tree.root().children().count(), 2);
+    assert!(tree.root().left().unwrap().is_leaf());
+}
+
+#[test]
+fn test_tree_variables() {
+    use std::collections::HashMap;
+
+    let tree = Tree::parse("2 * total_cost * (1 + rate) - rate").unwrap();
+    assert_eq!(tree.variables().into_iter().collect::<Vec<&str>>(), vec!["rate", "total_cost"]);
+
+    let mut env: HashMap<String, f64> = HashMap::new();
+    env.insert("total_cost".to_string(), 10.0);
+    assert_eq!(tree.evaluate_with(&env), Err(Error::UnboundVariable("rate".to_string(), Span::new(22, 26))));
+
+    env.insert("rate".to_string(), 0.5);
+    assert_eq!(tree.evaluate_with(&env), Ok(29.5));
+
+    assert_eq!(Tree::parse("x").unwrap().evaluate(), Err(Error::UnboundVariable("x".to_string(), Span::new(0, 1))));
+    assert!(Tree::parse("1 + 2").unwrap().variables().is_empty());
+}
+
+#[test]
+fn test_tree_functions() {
+    use crate::function::Arity;
+
+    let tree = Tree::parse("max(1, sqrt(16) * 2, -3) + min(2, x)").unwrap();
+    assert_eq!(format!("{:?}", tree), "(max(1, (sqrt(16) * 2), u3) + min(2, x))");
+    assert_eq!(tree.depth(), 5);
+    assert_eq!(tree.variables().into_iter().collect::<Vec<&str>>(), vec!["x"]);
+
+    let mut env = std::collections::HashMap::new();
+    env.insert("x", -1.0);
+    assert_eq!(tree.evaluate_with(&env), Ok(7.0));
+
+    // Postfix round trip keeps the arguments in order
+    let tree = Tree::parse("atan2(1, 2) - hypot(3, 4)").unwrap();
+    let rebuilt = Tree::try_from(tree.to_postfix()).unwrap();
+    assert_eq!(format!("{:?}", rebuilt), "(atan2(1, 2) - hypot(3, 4))");
+    assert_eq!(rebuilt.evaluate(), Ok(1.0_f64.atan2(2.0) - 5.0));
+
+    assert_eq!(
+        Tree::parse("1 + max()").unwrap().evaluate(),
+        Err(Error::ArityMismatch("max".to_string(), Arity::Variadic(1), 0, Span::new(4, 9))),
+    );
+    assert_eq!(
+        Tree::parse("sqrt(4, 9)").unwrap().evaluate(),
+        Err(Error::ArityMismatch("sqrt".to_string(), Arity::Fixed(1), 2, Span::new(0, 10))),
+    );
+    assert_eq!(
+        Tree::parse("nope(1)").unwrap().evaluate(),
+        Err(Error::UnknownFunction("nope".to_string(), Span::new(0, 7))),
+    );
+    assert_eq!(Tree::parse("1,000").unwrap_err(), Error::MisplacedComma(Span::new(1, 2)));
+
+    let stream = vec![
+        Spanned::new(Token::new("1"), Span::default()),
+        Spanned::new(Token::Function("max".to_string(), 2), Span::default()),
+    ];
+    assert_eq!(Tree::try_from(stream).unwrap_err(), Error::MissingArgument("max".to_string(), Span::default()));
+}
+
+#[test]
+fn test_tree_fold_constants() {
+    let mut constants = Constants::new();
+    constants.define("g", 9.8);
+
+    let mut tree = Tree::parse("max(g, pi) * t^2 / 2 + e").unwrap();
+    assert_eq!(tree.variables().into_iter().collect::<Vec<&str>>(), vec!["e", "g", "pi", "t"]);
+
+    tree.fold_constants(&constants);
+    assert_eq!(tree.variables().into_iter().collect::<Vec<&str>>(), vec!["t"]);
+    assert_eq!(format!("{:?}", tree), format!("(((max(9.8, {}) * (t ^ 2)) / 2) + {})", std::f64::consts::PI, std::f64::consts::E));
+}
+
+#[test]
+fn test_tree_display() {
+    let cases = vec![
+        ("1+2*3", "1 + 2 * 3"),
+        ("(1+2)*3", "(1 + 2) * 3"),
+        ("1-(2-3)", "1 - (2 - 3)"),
+        ("(1-2)-3", "1 - 2 - 3"),
+        ("1+(2+3)", "1 + (2 + 3)"),
+        ("2/(3*4)", "2 / (3 * 4)"),
+        ("2^3^4", "2 ^ 3 ^ 4"),
+        ("(2^3)^4", "(2 ^ 3) ^ 4"),
+        ("-2^2", "-2 ^ 2"),
+        ("-(2^2)", "-(2 ^ 2)"),
+        ("-(x+1)", "-(x + 1)"),
+        ("--x", "--x"),
+        ("2^-x", "2 ^ -x"),
+        ("3 - -x", "3 - -x"),
+        ("((max((1), (2+3)*x)))", "max(1, (2 + 3) * x)"),
+        ("(54/9)^2-4*7+7", "(54 / 9) ^ 2 - 4 * 7 + 7"),
+        ("7//(2%3)", "7 // (2 % 3)"),
+        ("(-3)!", "(-3)!"),
+        ("-(3!)", "-3!"),
+        ("(2^3)!+x!!", "(2 ^ 3)! + x!!"),
+    ];
+
+    for (input, expected) in cases {
+        let tree = Tree::parse(input).unwrap();
+        assert_eq!(tree.to_string(), expected, "displaying {}", input);
+        assert_eq!(Tree::parse(&tree.to_string()).unwrap(), tree, "round tripping {}", input);
+    }
+}
+
+#[test]
+fn test_tree_display_round_trip() {
+    // Every way of nesting two operators (or one operator and a unary minus) around three operands,
+    // built from the fully parenthesized form so the shape is known ahead of time
+    let binary = ["+", "-", "*", "/", "//", "%", "^"];
+    let mut shapes = Vec::new();
+    for a in binary.iter() {
+        for b in binary.iter() {
+            shapes.push(format!("((x {} y) {} z)", a, b));
+            shapes.push(format!("(x {} (y {} z))", a, b));
+            shapes.push(format!("(-(x {} y) {} z)", a, b));
+            shapes.push(format!("(x {} -(y {} z))", a, b));
+            shapes.push(format!("((-x {} y) {} -z)", a, b));
+        }
+        shapes.push(format!("-(x {} y)", a));
+        shapes.push(format!("(x! {} y)! {} -z!", a, a));
+        shapes.push(format!("f(-x {} y, (x {} y) {} 1)", a, a, a));
+    }
+
+    for shape in shapes {
+        let tree = Tree::parse(&shape).unwrap();
+        let displayed = tree.to_string();
+        assert_eq!(Tree::parse(&displayed).unwrap(), tree, "{} displayed as {}", shape, displayed);
+        assert_eq!(format!("{:?}", Tree::parse(&displayed).unwrap()), format!("{:?}", tree));
+    }
+
+    // Folded values parse back as negations and constants, which print and evaluate the same
+    let mut env = std::collections::HashMap::new();
+    env.insert("x", 3.0);
+    for input in ["-inf * x + nan", "x * (0 - 2)", "x ^ (1 - 4) - inf"].iter() {
+        let mut tree = Tree::parse(input).unwrap();
+        tree.fold_constants(&Constants::new());
+        let tree = tree.simplify();
+        let reparsed = Tree::parse(&tree.to_string()).unwrap();
+        assert_eq!(reparsed.to_string(), tree.to_string(), "{}", input);
+        assert_eq!(format!("{:?}", reparsed.evaluate_with(&env)), format!("{:?}", tree.evaluate_with(&env)), "{}", input);
+    }
+
+    // And every worksheet problem
+    let problems = [
+        "-2--4--8+-2+-11", "-2*-11+-3*-7+-4", "48/4+7-6+6", "-12--10+-4+-6*-4", "5+-12-42/7*-10",
+        "(-2^3+-3)*-2--4+-3", "(54/9)^2-4*7+7", "(-3^2+-4)*-3--9+-4", "4-(8/4)^3*9+9", "6-(10/5)^2*-5+-5",
+        "(5-(9/3)^2)*6+6", "(10+(16/8))*3^3-8", "((4^2+-6)*4)-3+6", "(4-(-2^2-4))*(-2-8)", "((-78/-13)^3-8)*-4+4",
+    ];
+    for problem in problems.iter() {
+        let tree = Tree::parse(problem).unwrap();
+        let reparsed = Tree::parse(&tree.to_string()).unwrap();
+        assert_eq!(reparsed, tree, "{} displayed as {}", problem, tree);
+        assert_eq!(reparsed.evaluate(), tree.evaluate());
+    }
+}